@@ -0,0 +1,536 @@
+use std::cmp::{self, Ordering};
+use std::collections::{HashSet, VecDeque};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::metadata::{format_allocated, format_permissions, format_time, name_key, path_key, FileMetadata, FileType};
+
+#[derive(Debug)]
+pub struct AVLTreeNode {
+    // Shared rather than owned, so cloning a tree (as every command does with `--watch`) copies the
+    // nodes but not the names and paths in them. Changing a file in place copies it first if
+    // another tree still shares it.
+    pub file: Option<Arc<FileMetadata>>,
+    pub left: Option<Box<AVLTreeNode>>,
+    pub right: Option<Box<AVLTreeNode>>,
+    pub parent: Option<Box<AVLTreeNode>>,
+    pub height: i32,
+    // Number of nodes in the subtree rooted here, used for order-statistic queries.
+    pub size: usize,
+}
+
+impl AVLTreeNode {
+    pub fn new(file: FileMetadata) -> Self {
+        AVLTreeNode {
+            file: Some(Arc::new(file)),
+            left: None,
+            right: None,
+            parent: None,
+            height: 1,
+            size: 1,
+        }
+    }
+}
+
+impl Clone for AVLTreeNode {
+    fn clone(&self) -> Self {
+        AVLTreeNode {
+            file: self.file.clone(),
+            left: self.left.clone(),
+            right: self.right.clone(),
+            parent: self.parent.clone(),
+            height: self.height,
+            size: self.size,
+        }
+    }
+}
+
+// What a tree is ordered by. The scan builds name-ordered trees, which everything taking a tree
+// assumes unless it says otherwise; the other keys are for queries over that field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderKey {
+    Name,
+    Path,
+    Size,
+}
+
+// Trees are ordered by name, then by path among equal names, so every file has one place in the
+// tree and files sharing a name sit next to each other in path order.
+fn compare_entries(name: &str, path: &Path, other: &FileMetadata) -> Ordering {
+    name_key(name).cmp(&name_key(&other.name)).then_with(|| path_key(path).cmp(&path_key(&other.path)))
+}
+
+// Where `a` sorts against `b` in a tree ordered by `key`; inserts and lookups both go through it.
+// The other keys break ties by path too, for the same reason, so only two entries for the same
+// path compare `Equal`. Inserting one that compares `Equal` to a node puts it to the node's right.
+pub fn compare_metadata(a: &FileMetadata, b: &FileMetadata, key: OrderKey) -> Ordering {
+    match key {
+        OrderKey::Name => compare_entries(&a.name, &a.path, b),
+        OrderKey::Path => path_key(&a.path).cmp(&path_key(&b.path)),
+        OrderKey::Size => a.size.cmp(&b.size).then_with(|| path_key(&a.path).cmp(&path_key(&b.path))),
+    }
+}
+
+// Inserts into a name-ordered tree.
+pub fn insert_into_avl_tree(root: Option<Box<AVLTreeNode>>, file: FileMetadata) -> Box<AVLTreeNode> {
+    insert_into_avl_tree_by(root, file, OrderKey::Name)
+}
+
+// Takes ownership of the subtree and hands it back rebalanced, so no nodes are cloned on the way down.
+// A tree must only ever be given one `key`.
+pub fn insert_into_avl_tree_by(root: Option<Box<AVLTreeNode>>, file: FileMetadata, key: OrderKey) -> Box<AVLTreeNode> {
+    match root {
+        Some(mut node) => {
+            if compare_metadata(&file, node.file.as_ref().unwrap(), key) == Ordering::Less {
+                node.left = Some(insert_into_avl_tree_by(node.left.take(), file, key));
+            } else {
+                node.right = Some(insert_into_avl_tree_by(node.right.take(), file, key));
+            }
+            balance_avl_tree(node)
+        }
+        None => Box::new(AVLTreeNode::new(file)),
+    }
+}
+
+// Removes the node holding `path` from a name-ordered tree, returning the new root and the removed file.
+pub fn remove_from_avl_tree(root: Option<Box<AVLTreeNode>>, name: &str, path: &Path) -> (Option<Box<AVLTreeNode>>, Option<FileMetadata>) {
+    let mut target = FileMetadata::new(path.to_path_buf(), 0, FileType::File, None);
+    target.name = name.to_string();
+    remove_from_avl_tree_by(root, &target, OrderKey::Name)
+}
+
+// Removes the node comparing equal to `target` under `key`, which has to be the key the tree was
+// built with. For `OrderKey::Size` that means `target` carries the size the file was indexed at.
+pub fn remove_from_avl_tree_by(root: Option<Box<AVLTreeNode>>, target: &FileMetadata, key: OrderKey) -> (Option<Box<AVLTreeNode>>, Option<FileMetadata>) {
+    let mut node = match root {
+        Some(node) => node,
+        None => return (None, None),
+    };
+    let ordering = match &node.file {
+        Some(file) => compare_metadata(target, file, key),
+        None => return (Some(node), None),
+    };
+
+    let removed = match ordering {
+        Ordering::Less => {
+            let (left, removed) = remove_from_avl_tree_by(node.left.take(), target, key);
+            node.left = left;
+            removed
+        }
+        Ordering::Greater => {
+            let (right, removed) = remove_from_avl_tree_by(node.right.take(), target, key);
+            node.right = right;
+            removed
+        }
+        Ordering::Equal => {
+            let removed = node.file.take().map(Arc::unwrap_or_clone);
+            match (node.left.take(), node.right.take()) {
+                (None, None) => return (None, removed),
+                (Some(child), None) | (None, Some(child)) => return (Some(child), removed),
+                (Some(left), Some(right)) => {
+                    let (right, min) = remove_min(right);
+                    node.file = Some(min);
+                    node.left = Some(left);
+                    node.right = right;
+                }
+            }
+            removed
+        }
+    };
+
+    if removed.is_none() {
+        return (Some(node), None);
+    }
+    (Some(balance_avl_tree(node)), removed)
+}
+
+// Removes every file called `name` from a name-ordered tree, whatever directory it's in, and
+// returns the new root with how many were removed. Names compare as `name_key` has them. Each
+// removal rebalances like `remove_from_avl_tree`.
+pub fn delete_by_name(root: Option<Box<AVLTreeNode>>, name: &str) -> (Option<Box<AVLTreeNode>>, usize) {
+    let key = name_key(name);
+    let matches = search_range(&root, &mut |file| name_key(&file.name).cmp(&key));
+    let mut root = root;
+    let mut removed = 0;
+    for file in matches {
+        let (rest, file) = remove_from_avl_tree(root, &file.name, &file.path);
+        root = rest;
+        removed += usize::from(file.is_some());
+    }
+    (root, removed)
+}
+
+fn remove_min(mut node: Box<AVLTreeNode>) -> (Option<Box<AVLTreeNode>>, Arc<FileMetadata>) {
+    match node.left.take() {
+        None => (node.right.take(), node.file.take().unwrap()),
+        Some(left) => {
+            let (left, min) = remove_min(left);
+            node.left = left;
+            (Some(balance_avl_tree(node)), min)
+        }
+    }
+}
+
+fn balance_avl_tree(mut node: Box<AVLTreeNode>) -> Box<AVLTreeNode> {
+    update_height(&mut node);
+    update_size(&mut node);
+    let balance = get_height(&node.left) - get_height(&node.right);
+    if balance.abs() <= 1 {
+        return node;
+    }
+    if balance > 1 {
+        if get_height(&node.left.as_ref().unwrap().left) >= get_height(&node.left.as_ref().unwrap().right) {
+            node = rotate_right(node);
+        } else {
+            node.left = Some(rotate_left(node.left.unwrap()));
+            node = rotate_right(node);
+        }
+    } else {
+        if get_height(&node.right.as_ref().unwrap().right) >= get_height(&node.right.as_ref().unwrap().left) {
+            node = rotate_left(node);
+        } else {
+            node.right = Some(rotate_right(node.right.unwrap()));
+            node = rotate_left(node);
+        }
+    }
+    debug_assert!(!loops_below(&node, node.size), "rotation left a cycle in the tree");
+    node
+}
+
+// Whether walking the tree from `root` reaches some node twice or visits more than `max_nodes`
+// nodes (pass the cached size, or the number of files expected). Either means the links loop, as
+// a bad rotation could make them once nodes are shared rather than boxed; with `Box` the first
+// can't happen, and the bound stops the walk instead of letting it hang. `parent` isn't followed.
+pub fn detect_cycle(root: &Option<Box<AVLTreeNode>>, max_nodes: usize) -> bool {
+    root.as_deref().is_some_and(|node| loops_below(node, max_nodes))
+}
+
+fn loops_below(root: &AVLTreeNode, max_nodes: usize) -> bool {
+    let mut seen: HashSet<*const AVLTreeNode> = HashSet::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if !seen.insert(node) || seen.len() > max_nodes {
+            return true;
+        }
+        stack.extend(node.left.as_deref());
+        stack.extend(node.right.as_deref());
+    }
+    false
+}
+
+fn rotate_left(mut node: Box<AVLTreeNode>) -> Box<AVLTreeNode> {
+    let mut right = node.right.unwrap();
+    node.right = right.left.take();
+    update_height(&mut node);
+    update_size(&mut node);
+    right.left = Some(node);
+    update_height(&mut right);
+    update_size(&mut right);
+    right
+}
+
+fn rotate_right(mut node: Box<AVLTreeNode>) -> Box<AVLTreeNode> {
+    let mut left = node.left.unwrap();
+    node.left = left.right.take();
+    update_height(&mut node);
+    update_size(&mut node);
+    left.right = Some(node);
+    update_height(&mut left);
+    update_size(&mut left);
+    left
+}
+
+fn update_height(node: &mut Box<AVLTreeNode>) {
+    node.height = 1 + cmp::max(get_height(&node.left), get_height(&node.right));
+}
+
+fn get_height(node: &Option<Box<AVLTreeNode>>) -> i32 {
+    match node {
+        Some(node) => node.height,
+        None => 0,
+    }
+}
+
+fn update_size(node: &mut Box<AVLTreeNode>) {
+    node.size = 1 + subtree_size(&node.left) + subtree_size(&node.right);
+}
+
+pub fn subtree_size(node: &Option<Box<AVLTreeNode>>) -> usize {
+    match node {
+        Some(node) => node.size,
+        None => 0,
+    }
+}
+
+// Height of the tree, read from the root's stored height; 0 for an empty tree.
+pub fn tree_height(root: &Option<Box<AVLTreeNode>>) -> i32 {
+    get_height(root)
+}
+
+// The tallest an AVL tree of `n` nodes can be: 1.44 * log2(n) for large trees, with the constant
+// terms that keep it exact for small ones.
+pub fn height_bound(n: usize) -> f64 {
+    1.4405 * ((n + 2) as f64).log2() - 0.3277
+}
+
+// Number of files in the tree. Reads the cached subtree size, so it doesn't walk the tree.
+pub fn count(root: &Option<Box<AVLTreeNode>>) -> usize {
+    subtree_size(root)
+}
+
+// Returns the kth file in name order, counting from 1.
+pub fn select_kth(root: &Option<Box<AVLTreeNode>>, k: usize) -> Option<FileMetadata> {
+    let node = root.as_ref()?;
+    let left = subtree_size(&node.left);
+    if k <= left {
+        select_kth(&node.left, k)
+    } else if k == left + 1 {
+        node.file.as_deref().cloned()
+    } else {
+        select_kth(&node.right, k - left - 1)
+    }
+}
+
+// Nearest file whose name sorts strictly before `name` in a name-ordered tree; `name` itself
+// doesn't have to be in the tree.
+pub fn predecessor(root: &Option<Box<AVLTreeNode>>, name: &str) -> Option<FileMetadata> {
+    let name = name_key(name);
+    nearest_before(root, &|file| name_key(&file.name) < name)
+}
+
+// Nearest file whose name sorts strictly after `name` in a name-ordered tree; `name` itself
+// doesn't have to be in the tree.
+pub fn successor(root: &Option<Box<AVLTreeNode>>, name: &str) -> Option<FileMetadata> {
+    let name = name_key(name);
+    nearest_after(root, &|file| name_key(&file.name) > name)
+}
+
+// Nearest file sorting strictly before `target` in a tree built with `key`.
+pub fn predecessor_by(root: &Option<Box<AVLTreeNode>>, target: &FileMetadata, key: OrderKey) -> Option<FileMetadata> {
+    nearest_before(root, &|file| compare_metadata(file, target, key) == Ordering::Less)
+}
+
+// Nearest file sorting strictly after `target` in a tree built with `key`.
+pub fn successor_by(root: &Option<Box<AVLTreeNode>>, target: &FileMetadata, key: OrderKey) -> Option<FileMetadata> {
+    nearest_after(root, &|file| compare_metadata(file, target, key) == Ordering::Greater)
+}
+
+// The last file `before` holds for; `before` has to hold for a prefix of the tree's order.
+fn nearest_before(root: &Option<Box<AVLTreeNode>>, before: &dyn Fn(&FileMetadata) -> bool) -> Option<FileMetadata> {
+    let mut current = root;
+    let mut best = None;
+    while let Some(node) = current {
+        let file = node.file.as_deref()?;
+        if before(file) {
+            best = Some(file);
+            current = &node.right;
+        } else {
+            current = &node.left;
+        }
+    }
+    best.cloned()
+}
+
+// The first file `after` holds for; `after` has to hold for a suffix of the tree's order.
+fn nearest_after(root: &Option<Box<AVLTreeNode>>, after: &dyn Fn(&FileMetadata) -> bool) -> Option<FileMetadata> {
+    let mut current = root;
+    let mut best = None;
+    while let Some(node) = current {
+        let file = node.file.as_deref()?;
+        if after(file) {
+            best = Some(file);
+            current = &node.left;
+        } else {
+            current = &node.right;
+        }
+    }
+    best.cloned()
+}
+
+// Combines the per-directory trees into a single tree so queries can span the whole scan.
+pub fn merge_avl_trees(avlvec: &[Option<Box<AVLTreeNode>>]) -> Option<Box<AVLTreeNode>> {
+    merge_avl_trees_by(avlvec, OrderKey::Name)
+}
+
+// `merge_avl_trees` into a tree ordered by `key`, e.g. by size for `files_in_size_range`.
+pub fn merge_avl_trees_by(avlvec: &[Option<Box<AVLTreeNode>>], key: OrderKey) -> Option<Box<AVLTreeNode>> {
+    let mut files = Vec::new();
+    for root in avlvec {
+        collect_avl_tree(root, &mut files);
+    }
+
+    let expected = files.len();
+    let mut merged = None;
+    for file in files {
+        merged = Some(insert_into_avl_tree_by(merged, file, key));
+    }
+    debug_assert!(!detect_cycle(&merged, expected), "merged tree has more nodes than files, or loops");
+    merged
+}
+
+// The printers write to `out` rather than stdout, so output can be captured or sent to a file.
+pub fn print_avl_tree(out: &mut impl Write, root: &Option<Box<AVLTreeNode>>, level: usize) -> io::Result<()> {
+    let mut lines = Vec::new();
+    avl_tree_lines(root, level, &mut lines);
+    for line in lines {
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+// The lines `print_avl_tree` prints, collected so callers can page through them.
+pub fn avl_tree_lines(root: &Option<Box<AVLTreeNode>>, level: usize, lines: &mut Vec<String>) {
+    if let Some(node) = root {
+        if let Some(file) = &node.file {
+            avl_tree_lines(&node.right, level+5, lines);
+            let balance = get_height(&node.left) - get_height(&node.right);
+            lines.push(format!(
+                "{}Path: {:?}; {:?} Name: {} - {} - {} bytes{} - extension {}{} - modified {} [height {}, balance {}]{}",
+                " ".repeat(level+3),
+                file.path,
+                file.file_type,
+                file.name,
+                format_permissions(file),
+                file.size,
+                format_allocated(file),
+                file.extension.as_deref().unwrap_or("-"),
+                file.detected.as_ref().map(|detected| format!(" - detected {:?}", detected)).unwrap_or_default(),
+                format_time(file.modified),
+                node.height,
+                balance,
+                if balance.abs() > 1 { " <-- UNBALANCED" } else { "" },
+            ));
+            avl_tree_lines(&node.left, level+5, lines);
+        }
+    }
+}
+
+pub fn print_avl_tree_bfs(out: &mut impl Write, root: &Option<Box<AVLTreeNode>>) -> io::Result<()> {
+    let mut queue = VecDeque::new();
+    if let Some(node) = root {
+        queue.push_back((node, 0));
+    }
+
+    let mut current_level = None;
+    while let Some((node, level)) = queue.pop_front() {
+        if current_level != Some(level) {
+            if current_level.is_some() {
+                writeln!(out)?;
+            }
+            write!(out, "Level {}:", level)?;
+            current_level = Some(level);
+        }
+        if let Some(file) = &node.file {
+            write!(out, " {} (h={})", file.name, node.height)?;
+        }
+        if let Some(left) = &node.left {
+            queue.push_back((left, level + 1));
+        }
+        if let Some(right) = &node.right {
+            queue.push_back((right, level + 1));
+        }
+    }
+    if current_level.is_some() {
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+// Looks up the file at `file_path` in a name-ordered tree, walking it by name and then path as
+// `insert_into_avl_tree` placed it, so it works on trees holding files from many directories.
+pub fn search_avl_tree(root: &Option<Box<AVLTreeNode>>, file_path: PathBuf) -> Option<FileMetadata> {
+    search_avl_tree_by(root, &FileMetadata::new(file_path, 0, FileType::File, None), OrderKey::Name)
+}
+
+// The file comparing equal to `target` in a tree built with `key`; as with `remove_from_avl_tree_by`,
+// a size-ordered tree is searched by `target`'s size and path together.
+pub fn search_avl_tree_by(root: &Option<Box<AVLTreeNode>>, target: &FileMetadata, key: OrderKey) -> Option<FileMetadata> {
+    let mut current = root;
+    while let Some(node) = current {
+        let file = node.file.as_deref()?;
+        current = match compare_metadata(target, file, key) {
+            Ordering::Equal => return Some(file.clone()),
+            Ordering::Greater => &node.right,
+            Ordering::Less => &node.left,
+        };
+    }
+    None
+}
+
+// Visits every file in order, allowing changes that don't affect where it sorts.
+pub fn for_each_file_mut(root: &mut Option<Box<AVLTreeNode>>, f: &mut impl FnMut(&mut FileMetadata)) {
+    if let Some(node) = root {
+        for_each_file_mut(&mut node.left, f);
+        if let Some(file) = &mut node.file {
+            f(Arc::make_mut(file));
+        }
+        for_each_file_mut(&mut node.right, f);
+    }
+}
+
+// Calls `f` on every file in tree order. The searches and listings are built on this, and it's
+// the way to gather anything else from a tree without another recursive walk.
+pub fn visit_in_order(root: &Option<Box<AVLTreeNode>>, f: &mut dyn FnMut(&FileMetadata)) {
+    if let Some(node) = root {
+        visit_in_order(&node.left, f);
+        if let Some(file) = &node.file {
+            f(file);
+        }
+        visit_in_order(&node.right, f);
+    }
+}
+
+// In-order walk that only descends where the range can be. `locate` says where a file sits against
+// the range: `Less` before it, `Greater` after it, `Equal` inside. It has to agree with the tree's
+// key, or whole subtrees of matches are skipped.
+pub fn search_range(root: &Option<Box<AVLTreeNode>>, locate: &mut dyn FnMut(&FileMetadata) -> Ordering) -> Vec<FileMetadata> {
+    let mut found = Vec::new();
+    range_into(root, locate, &mut found);
+    found
+}
+
+fn range_into(root: &Option<Box<AVLTreeNode>>, locate: &mut dyn FnMut(&FileMetadata) -> Ordering, found: &mut Vec<FileMetadata>) {
+    let Some(node) = root else { return };
+    let Some(file) = node.file.as_deref() else { return };
+    let position = locate(file);
+    if position != Ordering::Less {
+        range_into(&node.left, locate, found);
+    }
+    if position == Ordering::Equal {
+        found.push(file.clone());
+    }
+    if position != Ordering::Greater {
+        range_into(&node.right, locate, found);
+    }
+}
+
+// Files of `min..=max` bytes in a tree built with `OrderKey::Size`, smallest first.
+pub fn files_in_size_range(root: &Option<Box<AVLTreeNode>>, min: u64, max: u64) -> Vec<FileMetadata> {
+    search_range(root, &mut |file| {
+        if file.size < min {
+            Ordering::Less
+        } else if file.size > max {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    })
+}
+
+// Files whose name starts with `prefix` in a name-ordered tree, in name order.
+pub fn files_with_prefix(root: &Option<Box<AVLTreeNode>>, prefix: &str) -> Vec<FileMetadata> {
+    let prefix = name_key(prefix);
+    search_range(root, &mut |file| {
+        let name = name_key(&file.name);
+        if name.starts_with(&*prefix) {
+            Ordering::Equal
+        } else {
+            name.as_ref().cmp(prefix.as_ref())
+        }
+    })
+}
+
+pub fn collect_avl_tree(root: &Option<Box<AVLTreeNode>>, files: &mut Vec<FileMetadata>) {
+    visit_in_order(root, &mut |file| files.push(file.clone()));
+}
@@ -0,0 +1,66 @@
+use std::borrow::Cow;
+
+use crate::metadata::{FileMetadata, FileType};
+
+// `--color=auto|always|never`. Auto colors only when stdout is a terminal and `NO_COLOR` is unset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn parse(value: &str) -> Option<ColorChoice> {
+        match value {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    // `no_color` is whether `NO_COLOR` is set to something non-empty, per no-color.org; an explicit
+    // `always` still wins over it.
+    pub fn enabled(self, is_terminal: bool, no_color: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => is_terminal && !no_color,
+        }
+    }
+}
+
+const BLUE: &str = "\x1b[34m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+// The escape code for how `ls --color` would show this entry: directories blue, executables
+// green, symlinks cyan. Executables and symlinks come from the mode bits, so only Unix has them.
+fn style(file: &FileMetadata) -> Option<&'static str> {
+    if file.file_type == FileType::Directory {
+        return Some(BLUE);
+    }
+    #[cfg(unix)]
+    {
+        const S_IFMT: u32 = 0o170000;
+        const S_IFLNK: u32 = 0o120000;
+        if file.file_type == FileType::Special && file.mode & S_IFMT == S_IFLNK {
+            return Some(CYAN);
+        }
+        if file.file_type == FileType::File && file.mode & 0o111 != 0 {
+            return Some(GREEN);
+        }
+    }
+    None
+}
+
+// `text` (usually the entry's path or name) wrapped in the entry's color, or as-is when `color` is
+// off or the entry has none.
+pub fn paint<'a>(file: &FileMetadata, text: &'a str, color: bool) -> Cow<'a, str> {
+    match style(file) {
+        Some(code) if color => Cow::Owned(format!("{}{}{}", code, text, RESET)),
+        _ => Cow::Borrowed(text),
+    }
+}
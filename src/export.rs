@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::avl::{collect_avl_tree, AVLTreeNode};
+use crate::hash_table::HashTable;
+use crate::metadata::{format_size, FileMetadata, FileType};
+
+// Quotes a field per RFC 4180 when it contains a comma, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_csv_row(writer: &mut impl Write, file: &FileMetadata) -> io::Result<()> {
+    write!(
+        writer,
+        "{},{},{:?},{},{}\r\n",
+        csv_field(&file.name),
+        csv_field(&file.path.to_string_lossy()),
+        file.file_type,
+        file.size,
+        file.file_count
+    )
+}
+
+// Every indexed entry: files from the AVL trees, then directories from the hash table, in the
+// order the scan happened to store them. With `sorted`, everything is ordered by path instead,
+// so two scans of an unchanged directory give identical output whatever the bucket count or
+// traversal order.
+pub fn collect_entries(avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable, sorted: bool) -> Vec<FileMetadata> {
+    let mut entries = Vec::new();
+    for root in avlvec {
+        collect_avl_tree(root, &mut entries);
+    }
+    entries.extend(table.entries().cloned());
+    if sorted {
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    entries
+}
+
+pub fn export_csv(avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable, out: &Path, sorted: bool) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(out)?);
+    write!(writer, "name,path,type,size,file_count\r\n")?;
+
+    for file in &collect_entries(avlvec, table, sorted) {
+        write_csv_row(&mut writer, file)?;
+    }
+    writer.flush()
+}
+
+// Every indexed file and directory, largest first and ties by path, as `print_size_report` lists them.
+pub fn size_report(avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable) -> Vec<FileMetadata> {
+    let mut entries = collect_entries(avlvec, table, true);
+    entries.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    entries
+}
+
+// A plain-text listing of `entries` in the order given, one per line with a human-readable size,
+// and a total at the bottom. Directory sizes already include their files, so the total only adds
+// up the rest.
+pub fn print_size_report(out: &mut impl Write, entries: &[FileMetadata]) -> io::Result<()> {
+    let directories = entries.iter().filter(|file| file.file_type == FileType::Directory).count();
+    writeln!(out, "Size report: {} files and {} directories, largest first", entries.len() - directories, directories)?;
+    writeln!(out, "{:>10}  {:<9}  Path", "Size", "Type")?;
+    for file in entries {
+        writeln!(out, "{:>10}  {:<9}  {}", format_size(file.size), format!("{:?}", file.file_type), file.path.display())?;
+    }
+    let total: u64 = entries.iter().filter(|file| file.file_type != FileType::Directory).map(|file| file.size).sum();
+    writeln!(out, "Total: {} ({} bytes)", format_size(total), total)
+}
+
+pub fn export_report(avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable, out: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(out)?);
+    print_size_report(&mut writer, &size_report(avlvec, table))?;
+    writer.flush()
+}
+
+// The index below `root` as one JSON object per directory, holding its files and subdirectories
+// in name order. Every node has its name, path, type and size; directories also carry their file
+// count. The root usually has no entry in the table, so its totals are summed from its children.
+pub fn to_nested_json(root: &Path, avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable) -> Value {
+    let mut children: BTreeMap<PathBuf, Vec<FileMetadata>> = BTreeMap::new();
+    for file in collect_entries(avlvec, table, false) {
+        if let Some(parent) = file.path.parent() {
+            children.entry(parent.to_path_buf()).or_default().push(file);
+        }
+    }
+    for files in children.values_mut() {
+        files.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.path.cmp(&b.path)));
+    }
+
+    let directory = table.find_by_path(root).cloned().unwrap_or_else(|| {
+        let mut directory = FileMetadata::new(root.to_path_buf(), 0, FileType::Directory, None);
+        for child in children.get(root).into_iter().flatten() {
+            directory.size += child.size;
+            directory.file_count += if child.file_type == FileType::File { 1 } else { child.file_count };
+        }
+        directory
+    });
+    nested_node(&directory, &children)
+}
+
+fn nested_node(file: &FileMetadata, children: &BTreeMap<PathBuf, Vec<FileMetadata>>) -> Value {
+    let mut node = json!({
+        "name": file.name,
+        "path": file.path,
+        "type": file.file_type,
+        "size": file.size,
+    });
+    if file.file_type == FileType::Directory {
+        let (directories, files): (Vec<&FileMetadata>, Vec<&FileMetadata>) =
+            children.get(&file.path).into_iter().flatten().partition(|child| child.file_type == FileType::Directory);
+        node["file_count"] = json!(file.file_count);
+        node["files"] = files.into_iter().map(|child| nested_node(child, children)).collect();
+        node["directories"] = directories.into_iter().map(|child| nested_node(child, children)).collect();
+    }
+    node
+}
@@ -0,0 +1,377 @@
+use std::io::{self, Write};
+use std::path::{Component, Path};
+use std::sync::Arc;
+use std::{cmp, fmt};
+
+use crate::metadata::{format_permissions, format_time, name_key, path_key, same_path, FileMetadata};
+
+const FNV_PRIME: u64 = 1099511628211;
+
+const FNV_OFFSET_BASIS: u64 = 	14695981039346656037;
+
+pub trait Hasher: fmt::Debug + Send + Sync {
+    fn hash(&self, key: &str) -> u64;
+}
+
+#[derive(Debug)]
+pub struct FnvHasher;
+
+impl Hasher for FnvHasher {
+    fn hash(&self, key: &str) -> u64 {
+        let mut hash: u64 = FNV_OFFSET_BASIS;
+
+        for byte in key.bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+        hash
+    }
+}
+
+#[derive(Debug)]
+pub struct Djb2Hasher;
+
+impl Hasher for Djb2Hasher {
+    fn hash(&self, key: &str) -> u64 {
+        let mut hash: u64 = 5381;
+
+        for byte in key.bytes() {
+            hash = hash.wrapping_mul(33).wrapping_add(u64::from(byte));
+        }
+        hash
+    }
+}
+
+// The load factor `--buckets auto` sizes for, and the one tables grow at.
+pub const MAX_LOAD_FACTOR: f64 = 0.75;
+
+#[derive(Debug)]
+pub struct HashTable {
+    // Entries are shared like the trees' (see `AVLTreeNode::file`), so a cloned table points at the
+    // same directories until one of them is replaced.
+    // Changed only through the methods below, which keep `count` in step.
+    pub buckets: Vec<Vec<Arc<FileMetadata>>>,
+    // Entries across all buckets, so `len` and the load check on every insert don't walk the table.
+    count: usize,
+    hasher: Arc<dyn Hasher>,
+    // Load factor above which `insert` doubles the bucket count; `None` keeps the count fixed.
+    max_load: Option<f64>,
+    resizes: usize,
+}
+
+impl HashTable {
+    pub fn new(size: usize) -> Self {
+        HashTable::with_hasher(size, Arc::new(FnvHasher))
+    }
+
+    pub fn with_hasher(size: usize, hasher: Arc<dyn Hasher>) -> Self {
+        HashTable {
+            buckets: vec![Vec::new(); size],
+            count: 0,
+            hasher,
+            max_load: None,
+            resizes: 0,
+        }
+    }
+
+    // Lets the table grow: once an insert takes the load factor above `max_load`, the buckets are
+    // doubled and every entry rehashed.
+    pub fn growing(mut self, max_load: f64) -> Self {
+        self.max_load = Some(max_load);
+        self
+    }
+
+    // How many times the table has grown since it was created.
+    pub fn resizes(&self) -> usize {
+        self.resizes
+    }
+
+    fn grow(&mut self) {
+        self.rehash((self.buckets.len() * 2).max(1));
+        self.resizes += 1;
+    }
+
+    // Empties every bucket but keeps the buckets and their allocations, so the table can be
+    // filled again without reallocating.
+    pub fn clear(&mut self) {
+        for files in &mut self.buckets {
+            files.clear();
+        }
+        self.count = 0;
+    }
+
+    // Cuts the table down to `size` buckets (at least one) and rehashes every entry into them.
+    // A table that already has that many or fewer is left alone.
+    pub fn shrink_to(&mut self, size: usize) {
+        let size = size.max(1);
+        if size < self.buckets.len() {
+            self.rehash(size);
+        }
+    }
+
+    fn rehash(&mut self, size: usize) {
+        let entries: Vec<Arc<FileMetadata>> = self.buckets.drain(..).flatten().collect();
+        self.count = entries.len();
+        self.buckets = vec![Vec::new(); size];
+        for file in entries {
+            let index = self.bucket_index(&HashTable::key(&file.name, &file.path, file.size));
+            self.buckets[index].push(file);
+        }
+    }
+
+    // Builds the key from raw name and path text instead of `Debug` output, which may change
+    // between Rust versions. Path components are joined with '/' so separators don't affect it,
+    // and '\0' (never valid in a name or path) keeps the fields from running into each other.
+    // Name and path go through `name_key` and `path_key` first, so every spelling that `same_path`
+    // treats as one path (a verbatim Windows prefix, other letter case where the platform ignores
+    // it) lands in the same bucket.
+    pub fn key(name: &str, path: &Path, size: u64) -> String {
+        let components: Vec<String> = path_key(path)
+            .components()
+            .map(|component| match component {
+                Component::RootDir => String::new(),
+                other => other.as_os_str().to_string_lossy().into(),
+            })
+            .collect();
+        format!("{}\0{}\0{}", name_key(name), components.join("/"), size)
+    }
+
+    // What `key` identifies, compared the way it was built rather than on the raw text.
+    fn same_entry(file: &FileMetadata, name: &str, path: &Path, size: u64) -> bool {
+        file.size == size && name_key(&file.name) == name_key(name) && same_path(&file.path, path)
+    }
+
+    // The one place a key is reduced to a bucket, so lookups and the displayed indices agree.
+    // Callers make sure there is at least one bucket.
+    fn bucket_index(&self, key: &str) -> usize {
+        (self.hasher.hash(key) % self.buckets.len() as u64) as usize
+    }
+
+    // An entry with the same key is replaced rather than added again, so inserting a directory
+    // twice (say, rebuilding into a table that wasn't cleared) leaves one copy.
+    pub fn insert(&mut self, file: FileMetadata) {
+        self.insert_or_replace(file);
+    }
+
+    // Like `insert`, but hands back the entry that was replaced, if any.
+    // A table created with no buckets gets its first one here rather than dividing by zero.
+    pub fn insert_or_replace(&mut self, file: FileMetadata) -> Option<FileMetadata> {
+        if self.buckets.is_empty() {
+            self.buckets.push(Vec::new());
+        }
+        let index = self.bucket_index(&HashTable::key(&file.name, &file.path, file.size));
+        let files = &mut self.buckets[index];
+        match files.iter_mut().find(|existing| HashTable::same_entry(existing, &file.name, &file.path, file.size)) {
+            Some(existing) => Some(Arc::unwrap_or_clone(std::mem::replace(existing, Arc::new(file)))),
+            None => {
+                files.push(Arc::new(file));
+                self.count += 1;
+                if self.max_load.is_some_and(|max_load| self.load_factor() > max_load) {
+                    self.grow();
+                }
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str, path: &Path, size: u64) -> Option<&FileMetadata> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let index = self.bucket_index(&HashTable::key(name, path, size));
+        self.buckets[index].iter().find(|file| HashTable::same_entry(file, name, path, size)).map(|file| &**file)
+    }
+
+    pub fn remove(&mut self, name: &str, path: &Path, size: u64) -> Option<FileMetadata> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let index = self.bucket_index(&HashTable::key(name, path, size));
+        let files = &mut self.buckets[index];
+        let position = files.iter().position(|file| HashTable::same_entry(file, name, path, size))?;
+        self.count -= 1;
+        Some(Arc::unwrap_or_clone(files.remove(position)))
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Vec<&FileMetadata> {
+        let name = name_key(name);
+        self.entries().filter(|file| name_key(&file.name) == name).collect()
+    }
+
+    pub fn find_by_path(&self, path: &Path) -> Option<&FileMetadata> {
+        let path = path_key(path);
+        self.entries().find(|file| path_key(&file.path) == path)
+    }
+
+    // Directories whose path starts with `prefix`, including `prefix` itself. The table is ordered
+    // by hash rather than by path, so this is a linear scan over every entry.
+    pub fn dirs_under(&self, prefix: &Path) -> Vec<&FileMetadata> {
+        let prefix = path_key(prefix);
+        self.entries().filter(|file| path_key(&file.path).starts_with(&prefix)).collect()
+    }
+
+    // The key includes the size, which callers reacting to a change on disk no longer know,
+    // so these scan every bucket.
+    pub fn remove_path(&mut self, path: &Path) -> Option<FileMetadata> {
+        for files in &mut self.buckets {
+            if let Some(index) = files.iter().position(|file| same_path(&file.path, path)) {
+                self.count -= 1;
+                return Some(Arc::unwrap_or_clone(files.remove(index)));
+            }
+        }
+        None
+    }
+
+    pub fn remove_under(&mut self, prefix: &Path) -> Vec<FileMetadata> {
+        let prefix = path_key(prefix);
+        let mut removed = Vec::new();
+        for files in &mut self.buckets {
+            let (under, kept): (Vec<_>, Vec<_>) = files.drain(..).partition(|file| path_key(&file.path).starts_with(&prefix));
+            *files = kept;
+            removed.extend(under.into_iter().map(Arc::unwrap_or_clone));
+        }
+        self.count -= removed.len();
+        removed
+    }
+
+    // Every entry, bucket by bucket.
+    pub fn entries(&self) -> impl Iterator<Item = &FileMetadata> {
+        self.buckets.iter().flatten().map(|file| &**file)
+    }
+
+    // Both read the running count instead of walking the buckets.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    // Entries per bucket; above 1.0 once chains start to form. Computed in f64 so large counts
+    // don't lose precision, and 0.0 for a table without buckets.
+    pub fn load_factor(&self) -> f64 {
+        if self.buckets.is_empty() {
+            return 0.0;
+        }
+        self.len() as f64 / self.buckets.len() as f64
+    }
+
+    // Fraction of buckets holding at least one entry, between 0.0 and 1.0.
+    pub fn bucket_usage(&self) -> f64 {
+        if self.buckets.is_empty() {
+            return 0.0;
+        }
+        let used = self.buckets.iter().filter(|files| !files.is_empty()).count();
+        used as f64 / self.buckets.len() as f64
+    }
+
+    // The `n` biggest directories, largest first and ties by path.
+    pub fn largest(&self, n: usize) -> Vec<&FileMetadata> {
+        let mut directories: Vec<&FileMetadata> = self.entries().collect();
+        directories.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        directories.truncate(n);
+        directories
+    }
+
+    // Returns (longest chain, empty buckets, average length of the non-empty chains).
+    pub fn collision_stats(&self) -> (usize, usize, f64) {
+        let mut longest = 0;
+        let mut empty = 0;
+        let mut entries = 0;
+
+        for files in &self.buckets {
+            if files.is_empty() {
+                empty += 1;
+            }
+            longest = cmp::max(longest, files.len());
+            entries += files.len();
+        }
+
+        let used = self.buckets.len() - empty;
+        let average = if used == 0 { 0.0 } else { entries as f64 / used as f64 };
+        (longest, empty, average)
+    }
+}
+
+impl Clone for HashTable {
+    fn clone(&self) -> Self {
+        HashTable {
+            buckets: self.buckets.clone(),
+            count: self.count,
+            hasher: Arc::clone(&self.hasher),
+            max_load: self.max_load,
+            resizes: self.resizes,
+        }
+    }
+}
+
+// The hasher only decides placement, so two tables are equal when their buckets are.
+impl PartialEq for HashTable {
+    fn eq(&self, other: &Self) -> bool {
+        self.buckets == other.buckets
+    }
+}
+
+pub fn print_hash_table(out: &mut impl Write, hash_table: &HashTable) -> io::Result<()> {
+    for line in hash_table_lines(hash_table) {
+        writeln!(out, "{}", line)?;
+    }
+    print_hash_table_summary(out, hash_table)
+}
+
+// One line per entry, as printed by `print_hash_table`, so callers can page through them. The
+// bucket shown is the position in `buckets`, which is where `bucket_index` places and finds it.
+pub fn hash_table_lines(hash_table: &HashTable) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (bucket_index, files) in hash_table.buckets.iter().enumerate() {
+        for file in files {
+            lines.push(format!(
+                "Bucket {}: {} ({:?} Name: {} - {} - {} bytes - {} kilobytes - {} megabytes - {} files - modified {} - created {})",
+                bucket_index,
+                file.path.display(),
+                file.file_type,
+                file.name,
+                format_permissions(file),
+                file.size,
+                file.size as f32 / 1024.0,
+                file.size as f32 / 1024.0 / 1024.0,
+                file.file_count,
+                format_time(file.modified),
+                format_time(file.created),
+            ));
+        }
+    }
+    lines
+}
+
+// A ranked list of `directories` (as returned by `largest`) with each one's share of `total`, the
+// scanned size. The cumulative share is of everything listed so far; a directory inside one already
+// listed is counted there, so it adds nothing and the running figure never passes 100%.
+pub fn print_largest_directories(out: &mut impl Write, directories: &[&FileMetadata], total: u64) -> io::Result<()> {
+    let percent = |size: u64| if total == 0 { 0.0 } else { size as f64 * 100.0 / total as f64 };
+    let mut covered = 0;
+    for (rank, file) in directories.iter().enumerate() {
+        if !directories[..rank].iter().any(|listed| file.path.starts_with(&listed.path)) {
+            covered += file.size;
+        }
+        writeln!(
+            out,
+            "{:>3}. {} - {} bytes - {:.1}% (cumulative {:.1}%)",
+            rank + 1,
+            file.path.display(),
+            file.size,
+            percent(file.size),
+            percent(covered),
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_hash_table_summary(out: &mut impl Write, hash_table: &HashTable) -> io::Result<()> {
+    let bucket_count = hash_table.buckets.iter().filter(|files| !files.is_empty()).count();
+    writeln!(out, "Used buckets: {}", bucket_count)?;
+    writeln!(out, "Entries: {}", hash_table.len())?;
+    writeln!(out, "Load factor {:.2}", hash_table.load_factor())?;
+    writeln!(out, "Bucket usage {:.2}", hash_table.bucket_usage())
+}
@@ -0,0 +1,43 @@
+use std::io::BufRead;
+use std::str::FromStr;
+
+// What a numeric prompt got back once the input was usable.
+#[derive(Debug, PartialEq)]
+pub enum Answer<T> {
+    Value(T),
+    // A blank line; callers treat it as "do nothing" rather than an error.
+    Blank,
+    // Input ended, so there's nothing left to ask.
+    Closed,
+}
+
+// `None` for a blank line, otherwise the parsed value or the reason it didn't parse.
+pub fn parse_number<T: FromStr>(line: &str) -> Option<Result<T, T::Err>> {
+    let line = line.trim();
+    if line.is_empty() {
+        None
+    } else {
+        Some(line.parse())
+    }
+}
+
+// Reads lines from `input` until one is blank or parses as a `T`, asking again after anything else.
+pub fn read_number<T: FromStr>(input: &mut impl BufRead) -> Answer<T> {
+    loop {
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) => return Answer::Closed,
+            Ok(_) => {}
+            Err(e) => {
+                println!("Failed to read line: {}", e);
+                return Answer::Closed;
+            }
+        }
+
+        match parse_number(&line) {
+            None => return Answer::Blank,
+            Some(Ok(value)) => return Answer::Value(value),
+            Some(Err(_)) => println!("Please type a number! (got {:?})", line.trim()),
+        }
+    }
+}
@@ -0,0 +1,19 @@
+// Indexing and file operations behind the interactive CLI in main.rs. Directories live in a
+// chained hash table and files in one AVL tree per directory.
+
+pub mod avl;
+pub mod color;
+pub mod export;
+pub mod hash_table;
+pub mod input;
+pub mod metadata;
+pub mod nav;
+pub mod ops;
+pub mod scan;
+pub mod search;
+pub mod snapshot;
+pub mod watch;
+
+pub use avl::AVLTreeNode;
+pub use hash_table::HashTable;
+pub use metadata::{DetectedType, FileMetadata, FileType};
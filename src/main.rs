@@ -1,377 +1,502 @@
-use std::{cmp, fs};
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
-extern crate fs_extra;
-use fs_extra::dir::{get_size, remove};
-use std::fs::File;
-use std::io;
-use std::io::Read;
-
-/**
-Include the following in your Cargo.toml file:
-[dependencies]
-fs_extra = "1.3.0"
-**/
-
-const FNV_PRIME: u64 = 1099511628211;
-const FNV_OFFSET_BASIS: u64 = 	14695981039346656037;
-
-#[derive(Debug)]
-#[derive(PartialEq)]
-enum FileType {
-    File,
-    Directory,
-}
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use file_management::avl::{
+    avl_tree_lines, collect_avl_tree, count, files_in_size_range, height_bound, merge_avl_trees, merge_avl_trees_by,
+    predecessor, print_avl_tree_bfs, select_kth,
+    successor, tree_height, visit_in_order, OrderKey,
+};
+use file_management::color::ColorChoice;
+use file_management::export::{export_csv, export_report, print_size_report, size_report, to_nested_json};
+use file_management::hash_table::{
+    hash_table_lines, print_hash_table_summary, print_largest_directories, Djb2Hasher, Hasher, MAX_LOAD_FACTOR,
+};
+use file_management::input::{parse_number, read_number, Answer};
+use file_management::metadata::{format_allocated, format_time};
+use file_management::nav::Navigator;
+use file_management::ops;
+use file_management::scan::{
+    auto_bucket_count, build_index_roots, count_directories, count_entries, count_entries_up_to, detect_types, expand_path,
+    format_progress, index_summary, needs_confirmation, normalize_roots, parse_roots, print_skipped, resolve_path, validate_scan_root,
+    Progress, ScanOptions, LARGE_SCAN_ENTRIES,
+};
+use file_management::search::{
+    dirs_by_glob, duplicate_names, extension_histogram, files_by_modified, find_empty, find_larger_than, fuzzy_search,
+    mismatched_types, modified_since, page_ranges, parse_size, print_directory_tree_colored, print_entries_colored,
+    load_limit_rules, print_extension_histogram, print_size_breakdown, run_query, scan_limits, search_all, search_avl_by_extension, search_avl_by_name,
+    search_by_glob, search_by_regex, search_everything, sort_entries, top_level_sizes, top_n_largest, verify_index,
+    Query, SortKey,
+};
+use file_management::snapshot::{diff_indexes, diff_trees, print_diff, Snapshot};
+use file_management::watch::{refresh_path, rescan_subtree, IndexWatcher, SharedIndex};
+use file_management::{FileMetadata, FileType, HashTable};
+
+// Asks how to order a listing; `None` keeps the structure's own layout.
+fn read_sort_choice() -> Option<(SortKey, bool)> {
+    let mut key = String::new();
+    println!("Sort by name, size, path or files (leave empty to show the raw layout): ");
 
-impl Clone for FileType {
-    fn clone(&self) -> Self {
-        match self {
-            FileType::File => FileType::File,
-            FileType::Directory => FileType::Directory,
-        }
-    }
-}
+    io::stdin()
+        .read_line(&mut key)
+        .expect("Failed to read line");
 
-#[derive(Debug)]
-#[derive(PartialEq)]
-struct FileMetadata {
-    name: String,
-    path: PathBuf,
-    size: u64,
-    file_type: FileType,
-}
+    let key = SortKey::parse(&key)?;
 
-impl Clone for FileMetadata {
-    fn clone(&self) -> Self {
-        FileMetadata {
-            name: self.name.clone(),
-            path: self.path.clone(),
-            size: self.size,
-            file_type: self.file_type.clone(),
-        }
-    }
-}
+    let mut order = String::new();
+    println!("Order (asc/desc) [asc]: ");
 
-#[derive(Debug)]
-#[derive(PartialEq)]
-struct HashTable {
-    buckets: Vec<Vec<FileMetadata>>,
+    io::stdin()
+        .read_line(&mut order)
+        .expect("Failed to read line");
+
+    Some((key, order.trim().eq_ignore_ascii_case("desc")))
 }
 
-impl HashTable {
-    fn new(size: usize) -> Self {
-        HashTable {
-            buckets: vec![Vec::new(); size],
+// Prints `items` a page at a time, waiting for enter between pages; 'q' stops early.
+fn print_paged<T>(items: &[T], page_size: usize, print: impl Fn(&mut io::Stdout, &[T]) -> io::Result<()>) {
+    let pages = page_ranges(items.len(), page_size);
+    for (page, range) in pages.iter().enumerate() {
+        print(&mut io::stdout(), &items[range.clone()]).expect("Failed to write output");
+        if page + 1 == pages.len() {
+            break;
         }
-    }
 
-    fn hash(&self, key: &str) -> usize {
-        let mut hash: u64 = FNV_OFFSET_BASIS;
+        let mut answer = String::new();
+        println!("-- {} of {} shown, press enter for more or q to stop --", range.end, items.len());
 
-        for byte in key.bytes() {
-            hash ^= u64::from(byte);
-            hash = hash.wrapping_mul(FNV_PRIME);
-        }
-        hash as usize
-    }
+        io::stdin()
+            .read_line(&mut answer)
+            .expect("Failed to read line");
 
-    fn insert(&mut self, file: FileMetadata) {
-        let name = format!("{:?}{:?}{}", file.name,file.path,file.size);
-        let hash = self.hash(&name);
-        let len = self.buckets.len();
-        let files = &mut self.buckets[hash % len];
-        files.push(file.clone());
+        if answer.trim().eq_ignore_ascii_case("q") {
+            break;
+        }
     }
 }
 
-impl Clone for HashTable {
-    fn clone(&self) -> Self {
-        HashTable {
-            buckets: self.buckets.clone(),
-        }
+fn print_lines(out: &mut impl Write, lines: &[String]) -> io::Result<()> {
+    for line in lines {
+        writeln!(out, "{}", line)?;
     }
+    Ok(())
 }
 
-fn build_hash_table(path: &Path, mut hash_table: HashTable) -> Option<HashTable>{
-    for entry in fs::read_dir(path).ok()? {
-        match entry {
-            Ok(entry) => {
-                let file_type = entry.file_type().unwrap();
+// Snapshots ending in .bin use the binary format, anything else JSON.
+fn is_binary_snapshot(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "bin")
+}
 
-                if file_type.is_file() {
-                    continue;
-                } else if file_type.is_dir() {
-                    let dir_size = get_size(&entry.path());
-                    match dir_size {
-                        Ok(size) => {
-                            let file_metadata = FileMetadata {
-                                name: entry.file_name().to_string_lossy().into(),
-                                path: entry.path(),
-                                size,
-                                file_type: FileType::Directory,
-                            };
-                            hash_table.insert(file_metadata);
-                            hash_table = build_hash_table(&entry.path(), hash_table)?;
-                        }
-                        Err(e) => {
-                            println!("Error reading directory: {}", e);
-                            continue;
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                println!("Error reading directory: {}", e);
-                continue;
-            }
-        }
-    }
-    Some(hash_table)
+// Says what was loaded, since a snapshot can be far older than the files it lists.
+fn load_snapshot(path: &Path) -> io::Result<Snapshot> {
+    let snapshot = if is_binary_snapshot(path) { Snapshot::load_binary(path) } else { Snapshot::load(path) }?;
+    println!("Loaded {:?}: {}", path, snapshot.describe());
+    Ok(snapshot)
 }
 
-fn print_hash_table(hash_table: &HashTable) {
-    let mut bucket_count = 0;
-    for (bucket_index, files) in hash_table.buckets.iter().enumerate() {
-        if files.is_empty() {
-            continue;
-        }
-        bucket_count += 1;
-        for file in files {
-            println!(
-                "Bucket {}: {} ({:?} Name: {} - {} bytes - {} kilobytes - {} megabytes)",
-                bucket_index,
-                file.path.display(),
-                file.file_type,
-                file.name,
-                file.size,
-                file.size as f32 / 1024.0,
-                file.size as f32 / 1024.0 / 1024.0,
-            );
-        }
+// --diff OLD NEW compares two saved snapshots instead of starting the menu.
+fn print_snapshot_diff(old: &Path, new: &Path) {
+    let (old_trees, old_table) = match load_snapshot(old) {
+        Ok(snapshot) => snapshot.into_index(),
+        Err(e) => return println!("Failed to load {:?}: {}", old, e),
+    };
+    let (new_trees, new_table) = match load_snapshot(new) {
+        Ok(snapshot) => snapshot.into_index(),
+        Err(e) => return println!("Failed to load {:?}: {}", new, e),
+    };
+
+    let files = diff_trees(&old_trees, &new_trees);
+    let directories = diff_indexes(&old_table, &new_table);
+    if files.is_empty() && directories.is_empty() {
+        println!("No changes.");
     }
-    println!("Total buckets: {}", bucket_count);
-    println!("Load factor {}", bucket_count as f32 / hash_table.buckets.len() as f32);
+    print_diff(&mut io::stdout(), &files).expect("Failed to write output");
+    print_diff(&mut io::stdout(), &directories).expect("Failed to write output");
 }
 
-#[derive(Debug)]
-struct AVLTreeNode {
-    file: Option<FileMetadata>,
-    left: Option<Box<AVLTreeNode>>,
-    right: Option<Box<AVLTreeNode>>,
-    parent: Option<Box<AVLTreeNode>>,
-    height: i32,
-}
+// Prints `question` and returns the answer, or `None` once input has ended.
+fn ask(question: &str) -> Option<String> {
+    println!("{}", question);
 
-impl AVLTreeNode {
-    fn new(file: FileMetadata) -> Self {
-        AVLTreeNode {
-            file: Some(file),
-            left: None,
-            right: None,
-            parent: None,
-            height: 1,
-        }
-    }
+    let mut answer = String::new();
+    let read = io::stdin()
+        .read_line(&mut answer)
+        .expect("Failed to read line");
+    (read > 0).then_some(answer)
 }
 
-impl Clone for AVLTreeNode {
-    fn clone(&self) -> Self {
-        AVLTreeNode {
-            file: self.file.clone(),
-            left: self.left.clone(),
-            right: self.right.clone(),
-            parent: self.parent.clone(),
-            height: self.height,
+// Builds a `Query` one filter at a time; a blank answer leaves that filter out. `None` when stdin
+// closes or an answer can't be used.
+fn ask_query() -> Option<Query> {
+    let optional = |answer: String| Some(answer.trim().to_string()).filter(|answer| !answer.is_empty());
+    let size = |answer: Option<String>| match answer {
+        Some(answer) => match parse_size(&answer) {
+            Some(size) => Ok(Some(size)),
+            None => Err(()),
+        },
+        None => Ok(None),
+    };
+
+    let name = optional(ask("Name or path glob (e.g. *.log) [any]: ")?);
+    let Ok(min_size) = size(optional(ask("Smallest size (e.g. 10MB) [any]: ")?)) else {
+        println!("Invalid size");
+        return None;
+    };
+    let Ok(max_size) = size(optional(ask("Largest size [any]: ")?)) else {
+        println!("Invalid size");
+        return None;
+    };
+    let file_type = match optional(ask("Type (file, directory or special) [any]: ")?).as_deref() {
+        None => None,
+        Some("file") => Some(FileType::File),
+        Some("directory" | "dir") => Some(FileType::Directory),
+        Some("special") => Some(FileType::Special),
+        Some(other) => {
+            println!("Unknown type {:?}", other);
+            return None;
         }
-    }
+    };
+    let extension = optional(ask("Extension (e.g. log) [any]: ")?);
+    Some(Query { name, min_size, max_size, file_type, extension })
 }
 
-fn build_avl_tree(path: &Path, avlvec: &mut Vec<Option<Box<AVLTreeNode>>>) -> Option<Box<AVLTreeNode>> {
-    let mut root = None;
-
-    for entry in fs::read_dir(path).ok()?{
-        match entry {
-            Ok(entry) => {
-                let file_type = entry.file_type().unwrap();
-                let metadata = entry.metadata().unwrap();
-
-                if file_type.is_file() {
-                    let file_metadata = FileMetadata {
-                        name: entry.file_name().to_string_lossy().into(),
-                        path: entry.path(),
-                        size: metadata.len(),
-                        file_type: FileType::File,
-                    };
-                    root = Some(insert_into_avl_tree(root, file_metadata));
-                } else if file_type.is_dir() {
-                    build_avl_tree(&entry.path(), avlvec);
-                }
-                else{
-                    let file_metadata = FileMetadata {
-                    name: entry.file_name().to_string_lossy().into(),
-                    path: entry.path(),
-                    size: metadata.len(),
-                    file_type: FileType::Directory,
-                    };
-                    root = Some(insert_into_avl_tree(root, file_metadata));
-                }
+// --query SNAPSHOT searches a saved index instead of scanning, so it works with the drive gone.
+// Nothing here touches the indexed paths; operations that need them aren't offered.
+fn query_snapshot(path: &Path, page_size: usize, color: bool) {
+    let print_listing = |out: &mut io::Stdout, entries: &[FileMetadata]| print_entries_colored(out, entries, color);
+    let snapshot = match load_snapshot(path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return println!("Failed to load {:?}: {}", path, e),
+    };
+    let roots = snapshot.roots();
+    let (avlvec, hash_table) = snapshot.into_index();
+    let by_size = merge_avl_trees_by(&avlvec, OrderKey::Size);
+
+    println!("Searching the snapshot in {:?} without touching the disk.", path);
+    println!("Reading, writing, creating, deleting and verifying files are unavailable in this mode.");
+    loop {
+        println!("Enter the number of the option you want to choose: ");
+        println!("1. Search files and directories by name");
+        println!("2. Search for files by glob pattern");
+        println!("3. Find files between two sizes");
+        println!("4. Show the directory tree");
+        println!("5. Show file counts and sizes per extension");
+        println!("6. Exit");
+
+        let choice: usize = match read_number(&mut io::stdin().lock()) {
+            Answer::Value(choice) => choice,
+            Answer::Blank => continue,
+            Answer::Closed => return,
+        };
+
+        if choice == 1 {
+            let Some(name) = ask("Enter the name to search for: ") else { return };
+            let matches = search_everything(&avlvec, &hash_table, name.trim());
+            if matches.is_empty() {
+                println!("Nothing named {} found!", name.trim());
             }
-            Err(e) => {
-                println!("Error reading directory: {}", e);
-                continue;
+            print_paged(&matches, page_size, print_listing);
+        } else if choice == 2 {
+            let Some(pattern) = ask("Enter the glob pattern (e.g. *.log): ") else { return };
+            match search_by_glob(&avlvec, pattern.trim(), pattern.contains('/')) {
+                Ok(matches) => print_paged(&matches, page_size, print_listing),
+                Err(e) => println!("Invalid pattern: {}", e),
             }
-        }
-    }
-    //println!("AVL Tree for directory: {:?}:", path);
-    //print_avl_tree(&root, 0);
-    avlvec.push(root.clone());
-    root
-}
-
-fn insert_into_avl_tree(root: Option<Box<AVLTreeNode>>, file: FileMetadata) -> Box<AVLTreeNode> {
-    let mut root = root;
-    if let Some(node) = root {
-        let mut new_node = node.clone();
-        if file.name < node.file.as_ref().unwrap().name {
-            let left = insert_into_avl_tree(node.left, file);
-            new_node.left = Some(left);
+        } else if choice == 3 {
+            let Some(min) = ask("Enter the smallest size (e.g. 10MB) [0]: ") else { return };
+            let Some(max) = ask("Enter the largest size [no limit]: ") else { return };
+            let min = if min.trim().is_empty() { Some(0) } else { parse_size(&min) };
+            let max = if max.trim().is_empty() { Some(u64::MAX) } else { parse_size(&max) };
+            match (min, max) {
+                (Some(min), Some(max)) => print_paged(&files_in_size_range(&by_size, min, max), page_size, print_listing),
+                _ => println!("Invalid size"),
+            }
+        } else if choice == 4 {
+            for root in &roots {
+                print_directory_tree_colored(&mut io::stdout(), &avlvec, &hash_table, root, None, color).expect("Failed to write output");
+            }
+        } else if choice == 5 {
+            let histogram = extension_histogram(&by_size);
+            print_extension_histogram(&mut io::stdout(), &histogram).expect("Failed to write output");
+        } else if choice == 6 {
+            return;
         } else {
-            let right = insert_into_avl_tree(node.right, file);
-            new_node.right = Some(right);
+            println!("Invalid choice!");
         }
-        root = Some(balance_avl_tree(new_node));
-    } else {
-        root = Some(Box::new(AVLTreeNode::new(file)));
     }
-    root.unwrap()
 }
 
-fn balance_avl_tree(mut node: Box<AVLTreeNode>) -> Box<AVLTreeNode> {
-    update_height(&mut node);
-    let balance = get_height(&node.left) - get_height(&node.right);
-    if balance > 1 {
-        if get_height(&node.left.as_ref().unwrap().left) >= get_height(&node.left.as_ref().unwrap().right) {
-            node = rotate_right(node);
-        } else {
-            node.left = Some(rotate_left(node.left.unwrap()));
-            node = rotate_right(node);
-        }
-    } else if balance < -1 {
-        if get_height(&node.right.as_ref().unwrap().right) >= get_height(&node.right.as_ref().unwrap().left) {
-            node = rotate_left(node);
-        } else {
-            node.right = Some(rotate_right(node.right.unwrap()));
-            node = rotate_left(node);
+fn main() {
+
+    let diff_args: Vec<String> = std::env::args().skip_while(|arg| arg != "--diff").skip(1).take(2).collect();
+    if std::env::args().any(|arg| arg == "--diff") {
+        match diff_args.as_slice() {
+            [old, new] => print_snapshot_diff(Path::new(old), Path::new(new)),
+            _ => println!("Usage: --diff OLD.json NEW.json"),
         }
+        return;
     }
-    node
-}
-
-fn rotate_left(mut node: Box<AVLTreeNode>) -> Box<AVLTreeNode> {
-    let mut right = node.right.unwrap();
-    node.right = right.left.take();
-    update_height(&mut node);
-    right.left = Some(node);
-    update_height(&mut right);
-    right
-}
-
-fn rotate_right(mut node: Box<AVLTreeNode>) -> Box<AVLTreeNode> {
-    let mut left = node.left.unwrap();
-    node.left = left.right.take();
-    update_height(&mut node);
-    left.right = Some(node);
-    update_height(&mut left);
-    left
-}
-
-fn update_height(node: &mut Box<AVLTreeNode>) {
-    node.height = 1 + cmp::max(get_height(&node.left), get_height(&node.right));
-}
-
-
-fn get_height(node: &Option<Box<AVLTreeNode>>) -> i32 {
-    match node {
-        Some(node) => node.height,
-        None => 0,
+    // --page-size 0 turns paging off.
+    let page_size = std::env::args()
+        .skip_while(|arg| arg != "--page-size")
+        .nth(1)
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(50);
+    // --color=auto|always|never. Auto colors listings only on a terminal, and not with NO_COLOR set.
+    let color_choice = match std::env::args().find_map(|arg| arg.strip_prefix("--color=").map(String::from)) {
+        Some(value) => ColorChoice::parse(&value).unwrap_or_else(|| {
+            println!("Unknown --color value {:?}; expected auto, always or never", value);
+            ColorChoice::Auto
+        }),
+        None => ColorChoice::Auto,
+    };
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let color = color_choice.enabled(io::stdout().is_terminal(), no_color);
+    let print_listing = move |out: &mut io::Stdout, entries: &[FileMetadata]| print_entries_colored(out, entries, color);
+    if let Some(snapshot) = std::env::args().skip_while(|arg| arg != "--query").nth(1) {
+        return query_snapshot(Path::new(&snapshot), page_size, color);
     }
-}
 
-fn print_avl_tree(root: &Option<Box<AVLTreeNode>>, level: usize) {
-    if let Some(node) = root {
-        if let Some(file) = &node.file {
-            print_avl_tree(&node.right, level+5);
-            for _ in 0..level+3 {
-                print!(" ");
-            }
-            println!(
-                "Path: {:?}; {:?} Name: {} - {} bytes",
-                file.path,
-                file.file_type,
-                file.name,
-                file.size,
-            );
-            print_avl_tree(&node.left, level+5);
+    // Roots come from repeated --path flags (each may be a comma-separated list) or the prompt.
+    let mut roots = Vec::new();
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == "--path" {
+            roots.extend(parse_roots(&args.next().unwrap_or_default()));
         }
     }
-}
 
-fn search_avl_tree(root: &Option<Box<AVLTreeNode>>, file_path: PathBuf) -> Option<FileMetadata> {
-    if let Some(node) = root {
-        if let Some(file) = &node.file {
-            return if file.path == file_path {
-                Some(file.clone())
-            } else {
-                return if file.path < file_path {
-                    search_avl_tree(&node.right, file_path.clone())
-                } else {
-                    search_avl_tree(&node.left, file_path.clone())
-                }
+    let roots = loop {
+        if roots.is_empty() {
+            let mut path_input = String::new();
+            println!("Enter the path of the directory (separate several with commas): ");
+
+            let read = io::stdin()
+                .read_line(&mut path_input)
+                .expect("Failed to read line");
+            if read == 0 {
+                return;
             }
+            roots = parse_roots(&path_input);
+        }
+
+        let invalid: Vec<_> = roots.iter().filter_map(|root| validate_scan_root(root).err().map(|e| (root, e))).collect();
+        if invalid.is_empty() && !roots.is_empty() {
+            break normalize_roots(roots);
         }
+        for (root, e) in invalid {
+            println!("Can't scan {:?}: {}", root, e);
+        }
+        roots = Vec::new();
+    };
+    let parallel = std::env::args().any(|arg| arg == "--parallel");
+    let watch = std::env::args().any(|arg| arg == "--watch");
+    let show_progress = std::env::args().any(|arg| arg == "--progress");
+    // --one-file-system keeps the scan on each root's device, like `du -x`. --allocated records
+    // the disk space each entry takes up next to its apparent size.
+    let options = ScanOptions {
+        one_file_system: std::env::args().any(|arg| arg == "--one-file-system"),
+        allocated: std::env::args().any(|arg| arg == "--allocated"),
+    };
+    if options.one_file_system && !cfg!(unix) {
+        println!("--one-file-system is only supported on Unix; mount points will be scanned");
+    }
+    if options.allocated && !cfg!(unix) {
+        println!("--allocated is only supported on Unix; only apparent sizes will be shown");
     }
-    None
-}
 
-fn search_avl_by_name(root: &Option<Box<AVLTreeNode>>, file_name: String) {
-    if let Some(node) = root {
-        if let Some(file) = &node.file {
-            search_avl_by_name(&node.right, file_name.clone());
-            if file.name.to_string() == file_name {
-                println!("Path: {:?}; {:?} Name: {} - {} bytes", file.path, file.file_type, file.name, file.size,);
+    // Pointing the tool at / by mistake would index everything, so very large trees are confirmed
+    // first unless --yes was given. Counting stops just past the limit, so the check stays quick.
+    if !std::env::args().any(|arg| arg == "--yes") {
+        let estimate: usize = roots.iter().map(|root| count_entries_up_to(root, options, LARGE_SCAN_ENTRIES + 1)).sum();
+        if needs_confirmation(estimate, LARGE_SCAN_ENTRIES) {
+            println!("This looks very large (more than {} entries), continue? [y/N]: ", LARGE_SCAN_ENTRIES);
+
+            let mut answer = String::new();
+            io::stdin()
+                .read_line(&mut answer)
+                .expect("Failed to read line");
+            if !answer.trim().eq_ignore_ascii_case("y") {
+                return;
             }
-            search_avl_by_name(&node.left, file_name.clone());
         }
     }
-}
+    // --limits FILE checks the scan against per-extension size limits and exits instead of starting
+    // the menu, with status 1 when something is over. Read first, so a bad file is reported before
+    // the scan rather than after it.
+    let limits = match std::env::args().skip_while(|arg| arg != "--limits").nth(1) {
+        Some(path) => match load_limit_rules(Path::new(&expand_path(&path))) {
+            Ok(rules) => Some(rules),
+            Err(e) => {
+                println!("Failed to read limits from {}: {}", path, e);
+                return;
+            }
+        },
+        None => None,
+    };
+    // --detect-types opens every file after the scan to classify it by its first bytes.
+    let detect = std::env::args().any(|arg| arg == "--detect-types");
+    // --buckets takes a count or "auto"; without it, or when it's neither, the count is asked for
+    // until a usable answer comes back. A blank line or zero just asks again.
+    let mut buckets_input = std::env::args().skip_while(|arg| arg != "--buckets").nth(1);
+    let num_buckets: Option<usize> = loop {
+        let input = match buckets_input.take() {
+            Some(input) => input,
+            None => {
+                let mut num_buckets = String::new();
+                println!("Enter the number of buckets (or auto): ");
+
+                let read = io::stdin()
+                    .read_line(&mut num_buckets)
+                    .expect("Failed to read line");
+                if read == 0 {
+                    return;
+                }
+                num_buckets
+            }
+        };
 
-fn main() {
+        if input.trim().eq_ignore_ascii_case("auto") {
+            break None;
+        }
+        match parse_number(&input) {
+            Some(Ok(0)) => println!("The table needs at least one bucket!"),
+            Some(Ok(buckets)) => break Some(buckets),
+            Some(Err(_)) => println!("Please type a number! (got {:?})", input.trim()),
+            None => {}
+        }
+    };
+
+    // In auto mode later rebuilds are sized from the previous index instead of walking the tree again.
+    let auto_buckets = num_buckets.is_none();
+    let num_buckets: usize = match num_buckets {
+        Some(buckets) => buckets,
+        None => {
+            let directories: usize = roots.iter().map(|root| count_directories(root)).sum();
+            let buckets = auto_bucket_count(directories);
+            println!("Found {} directories, using {} buckets", directories, buckets);
+            buckets
+        }
+    };
 
-    let mut path_input = String::new();
-    println!("Enter the path of the directory: ");
+    let mut hasher_choice = String::new();
+    println!("Choose the hash function (1. FNV-1a, 2. djb2) [1]: ");
 
     io::stdin()
-        .read_line(&mut path_input)
+        .read_line(&mut hasher_choice)
         .expect("Failed to read line");
 
-    let path = PathBuf::from(path_input.trim());
+    let hasher: Option<Arc<dyn Hasher>> = match hasher_choice.trim() {
+        "2" => Some(Arc::new(Djb2Hasher)),
+        _ => None,
+    };
+
+    // Tables grow when the chosen bucket count turns out too small; the scan reports how often.
+    let new_table = |buckets: usize| {
+        let table = match &hasher {
+            Some(hasher) => HashTable::with_hasher(buckets, Arc::clone(hasher)),
+            None => HashTable::new(buckets),
+        };
+        table.growing(MAX_LOAD_FACTOR)
+    };
+
+    // Without --watch the index is rebuilt before every command; with it, it's built once and kept
+    // up to date from filesystem events.
+    let watcher = if watch {
+        match IndexWatcher::new(&roots) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                println!("Failed to watch {:?}, rebuilding on every command instead: {}", roots, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    // Ctrl-C during a scan cancels it; anywhere else it quits as usual.
+    let scanning = Arc::new(AtomicBool::new(false));
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let (scanning, cancel) = (scanning.clone(), cancel.clone());
+        if let Err(e) = ctrlc::set_handler(move || {
+            if scanning.load(Ordering::SeqCst) {
+                cancel.store(true, Ordering::SeqCst);
+            } else {
+                process::exit(130);
+            }
+        }) {
+            println!("Failed to install Ctrl-C handler: {}", e);
+        }
+    }
 
-    let mut num_buckets = String::new();
-    println!("Enter the number of buckets: ");
+    // --progress reports a running count on stderr so scripted runs reading stdout stay quiet.
+    // Returns `None` if the scan was cancelled.
+    let scan = |buckets: usize, skipped: &mut Vec<PathBuf>| {
+        cancel.store(false, Ordering::SeqCst);
+        scanning.store(true, Ordering::SeqCst);
+        // A quick counting pass first, so progress can be shown as a percentage.
+        let total: Option<usize> = show_progress.then(|| roots.iter().map(|root| count_entries(root, options)).sum());
+        let progress = if show_progress {
+            Progress::new(1000, move |count| eprint!("\rScanned {}", format_progress(count, total)))
+        } else {
+            Progress::new(usize::MAX, |_| {})
+        };
+        let progress = progress.with_cancel(cancel.clone());
+        let (mut avlvec, hash_table) = build_index_roots(&roots, new_table(buckets), parallel, skipped, Some(&progress), options);
+        scanning.store(false, Ordering::SeqCst);
+        if show_progress {
+            eprintln!("\rScanned {}", format_progress(progress.count(), total));
+        }
+        if progress.cancelled() {
+            println!("Scan cancelled");
+            return None;
+        }
+        if detect {
+            detect_types(&mut avlvec);
+        }
+        Some((avlvec, hash_table))
+    };
+    let mut skipped = Vec::new();
+    let Some((mut avlvec, mut hash_table)) = scan(num_buckets, &mut skipped) else {
+        return;
+    };
+    print_skipped(&mut io::stdout(), &skipped).expect("Failed to write output");
+    println!("{}", index_summary(&avlvec, &hash_table));
+    if hash_table.resizes() > 0 {
+        println!("Table resized {} times (final buckets: {})", hash_table.resizes(), hash_table.buckets.len());
+    }
+    if let Some(rules) = limits {
+        let violations = scan_limits(&merge_avl_trees(&avlvec), &rules);
+        if violations.is_empty() {
+            println!("No file is over its limit");
+            return;
+        }
+        println!("{} files over their limit:", violations.len());
+        print_entries_colored(&mut io::stdout(), &violations, color).expect("Failed to write output");
+        process::exit(1);
+    }
 
-    io::stdin()
-        .read_line(&mut num_buckets)
-        .expect("Failed to read line");
+    // The watcher thread keeps the shared index current; each command works on a copy of it,
+    // refreshed whenever the watcher has applied something since the last command.
+    let shared = watcher.map(|watcher| {
+        let index = SharedIndex::new(avlvec.clone(), hash_table.clone());
+        watcher.spawn(index.clone(), roots.clone());
+        index
+    });
 
-    let num_buckets: usize = num_buckets.trim().parse().expect("Please type a number!");
+    // Paths typed at a prompt are resolved against the directory navigated to with option 40, then
+    // the roots, then the working directory, since the index only holds absolute paths.
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let navigator = RefCell::new(Navigator::new(&roots));
+    let resolve = |input: &str| resolve_path(input, &navigator.borrow().bases(), &cwd);
 
     loop {
 
-        let mut avlvec = Vec::new();
-        build_avl_tree(&path, &mut avlvec);
-        let hash_table = HashTable::new(num_buckets);
-        let hash_table = build_hash_table(&path, hash_table).unwrap();
-        let mut choice = String::new();
-
         println!("Enter the number of the option you want to choose: ");
         println!("1. Search for a file");
         println!("2. Search for a directory");
@@ -383,13 +508,56 @@ fn main() {
         println!("8. Write to file");
         println!("9. Display AVL Tree");
         println!("10. Display Hash Table");
-        println!("11. Exit");
-
-        io::stdin()
-            .read_line(&mut choice)
-            .expect("Failed to read line");
-
-        let choice: usize = choice.trim().parse().expect("Please type a number!");
+        println!("11. List files by modification time");
+        println!("12. List files by extension");
+        println!("13. Display Hash Table stats");
+        println!("14. Display AVL Tree level by level");
+        println!("15. Show the k-th file in name order");
+        println!("16. Show the files before and after a name");
+        println!("17. Export CSV");
+        println!("18. Search for files by regex");
+        println!("19. Search for files by glob pattern");
+        println!("20. Find empty files and directories");
+        println!("21. Find files larger than a size");
+        println!("22. Search files and directories by name");
+        println!("23. Find file names used in more than one directory");
+        println!("24. List directories under a path");
+        println!("25. Show the size of each top-level directory");
+        println!("26. Fuzzy search for file names");
+        println!("27. List directories by file count");
+        println!("28. Show the directory tree");
+        println!("29. List files changed in the last N days");
+        println!("30. Save a snapshot of the index (JSON or binary)");
+        println!("31. Delete everything matching a glob pattern");
+        println!("32. Read a byte range from a file");
+        println!("33. Show file counts and sizes per extension");
+        println!("34. Verify the index against the disk");
+        println!("35. Read lines M to N of a file");
+        println!("36. Show the largest directories");
+        println!("37. Find files whose contents don't match their extension");
+        println!("38. Rename a directory");
+        println!("39. Export a nested JSON report");
+        println!("40. Browse the index (cd/ls)");
+        println!("41. Show the largest files");
+        println!("42. Search with combined filters");
+        println!("43. Export a size-sorted report");
+        println!("44. Rescan a folder");
+        println!("45. Exit");
+
+        // A blank line shows the menu again.
+        let choice: usize = match read_number(&mut io::stdin().lock()) {
+            Answer::Value(choice) => choice,
+            Answer::Blank => continue,
+            Answer::Closed => break,
+        };
+
+        if let Some(shared) = &shared {
+            let changes = shared.take_updates();
+            if changes > 0 {
+                (avlvec, hash_table) = shared.read(|trees, table| (trees.to_vec(), table.clone()));
+                println!("Index updated ({} changed paths).", changes);
+            }
+        }
 
         if choice == 1 {
 
@@ -404,7 +572,8 @@ fn main() {
             let file_name = PathBuf::from(file_name);
 
             for root in &avlvec {
-                search_avl_by_name(root, file_name.clone().into_os_string().into_string().unwrap());
+                search_avl_by_name(&mut io::stdout(), root, file_name.clone().into_os_string().into_string().unwrap())
+                    .expect("Failed to write output");
             }
 
         } else if choice == 2 {
@@ -418,25 +587,21 @@ fn main() {
 
             let dir_name = dir_name.trim();
             let dir_name = PathBuf::from(dir_name);
-            let mut found = false;
-
-            for root in hash_table.clone().buckets {
-                for file in root {
-                    if file.name == dir_name.to_string_lossy() {
-                        println!("Directory: {:?} - {} bytes - {} kilobytes - {} megabytes ",
-                                 file.path,
-                                 file.size,
-                                 file.size as f32 / 1024.0,
-                                 file.size as f32 / 1024.0 / 1024.0);
-                        found = true;
-                    }
-                }
-            }
+            let directories = hash_table.find_by_name(&dir_name.to_string_lossy());
 
-            if !found {
+            if directories.is_empty() {
                 println!("Directory not found!");
             }
 
+            for file in directories {
+                println!("Directory: {:?} - {} bytes - {} kilobytes - {} megabytes{}",
+                         file.path,
+                         file.size,
+                         file.size as f32 / 1024.0,
+                         file.size as f32 / 1024.0 / 1024.0,
+                         format_allocated(file));
+            }
+
         } else if choice == 3 {
 
             let mut file_name = String::new();
@@ -446,20 +611,18 @@ fn main() {
                 .read_line(&mut file_name)
                 .expect("Failed to read line");
 
-            let file_name = file_name.trim();
-            let file_name = PathBuf::from(file_name);
-            let mut found = false;
+            let file_name = resolve(&file_name);
+            let matches = search_all(&avlvec, &file_name);
 
-            for root in &avlvec {
-                if search_avl_tree(root, file_name.clone()).is_some() {
-                    fs_extra::file::remove(&file_name).expect("Failed to remove file");
-                    println!("File removed successfully!");
-                    found = true;
-                }
+            if matches.is_empty() {
+                println!("File not found!");
             }
 
-            if !found {
-                println!("File not found!");
+            for file in &matches {
+                match ops::delete_indexed_file(file) {
+                    Ok(path) => println!("File {:?} removed successfully!", path),
+                    Err(e) => println!("Failed to remove {:?}: {}", file.path, e),
+                }
             }
 
         } else if choice == 4 {
@@ -471,22 +634,20 @@ fn main() {
                 .read_line(&mut dir_name)
                 .expect("Failed to read line");
 
-            let dir_name = dir_name.trim();
-            let dir_name = PathBuf::from(dir_name);
-            let mut found = false;
+            let dir_name = resolve(&dir_name);
+            let matched = hash_table
+                .find_by_path(&dir_name)
+                .map(|file| (file.name.clone(), file.size));
 
-            for root in hash_table.clone().buckets {
-                for file in root {
-                    if file.path == dir_name {
-                        remove(dir_name.clone()).expect("Failed to remove directory");
+            match matched {
+                Some((name, size)) => match ops::delete_directory(&dir_name) {
+                    Ok(()) => {
+                        hash_table.remove(&name, &dir_name, size);
                         println!("Directory removed successfully!");
-                        found = true;
                     }
-                }
-            }
-
-            if !found {
-                println!("Directory not found!");
+                    Err(e) => println!("Failed to remove {:?}: {}", dir_name, e),
+                },
+                None => println!("Directory not found!"),
             }
 
         }else if choice == 5 {
@@ -498,10 +659,14 @@ fn main() {
                 .read_line(&mut file_name)
                 .expect("Failed to read line");
 
-            let file_name = file_name.trim();
-            let file_name = PathBuf::from(file_name);
-            File::create(file_name.clone()).expect("Failed to create file");
-            println!("File created successfully!");
+            let file_name = resolve(&file_name);
+            match ops::create_file(&file_name) {
+                Ok(()) => println!("File created successfully!"),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    println!("{:?} already exists, leaving it untouched!", file_name)
+                }
+                Err(e) => println!("Failed to create {:?}: {}", file_name, e),
+            }
 
         }else if choice == 6 {
 
@@ -512,10 +677,36 @@ fn main() {
                 .read_line(&mut dir_name)
                 .expect("Failed to read line");
 
-            let dir_name = dir_name.trim();
-            let dir_name = PathBuf::from(dir_name);
-            fs::create_dir(dir_name.clone()).expect("Failed to create directory");
-            println!("Directory created successfully!");
+            let dir_name = resolve(&dir_name);
+
+            let mut parents = String::new();
+            println!("Create missing parent directories too? (y/n) [n]: ");
+
+            io::stdin()
+                .read_line(&mut parents)
+                .expect("Failed to read line");
+
+            let created = if parents.trim().eq_ignore_ascii_case("y") {
+                ops::create_directory_all(&dir_name)
+            } else {
+                ops::create_directory(&dir_name).map(|()| vec![dir_name.clone()])
+            };
+
+            match created {
+                Ok(created) if created.is_empty() => println!("{:?} already exists!", dir_name),
+                Ok(created) => {
+                    // New directories are empty, so they go in as they are without rescanning.
+                    for dir in created {
+                        if roots.iter().any(|root| dir.starts_with(root) && dir != *root) {
+                            let metadata = fs::symlink_metadata(&dir).ok();
+                            hash_table.insert(FileMetadata::new(dir, 0, FileType::Directory, metadata.as_ref()));
+                        }
+                    }
+                    println!("Directory created successfully!");
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => println!("{:?} already exists!", dir_name),
+                Err(e) => println!("Failed to create {:?}: {}", dir_name, e),
+            }
 
         }else if choice == 7{
 
@@ -526,74 +717,805 @@ fn main() {
                 .read_line(&mut file_name)
                 .expect("Failed to read line");
 
-            let file_name = file_name.trim();
-            let file_name = PathBuf::from(file_name);
-            let mut found = false;
+            let file_name = resolve(&file_name);
 
-            for root in &avlvec {
-                if search_avl_tree(root, file_name.clone()).is_some() {
-                    found = true;
-                    let mut file = File::open(file_name.clone()).expect("Failed to open file");
-                    let mut contents = String::new();
-                    file.read_to_string(&mut contents).expect("Failed to read file");
-                    println!("File contents: {}", contents);
-                }
-            }
+            let matches = search_all(&avlvec, &file_name);
 
-            if !found {
+            if matches.is_empty() {
                 println!("File not found!");
             }
 
+            let mut hex = String::new();
+            if !matches.is_empty() {
+                println!("Show as hex? (y/N, files that aren't UTF-8 always are): ");
+
+                io::stdin()
+                    .read_line(&mut hex)
+                    .expect("Failed to read line");
+            }
+            let force_hex = hex.trim().eq_ignore_ascii_case("y");
+
+            for file in &matches {
+                match ops::read_indexed_bytes(file) {
+                    Ok(bytes) => match std::str::from_utf8(&bytes) {
+                        Ok(contents) if !force_hex => println!("File contents: {}", contents),
+                        _ => print!("{}", ops::hex_dump(&bytes)),
+                    },
+                    Err(e) => println!("Cannot read {:?}: {}", file.path, e),
+                }
+            }
+
         }else if choice == 8 {
 
             let mut file_name = String::new();
             println!("Enter the path to the file you want to write to: ");
 
+            io::stdin()
+                .read_line(&mut file_name)
+                .expect("Failed to read line");
+
+            let file_name = resolve(&file_name);
+            let matches = search_all(&avlvec, &file_name);
+
+            if matches.is_empty() {
+                println!("File not found!");
+            }
+
+            for file in matches {
+
+                if file.readonly {
+                    println!("Cannot write to {:?}: file is read-only!", file.path);
+                    continue;
+                }
+
+                let mut mode = String::new();
+                println!("Overwrite or append? (o/a) [o]: ");
+
+                io::stdin()
+                    .read_line(&mut mode)
+                    .expect("Failed to read line");
+
+                let append = mode.trim().eq_ignore_ascii_case("a");
+
+                // $EDITOR is used when it's set; otherwise the contents are read from stdin.
+                let editor = std::env::var("EDITOR").ok();
+                let new_contents = match ops::compose_in_editor(editor.as_deref()) {
+                    Ok(Some(contents)) => contents,
+                    Ok(None) => {
+                        println!("Enter the new contents of the file, then press Ctrl-D: ");
+                        ops::read_contents(io::stdin().lock()).expect("Failed to read input")
+                    }
+                    Err(e) => {
+                        println!("Failed to run the editor: {}", e);
+                        continue;
+                    }
+                };
+
+                let written = if append {
+                    ops::append_file(&file, &new_contents)
+                } else {
+                    ops::write_file(&file, &new_contents)
+                };
+                match written {
+                    Ok(()) => println!("File written to successfully!"),
+                    Err(e) => println!("Failed to write to file: {}", e),
+                }
+
+            }
+
+        }else if choice == 9 {
+            match read_sort_choice() {
+                Some((key, descending)) => {
+                    let mut files = Vec::new();
+                    for root in &avlvec {
+                        collect_avl_tree(root, &mut files);
+                    }
+                    sort_entries(&mut files, key, descending);
+                    print_paged(&files, page_size, print_listing);
+                }
+                None => {
+                    let mut lines = Vec::new();
+                    for root in &avlvec {
+                        avl_tree_lines(root, 0, &mut lines);
+                        lines.push(String::new());
+                    }
+                    print_paged(&lines, page_size, print_lines);
+                }
+            }
+        } else if choice == 10 {
+            match read_sort_choice() {
+                Some((key, descending)) => {
+                    let mut directories: Vec<FileMetadata> = hash_table.entries().cloned().collect();
+                    sort_entries(&mut directories, key, descending);
+                    print_paged(&directories, page_size, print_listing);
+                }
+                None => {
+                    print_paged(&hash_table_lines(&hash_table), page_size, print_lines);
+                    print_hash_table_summary(&mut io::stdout(), &hash_table).expect("Failed to write output");
+                }
+            }
+        } else if choice == 11 {
+            for file in files_by_modified(&avlvec) {
+                println!("{} - modified {} - created {} - {} bytes",
+                         file.path.display(),
+                         format_time(file.modified),
+                         format_time(file.created),
+                         file.size);
+            }
+        } else if choice == 12 {
+
+            let mut extension = String::new();
+            println!("Enter the extension (e.g. pdf): ");
+
+            io::stdin()
+                .read_line(&mut extension)
+                .expect("Failed to read line");
+
+            let extension = extension.trim().trim_start_matches('.');
+            let mut files = Vec::new();
+
+            for root in &avlvec {
+                search_avl_by_extension(root, extension, &mut files);
+            }
+
+            if files.is_empty() {
+                println!("No files with extension {:?} found!", extension);
+            }
+
+            for file in files {
+                println!("Path: {:?}; Name: {} - {} bytes", file.path, file.name, file.size);
+            }
+
+        } else if choice == 13 {
+            let (longest, empty, average) = hash_table.collision_stats();
+            println!("Buckets: {}", hash_table.buckets.len());
+            println!("Entries: {}", hash_table.len());
+            println!("Files in the trees: {}", avlvec.iter().map(count).sum::<usize>());
+            if let Some(tallest) = avlvec.iter().max_by_key(|root| tree_height(root)) {
+                println!(
+                    "Tallest tree: height {} for {} files (AVL bound {:.2})",
+                    tree_height(tallest),
+                    count(tallest),
+                    height_bound(count(tallest)),
+                );
+            }
+            println!("Load factor: {:.2}", hash_table.load_factor());
+            println!("Empty buckets: {}", empty);
+            println!("Longest chain: {}", longest);
+            println!("Average chain length (non-empty buckets): {:.2}", average);
+        } else if choice == 14 {
+            for root in &avlvec {
+                print_avl_tree_bfs(&mut io::stdout(), root).expect("Failed to write output");
+                println!();
+            }
+        } else if choice == 15 {
+
+            println!("Enter k (1 is the first file): ");
+
+            if let Answer::Value(k) = read_number::<usize>(&mut io::stdin().lock()) {
+                let merged = merge_avl_trees(&avlvec);
+
+                match select_kth(&merged, k) {
+                    Some(file) => println!("Path: {:?}; Name: {} - {} bytes", file.path, file.name, file.size),
+                    None => println!("There are only {} files!", count(&merged)),
+                }
+            }
+
+        } else if choice == 16 {
+
+            let mut file_name = String::new();
+            println!("Enter the name of the file: ");
+
             io::stdin()
                 .read_line(&mut file_name)
                 .expect("Failed to read line");
 
             let file_name = file_name.trim();
-            let file_name = PathBuf::from(file_name);
-            let mut found = false;
+            let merged = merge_avl_trees(&avlvec);
 
+            match predecessor(&merged, file_name) {
+                Some(file) => println!("Previous: {} ({:?})", file.name, file.path),
+                None => println!("Previous: none"),
+            }
+            match successor(&merged, file_name) {
+                Some(file) => println!("Next: {} ({:?})", file.name, file.path),
+                None => println!("Next: none"),
+            }
+
+        } else if choice == 17 {
+
+            let mut out = String::new();
+            println!("Enter the path of the CSV file to write: ");
+
+            io::stdin()
+                .read_line(&mut out)
+                .expect("Failed to read line");
+
+            let out = PathBuf::from(expand_path(out.trim()));
+
+            // Sorted by path, so exports of an unchanged directory can be diffed.
+            match export_csv(&avlvec, &hash_table, &out, true) {
+                Ok(()) => println!("Index exported to {:?}!", out),
+                Err(e) => println!("Failed to export CSV: {}", e),
+            }
+
+        } else if choice == 18 {
+
+            let mut pattern = String::new();
+            println!("Enter the regular expression to match file names against: ");
+
+            io::stdin()
+                .read_line(&mut pattern)
+                .expect("Failed to read line");
+
+            match search_by_regex(&merge_avl_trees(&avlvec), pattern.trim()) {
+                Ok(files) if files.is_empty() => println!("No matching files found!"),
+                Ok(files) => {
+                    for file in files {
+                        println!("Path: {:?}; Name: {} - {} bytes", file.path, file.name, file.size);
+                    }
+                }
+                Err(e) => println!("Invalid pattern: {}", e),
+            }
+
+        } else if choice == 19 {
+
+            let mut pattern = String::new();
+            println!("Enter the glob pattern (patterns containing '/' match the full path): ");
+
+            io::stdin()
+                .read_line(&mut pattern)
+                .expect("Failed to read line");
+
+            let pattern = pattern.trim();
+            match search_by_glob(&avlvec, pattern, pattern.contains('/')) {
+                Ok(files) if files.is_empty() => println!("No matching files found!"),
+                Ok(files) => {
+                    for file in files {
+                        println!("Path: {:?}; Name: {} - {} bytes", file.path, file.name, file.size);
+                    }
+                }
+                Err(e) => println!("Invalid pattern: {}", e),
+            }
+
+        } else if choice == 20 {
+
+            let empty = find_empty(&avlvec, &hash_table);
+
+            if empty.is_empty() {
+                println!("No empty files or directories found!");
+            } else {
+                for file in &empty {
+                    println!("{:?}: {:?}", file.file_type, file.path);
+                }
+
+                let mut answer = String::new();
+                println!("Delete these {} entries? (y/N): ", empty.len());
+
+                io::stdin()
+                    .read_line(&mut answer)
+                    .expect("Failed to read line");
+
+                if answer.trim().eq_ignore_ascii_case("y") {
+                    for file in &empty {
+                        if let Err(e) = ops::delete_empty(file) {
+                            println!("Failed to remove {:?}: {}", file.path, e);
+                        }
+                    }
+                    println!("Empty entries removed!");
+                }
+            }
+
+        } else if choice == 21 {
+
+            let mut threshold = String::new();
+            println!("Enter the minimum size (e.g. 500MB): ");
+
+            io::stdin()
+                .read_line(&mut threshold)
+                .expect("Failed to read line");
+
+            match parse_size(&threshold) {
+                Some(min_bytes) => {
+                    for file in find_larger_than(&merge_avl_trees(&avlvec), min_bytes) {
+                        println!("{} bytes - {:?}", file.size, file.path);
+                    }
+                }
+                None => println!("Invalid size: {}", threshold.trim()),
+            }
+
+        } else if choice == 22 {
+
+            let mut name = String::new();
+            println!("Enter the name to search for: ");
+
+            io::stdin()
+                .read_line(&mut name)
+                .expect("Failed to read line");
+
+            let matches = search_everything(&avlvec, &hash_table, name.trim());
+            if matches.is_empty() {
+                println!("Nothing named {} found!", name.trim());
+            }
+            print_listing(&mut io::stdout(), &matches).expect("Failed to write output");
+
+        } else if choice == 23 {
+
+            let duplicates = duplicate_names(&avlvec);
+            if duplicates.is_empty() {
+                println!("No duplicate file names found!");
+            }
+            for (name, paths) in duplicates {
+                println!("{} ({} locations):", name, paths.len());
+                for path in paths {
+                    println!("    {}", path.display());
+                }
+            }
+
+        } else if choice == 24 {
+
+            let mut prefix = String::new();
+            println!("Enter the path to list directories under: ");
+
+            io::stdin()
+                .read_line(&mut prefix)
+                .expect("Failed to read line");
+
+            let mut directories: Vec<FileMetadata> = hash_table.dirs_under(&resolve(&prefix)).into_iter().cloned().collect();
+            if directories.is_empty() {
+                println!("No directories found under {}!", prefix.trim());
+            }
+            sort_entries(&mut directories, SortKey::Path, false);
+            print_paged(&directories, page_size, print_listing);
+
+        } else if choice == 25 {
+
+            let mut files = Vec::new();
             for root in &avlvec {
-                if search_avl_tree(root, file_name.clone()).is_some() {
+                collect_avl_tree(root, &mut files);
+            }
+
+            for root in &roots {
+                let directories = top_level_sizes(&hash_table, root);
+                let loose_files: u64 = files.iter().filter(|file| file.path.parent() == Some(root.as_path())).map(|file| file.size).sum();
+                let root_total = loose_files + directories.iter().map(|file| file.size).sum::<u64>();
+                print_size_breakdown(&mut io::stdout(), root, &directories, root_total).expect("Failed to write output");
+            }
+
+        } else if choice == 26 {
 
-                    let mut new_contents = String::new();
-                    println!("Enter the new contents of the file: ");
+            let mut query = String::new();
+            println!("Enter the name to look for: ");
+
+            io::stdin()
+                .read_line(&mut query)
+                .expect("Failed to read line");
+
+            println!("How many matches to show [10]: ");
+
+            let limit = match read_number(&mut io::stdin().lock()) {
+                Answer::Value(limit) => limit,
+                Answer::Blank | Answer::Closed => 10,
+            };
+            let matches = fuzzy_search(&avlvec, query.trim(), limit);
+            if matches.is_empty() {
+                println!("No matches found!");
+            }
+            for (score, file) in matches {
+                println!("{:.2} {} ({:?})", score, file.name, file.path);
+            }
+
+        } else if choice == 27 {
+
+            let mut directories: Vec<FileMetadata> = hash_table.entries().cloned().collect();
+            if directories.is_empty() {
+                println!("No directories indexed!");
+            }
+            sort_entries(&mut directories, SortKey::Files, true);
+            print_paged(&directories, page_size, print_listing);
+
+        } else if choice == 28 {
+
+            println!("How many levels to show (leave empty for all): ");
+
+            let max_depth = match read_number(&mut io::stdin().lock()) {
+                Answer::Value(depth) => Some(depth),
+                Answer::Blank | Answer::Closed => None,
+            };
+            for root in &roots {
+                print_directory_tree_colored(&mut io::stdout(), &avlvec, &hash_table, root, max_depth, color).expect("Failed to write output");
+            }
 
-                    io::stdin()
-                        .read_line(&mut new_contents)
-                        .expect("Failed to read line");
+        } else if choice == 29 {
 
-                    fs_extra::file::write_all(file_name.clone(), new_contents.as_str()).expect("Failed to write to file");
-                    println!("File written to successfully!");
-                    found = true;
+            println!("Files changed in the last N days, N: ");
 
+            if let Answer::Value(days) = read_number::<u64>(&mut io::stdin().lock()) {
+                let since = SystemTime::now()
+                    .checked_sub(Duration::from_secs(days.saturating_mul(24 * 60 * 60)))
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                let mut files = modified_since(&merge_avl_trees(&avlvec), since);
+                if files.is_empty() {
+                    println!("No files changed in the last {} days!", days);
+                }
+                sort_entries(&mut files, SortKey::Path, false);
+                print_paged(&files, page_size, print_listing);
+            }
+
+        } else if choice == 30 {
+
+            let mut out = String::new();
+            println!("Enter the path of the snapshot to write (JSON, or binary if it ends in .bin): ");
+
+            io::stdin()
+                .read_line(&mut out)
+                .expect("Failed to read line");
+
+            let out = PathBuf::from(expand_path(out.trim()));
+            let snapshot = Snapshot::from_index(&avlvec, &hash_table).with_roots(&roots);
+            let saved = if is_binary_snapshot(&out) { snapshot.save_binary(&out) } else { snapshot.save(&out) };
+            match saved {
+                Ok(()) => println!("Snapshot saved to {:?}", out),
+                Err(e) => println!("Failed to save {:?}: {}", out, e),
+            }
+
+        } else if choice == 31 {
+
+            let mut pattern = String::new();
+            println!("Enter the glob pattern of what to delete (patterns containing '/' match the full path): ");
+
+            io::stdin()
+                .read_line(&mut pattern)
+                .expect("Failed to read line");
+
+            let mut directories = String::new();
+            println!("Delete matching directories and everything in them too? (y/N): ");
+
+            io::stdin()
+                .read_line(&mut directories)
+                .expect("Failed to read line");
+
+            let pattern = pattern.trim();
+            let allow_directories = directories.trim().eq_ignore_ascii_case("y");
+            let mut matches = match search_by_glob(&avlvec, pattern, pattern.contains('/')) {
+                Ok(matches) => matches,
+                Err(e) => {
+                    println!("Invalid pattern: {}", e);
+                    continue;
+                }
+            };
+            if allow_directories {
+                // Same pattern, so it can't fail here after succeeding above.
+                matches.extend(dirs_by_glob(&hash_table, pattern, pattern.contains('/')).unwrap_or_default());
+            }
+            sort_entries(&mut matches, SortKey::Path, false);
+
+            if matches.is_empty() {
+                println!("Nothing matches {}!", pattern);
+            } else {
+                print_listing(&mut io::stdout(), &matches).expect("Failed to write output");
+
+                let total: u64 = matches.iter().map(|file| file.size).sum();
+                let mut answer = String::new();
+                println!("Delete these {} entries ({} bytes)? (y/N): ", matches.len(), total);
+
+                io::stdin()
+                    .read_line(&mut answer)
+                    .expect("Failed to read line");
+
+                if answer.trim().eq_ignore_ascii_case("y") {
+                    let result = ops::delete_matches(&mut avlvec, &mut hash_table, &roots, &matches, allow_directories);
+                    println!("Deleted {} entries, {} failed.", result.deleted.len(), result.failed.len());
+                    for (path, e) in &result.failed {
+                        println!("Failed to remove {:?}: {}", path, e);
+                    }
                 }
             }
 
-            if !found {
+        } else if choice == 32 {
+
+            let mut file_name = String::new();
+            println!("Enter the path to the file you want to read from: ");
+
+            io::stdin()
+                .read_line(&mut file_name)
+                .expect("Failed to read line");
+
+            let file_name = resolve(&file_name);
+            let matches = search_all(&avlvec, &file_name);
+
+            if matches.is_empty() {
                 println!("File not found!");
+            } else {
+                println!("Start at byte [0]: ");
+                let offset = match read_number(&mut io::stdin().lock()) {
+                    Answer::Value(offset) => offset,
+                    Answer::Blank | Answer::Closed => 0,
+                };
+                println!("Number of bytes [256]: ");
+                let len = match read_number(&mut io::stdin().lock()) {
+                    Answer::Value(len) => len,
+                    Answer::Blank | Answer::Closed => 256,
+                };
+
+                for file in &matches {
+                    match ops::read_range(&file.path, offset, len) {
+                        Ok(bytes) if bytes.is_empty() => println!("{:?} has nothing at byte {}", file.path, offset),
+                        Ok(bytes) => print!("{}", ops::hex_dump_at(&bytes, offset)),
+                        Err(e) => println!("Cannot read {:?}: {}", file.path, e),
+                    }
+                }
             }
 
-        }else if choice == 9 {
+        } else if choice == 33 {
+
+            let histogram = extension_histogram(&merge_avl_trees(&avlvec));
+            if histogram.is_empty() {
+                println!("No files indexed!");
+            }
+            print_extension_histogram(&mut io::stdout(), &histogram).expect("Failed to write output");
+
+        } else if choice == 34 {
+
+            let missing = verify_index(&avlvec, &hash_table);
+            if missing.is_empty() {
+                println!("Every indexed entry still exists.");
+            } else {
+                println!("{} indexed entries no longer exist:", missing.len());
+                print_listing(&mut io::stdout(), &missing).expect("Failed to write output");
+
+                let mut answer = String::new();
+                println!("Remove them from the index? (y/N): ");
+
+                io::stdin()
+                    .read_line(&mut answer)
+                    .expect("Failed to read line");
+
+                if answer.trim().eq_ignore_ascii_case("y") {
+                    for file in &missing {
+                        if let Some(root) = roots.iter().find(|root| file.path.starts_with(root)) {
+                            refresh_path(&mut avlvec, &mut hash_table, root, &file.path);
+                        }
+                    }
+                    println!("Index pruned.");
+                }
+            }
+
+        } else if choice == 35 {
+
+            let mut file_name = String::new();
+            println!("Enter the path to the file you want to read from: ");
+
+            io::stdin()
+                .read_line(&mut file_name)
+                .expect("Failed to read line");
+
+            let file_name = resolve(&file_name);
+            let matches = search_all(&avlvec, &file_name);
+
+            if matches.is_empty() {
+                println!("File not found!");
+            } else {
+                println!("First line [1]: ");
+                let first = match read_number(&mut io::stdin().lock()) {
+                    Answer::Value(first) => first,
+                    Answer::Blank | Answer::Closed => 1,
+                };
+                println!("Last line [{}]: ", first + 49);
+                let last = match read_number(&mut io::stdin().lock()) {
+                    Answer::Value(last) => last,
+                    Answer::Blank | Answer::Closed => first + 49,
+                };
+
+                for file in &matches {
+                    match ops::read_lines(&file.path, first, last) {
+                        Ok(lines) if lines.is_empty() => println!("{:?} has no lines from {} to {}", file.path, first, last),
+                        Ok(lines) => {
+                            for (number, line) in lines {
+                                println!("{:>6}: {}", number, line);
+                            }
+                        }
+                        Err(e) => println!("Cannot read {:?}: {}", file.path, e),
+                    }
+                }
+            }
+
+        } else if choice == 36 {
+
+            println!("How many directories to show [10]: ");
+
+            let limit = match read_number(&mut io::stdin().lock()) {
+                Answer::Value(limit) => limit,
+                Answer::Blank | Answer::Closed => 10,
+            };
+            let mut total = 0;
             for root in &avlvec {
-                print_avl_tree(root, 0);
-                println!();
+                visit_in_order(root, &mut |file| total += file.size);
             }
-        } else if choice == 10 {
-            print_hash_table(&hash_table.clone());
-        } else if choice == 11 {
+            print_largest_directories(&mut io::stdout(), &hash_table.largest(limit), total).expect("Failed to write output");
+
+        } else if choice == 37 {
+
+            let mismatched: Vec<_> = avlvec.iter().flat_map(mismatched_types).collect();
+            if mismatched.is_empty() {
+                println!("Every file with a known extension matches its contents.");
+            }
+            for (file, detected) in mismatched {
+                println!("{} - named .{} but looks like {:?}", file.path.display(), file.extension.unwrap_or_default(), detected);
+            }
+
+        } else if choice == 38 {
+
+            let mut dir_name = String::new();
+            println!("Enter the path of the directory you want to rename: ");
+
+            io::stdin()
+                .read_line(&mut dir_name)
+                .expect("Failed to read line");
+
+            let dir_name = resolve(&dir_name);
+            if roots.contains(&dir_name) {
+                println!("{:?} is a scan root and can't be renamed here", dir_name);
+            } else if hash_table.find_by_path(&dir_name).is_none() {
+                println!("Directory not found!");
+            } else {
+                let mut new_name = String::new();
+                println!("Enter the new name: ");
+
+                io::stdin()
+                    .read_line(&mut new_name)
+                    .expect("Failed to read line");
+
+                match ops::rename_directory(&mut avlvec, &mut hash_table, &dir_name, new_name.trim()) {
+                    Ok(renamed) => println!("Directory renamed to {:?}", renamed),
+                    Err(e) => println!("Failed to rename {:?}: {}", dir_name, e),
+                }
+            }
+
+        } else if choice == 39 {
+
+            let mut out = String::new();
+            println!("Enter the path of the JSON file to write: ");
+
+            io::stdin()
+                .read_line(&mut out)
+                .expect("Failed to read line");
+
+            // One object for a single root, an array of them for several.
+            let out = PathBuf::from(expand_path(out.trim()));
+            let mut reports: Vec<_> = roots.iter().map(|root| to_nested_json(root, &avlvec, &hash_table)).collect();
+            let report = if reports.len() == 1 { reports.remove(0) } else { reports.into() };
+            let written = fs::File::create(&out).and_then(|file| {
+                let mut writer = io::BufWriter::new(file);
+                serde_json::to_writer_pretty(&mut writer, &report)?;
+                writer.flush()
+            });
+            match written {
+                Ok(()) => println!("Report written to {:?}", out),
+                Err(e) => println!("Failed to write {:?}: {}", out, e),
+            }
+
+        } else if choice == 40 {
+
+            let mut navigator = navigator.borrow_mut();
+            println!("Commands: ls, cd DIR (.. goes up, nothing goes back to the root), pwd; a blank line returns to the menu");
+            loop {
+                println!("{}> ", navigator.current().display());
+
+                let mut command = String::new();
+                let read = io::stdin()
+                    .read_line(&mut command)
+                    .expect("Failed to read line");
+
+                let command = command.trim();
+                let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+                if read == 0 || name.is_empty() {
+                    break;
+                } else if name == "ls" {
+                    let entries = navigator.ls(&avlvec, &hash_table);
+                    if entries.is_empty() {
+                        println!("(empty)");
+                    }
+                    print_paged(&entries, page_size, print_listing);
+                } else if name == "cd" {
+                    if let Err(e) = navigator.cd(argument, &hash_table) {
+                        println!("cd: {}", e);
+                    }
+                } else if name == "pwd" {
+                    println!("{}", navigator.current().display());
+                } else {
+                    println!("Unknown command {:?}", name);
+                }
+            }
+
+        } else if choice == 41 {
+
+            println!("How many files to show [20]: ");
+
+            let limit = match read_number(&mut io::stdin().lock()) {
+                Answer::Value(limit) => limit,
+                Answer::Blank | Answer::Closed => 20,
+            };
+            let largest = top_n_largest(&merge_avl_trees(&avlvec), limit);
+            if largest.is_empty() {
+                println!("No files indexed!");
+            }
+            print_paged(&largest, page_size, print_listing);
+
+        } else if choice == 42 {
+
+            let Some(query) = ask_query() else { continue };
+            let matches = match run_query(&avlvec, &hash_table, &query) {
+                Ok(matches) => matches,
+                Err(e) => {
+                    println!("Invalid pattern: {}", e);
+                    continue;
+                }
+            };
+            if matches.is_empty() {
+                println!("Nothing matches all of the filters!");
+            }
+            print_paged(&matches, page_size, print_listing);
+
+        } else if choice == 43 {
+
+            let mut out = String::new();
+            println!("Enter the path of the report file to write [show it here]: ");
+
+            io::stdin()
+                .read_line(&mut out)
+                .expect("Failed to read line");
+
+            if out.trim().is_empty() {
+                print_size_report(&mut io::stdout(), &size_report(&avlvec, &hash_table)).expect("Failed to write output");
+                continue;
+            }
+            let out = PathBuf::from(expand_path(out.trim()));
+            match export_report(&avlvec, &hash_table, &out) {
+                Ok(()) => println!("Report written to {:?}", out),
+                Err(e) => println!("Failed to write {:?}: {}", out, e),
+            }
+
+        } else if choice == 44 {
+
+            let mut dir_name = String::new();
+            println!("Enter the path of the folder to rescan: ");
+
+            io::stdin()
+                .read_line(&mut dir_name)
+                .expect("Failed to read line");
+
+            let dir_name = resolve(&dir_name);
+            if !roots.contains(&dir_name) && hash_table.find_by_path(&dir_name).is_none() {
+                println!("Directory not found!");
+                continue;
+            }
+            match &shared {
+                Some(shared) => {
+                    shared.write(|trees, table| rescan_subtree(trees, table, &dir_name));
+                    (avlvec, hash_table) = shared.read(|trees, table| (trees.to_vec(), table.clone()));
+                }
+                None => rescan_subtree(&mut avlvec, &mut hash_table, &dir_name),
+            }
+            println!("Rescanned {:?}", dir_name);
+            // Only the folder is walked again, not every root.
+            continue;
+
+        } else if choice == 45 {
             break;
         } else {
             println!("Invalid choice!");
         }
 
-        drop(avlvec);
-        drop(hash_table);
+        if shared.is_none() {
+            // A table that had to grow is rebuilt at its grown size rather than growing again.
+            let buckets = if auto_buckets { auto_bucket_count(hash_table.len()) } else { hash_table.buckets.len() };
+            // A cancelled rescan leaves the previous index in place.
+            if let Some(index) = scan(buckets, &mut Vec::new()) {
+                (avlvec, hash_table) = index;
+            }
+        }
 
     }
 
-}
\ No newline at end of file
+}
@@ -0,0 +1,392 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, Read};
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum FileType {
+    File,
+    Directory,
+    // Anything else the scan doesn't descend into: symlinks, FIFOs, sockets and device files.
+    Special,
+}
+
+impl Clone for FileType {
+    fn clone(&self) -> Self {
+        match self {
+            FileType::File => FileType::File,
+            FileType::Directory => FileType::Directory,
+            FileType::Special => FileType::Special,
+        }
+    }
+}
+
+// What a file's first bytes say it is, whatever its extension claims.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum DetectedType {
+    Png,
+    Jpeg,
+    Gif,
+    Pdf,
+    Elf,
+    Zip,
+    Text,
+    Unknown,
+}
+
+impl Clone for DetectedType {
+    fn clone(&self) -> Self {
+        match self {
+            DetectedType::Png => DetectedType::Png,
+            DetectedType::Jpeg => DetectedType::Jpeg,
+            DetectedType::Gif => DetectedType::Gif,
+            DetectedType::Pdf => DetectedType::Pdf,
+            DetectedType::Elf => DetectedType::Elf,
+            DetectedType::Zip => DetectedType::Zip,
+            DetectedType::Text => DetectedType::Text,
+            DetectedType::Unknown => DetectedType::Unknown,
+        }
+    }
+}
+
+const MAGIC_NUMBERS: [(&[u8], DetectedType); 7] = [
+    (b"\x89PNG\r\n\x1a\n", DetectedType::Png),
+    (b"\xff\xd8\xff", DetectedType::Jpeg),
+    (b"GIF87a", DetectedType::Gif),
+    (b"GIF89a", DetectedType::Gif),
+    (b"%PDF-", DetectedType::Pdf),
+    (b"\x7fELF", DetectedType::Elf),
+    (b"PK\x03\x04", DetectedType::Zip),
+];
+
+// How much of a file `detect_type` reads.
+const SNIFF_LEN: usize = 512;
+
+// Checks the start of the file against `MAGIC_NUMBERS`, and otherwise calls it text when the
+// bytes read are UTF-8 without any NULs. Empty and unreadable files are `Unknown`.
+pub fn detect_type(path: &Path) -> DetectedType {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    let read = fs::File::open(path).and_then(|file| file.take(SNIFF_LEN as u64).read_to_end(&mut head));
+    if read.is_err() || head.is_empty() {
+        return DetectedType::Unknown;
+    }
+
+    for (magic, detected) in &MAGIC_NUMBERS {
+        if head.starts_with(magic) {
+            return detected.clone();
+        }
+    }
+    // The read may stop in the middle of a multi-byte character, which doesn't make it binary.
+    let valid = match std::str::from_utf8(&head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if valid && !head.contains(&0) { DetectedType::Text } else { DetectedType::Unknown }
+}
+
+// What a file with this extension should detect as, or `None` for extensions without a mapping.
+// Office documents and Java archives are ZIP files underneath.
+pub fn expected_type(extension: &str) -> Option<DetectedType> {
+    match extension.to_lowercase().as_str() {
+        "png" => Some(DetectedType::Png),
+        "jpg" | "jpeg" => Some(DetectedType::Jpeg),
+        "gif" => Some(DetectedType::Gif),
+        "pdf" => Some(DetectedType::Pdf),
+        "so" | "o" => Some(DetectedType::Elf),
+        "zip" | "jar" | "apk" | "docx" | "xlsx" | "pptx" | "odt" | "ods" => Some(DetectedType::Zip),
+        "txt" | "md" | "csv" | "json" | "toml" | "yaml" | "yml" | "xml" | "html" | "css" | "js" | "rs" | "py" | "sh" => {
+            Some(DetectedType::Text)
+        }
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct FileMetadata {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub file_type: FileType,
+    pub modified: Option<SystemTime>,
+    pub created: Option<SystemTime>,
+    pub extension: Option<String>,
+    pub readonly: bool,
+    #[cfg(unix)]
+    pub mode: u32,
+    // Regular files anywhere below a directory; always 0 for anything else.
+    pub file_count: u64,
+    // Only filled in when the scan is asked to detect types (`--detect-types`).
+    #[serde(default)]
+    pub detected: Option<DetectedType>,
+    // Disk space actually taken up, next to the apparent `size`; only filled in when the scan is
+    // asked to measure it (`--allocated`). For directories it's the sum over everything below.
+    #[serde(default)]
+    pub allocated: Option<u64>,
+}
+
+impl FileMetadata {
+    pub fn new(path: PathBuf, size: u64, file_type: FileType, metadata: Option<&fs::Metadata>) -> Self {
+        FileMetadata {
+            name: path.file_name().map(|name| name.to_string_lossy().into()).unwrap_or_default(),
+            extension: extension_of(&path),
+            path,
+            size,
+            file_type,
+            modified: metadata.and_then(|m| m.modified().ok()),
+            created: metadata.and_then(|m| m.created().ok()),
+            readonly: metadata.map(|m| m.permissions().readonly()).unwrap_or(false),
+            #[cfg(unix)]
+            mode: metadata.map(|m| m.permissions().mode()).unwrap_or(0),
+            file_count: 0,
+            detected: None,
+            allocated: None,
+        }
+    }
+}
+
+impl Clone for FileMetadata {
+    fn clone(&self) -> Self {
+        FileMetadata {
+            name: self.name.clone(),
+            path: self.path.clone(),
+            size: self.size,
+            file_type: self.file_type.clone(),
+            modified: self.modified,
+            created: self.created,
+            extension: self.extension.clone(),
+            readonly: self.readonly,
+            #[cfg(unix)]
+            mode: self.mode,
+            file_count: self.file_count,
+            detected: self.detected.clone(),
+            allocated: self.allocated,
+        }
+    }
+}
+
+// What a file occupies on disk, `st_blocks * 512`: less than its length when it's sparse, more when
+// the last block is only partly used. Not available off Unix.
+pub fn allocated_size(metadata: &fs::Metadata) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some(metadata.blocks() * 512)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        None
+    }
+}
+
+// " (N bytes allocated)" after the apparent size when the scan measured it, otherwise nothing.
+pub fn format_allocated(file: &FileMetadata) -> String {
+    file.allocated.map(|allocated| format!(" ({} bytes allocated)", allocated)).unwrap_or_default()
+}
+
+// Counts with thousands separators: 1234567 -> "1,234,567".
+pub fn format_count(count: u64) -> String {
+    let digits = count.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+// Sizes like "512 B", "1.5 KB" or "20.0 GB", in powers of 1024 as `parse_size` reads them.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+// Windows and macOS filesystems ignore case by default, so names and paths are compared
+// case-folded there and as-is everywhere else.
+pub const CASE_INSENSITIVE_PATHS: bool = cfg!(any(windows, target_os = "macos"));
+
+// The form of a file name used for ordering and matching.
+pub fn name_key(name: &str) -> Cow<'_, str> {
+    if CASE_INSENSITIVE_PATHS {
+        Cow::Owned(name.to_lowercase())
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+// The form of a path used for ordering and matching: in `normal_path` form, "." components dropped
+// and case-folded like `name_key`. Purely lexical, so it also works for paths that don't exist
+// (unlike `canonicalize`).
+pub fn path_key(path: &Path) -> PathBuf {
+    let normalized: PathBuf = normal_path(path).components().filter(|component| *component != Component::CurDir).collect();
+    if CASE_INSENSITIVE_PATHS {
+        PathBuf::from(normalized.to_string_lossy().to_lowercase())
+    } else {
+        normalized
+    }
+}
+
+pub fn same_path(a: &Path, b: &Path) -> bool {
+    path_key(a) == path_key(b)
+}
+
+// `path` in Windows' verbatim form, `\\?\C:\...` or `\\?\UNC\server\share\...`, which isn't held to
+// MAX_PATH (260 characters). Forward slashes become backslashes, since verbatim paths are passed
+// through untouched. `None` for paths that can't be made verbatim: relative ones, ones already in
+// that form, and ones with `.` or `..`, which a verbatim path would take literally. Works on the text
+// alone, so it behaves the same on every platform.
+pub fn long_path_form(path: &str) -> Option<String> {
+    let path = path.replace('/', "\\");
+    if path.starts_with("\\\\?\\") || path.starts_with("\\\\.\\") {
+        return None;
+    }
+    if path.split('\\').any(|component| component == "." || component == "..") {
+        return None;
+    }
+    if let Some(unc) = path.strip_prefix("\\\\") {
+        return Some(format!("\\\\?\\UNC\\{}", unc));
+    }
+    let bytes = path.as_bytes();
+    if bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\' {
+        return Some(format!("\\\\?\\{}", path));
+    }
+    None
+}
+
+// The reverse of `long_path_form`: `\\?\C:\...` back to `C:\...` and `\\?\UNC\server\share\...` to
+// `\\server\share\...`. `None` for anything else, and for verbatim paths a normal one would read
+// differently: ones with `.` or `..` components, which verbatim paths take literally, or with
+// forward slashes, which they don't treat as separators.
+pub fn short_path_form(path: &str) -> Option<String> {
+    let (short, rest) = match path.strip_prefix("\\\\?\\UNC\\") {
+        Some(unc) => (format!("\\\\{}", unc), unc),
+        None => {
+            let rest = path.strip_prefix("\\\\?\\")?;
+            let bytes = rest.as_bytes();
+            if !(bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\') {
+                return None;
+            }
+            (rest.to_string(), rest)
+        }
+    };
+    if rest.contains('/') || rest.split('\\').any(|component| component == "." || component == "..") {
+        return None;
+    }
+    Some(short)
+}
+
+// `path` as the index keeps it. On Windows that's without the verbatim prefix `canonicalize` adds
+// and with backslashes throughout, so a share scanned as `\\server\share`, typed as `//server/share`
+// or reached through `\\?\UNC\server\share` compares, displays and hashes the same. Unchanged
+// elsewhere.
+pub fn normal_path(path: &Path) -> Cow<'_, Path> {
+    if cfg!(windows) {
+        if let Some(text) = path.to_str() {
+            if let Some(short) = short_path_form(text) {
+                return Cow::Owned(PathBuf::from(short));
+            }
+            if text.contains('/') && !text.starts_with("\\\\?\\") {
+                return Cow::Owned(PathBuf::from(text.replace('/', "\\")));
+            }
+        }
+    }
+    Cow::Borrowed(path)
+}
+
+// ENAMETOOLONG on Unix, ERROR_FILENAME_EXCED_RANGE on Windows.
+pub fn is_too_long(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::InvalidFilename
+}
+
+// Runs `op` on `path`. If that fails for the path being too long, Windows retries it on the
+// verbatim form from `long_path_form`; when that's not possible or fails too, the error says so
+// plainly instead of the OS's terse message. Callers name the path themselves.
+pub fn with_long_path<T>(path: &Path, op: impl Fn(&Path) -> io::Result<T>) -> io::Result<T> {
+    match op(path) {
+        Err(e) if is_too_long(&e) => {
+            #[cfg(windows)]
+            if let Some(long) = path.to_str().and_then(long_path_form) {
+                match op(Path::new(&long)) {
+                    Err(e) if is_too_long(&e) => {}
+                    result => return result,
+                }
+            }
+            Err(io::Error::new(
+                e.kind(),
+                format!("name or path too long ({} characters)", path.as_os_str().len()),
+            ))
+        }
+        result => result,
+    }
+}
+
+// Only the last extension counts ("archive.tar.gz" -> "gz"); "Makefile" and dotfiles like ".gitignore" have none.
+pub fn extension_of(path: &Path) -> Option<String> {
+    path.extension().map(|ext| ext.to_string_lossy().into())
+}
+
+// "rwxr-xr-x" style bits on Unix; elsewhere only the read-only flag is available.
+#[cfg(unix)]
+pub fn format_permissions(file: &FileMetadata) -> String {
+    let mut bits = String::with_capacity(9);
+    for shift in [6, 3, 0] {
+        let triple = (file.mode >> shift) & 0o7;
+        bits.push(if triple & 0o4 != 0 { 'r' } else { '-' });
+        bits.push(if triple & 0o2 != 0 { 'w' } else { '-' });
+        bits.push(if triple & 0o1 != 0 { 'x' } else { '-' });
+    }
+    bits
+}
+
+#[cfg(not(unix))]
+pub fn format_permissions(file: &FileMetadata) -> String {
+    String::from(if file.readonly { "read-only" } else { "read-write" })
+}
+
+// Formats a timestamp as "YYYY-MM-DD HH:MM:SS" (UTC), or "-" when the platform didn't provide one.
+pub fn format_time(time: Option<SystemTime>) -> String {
+    let secs = match time.and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+        Some(duration) => duration.as_secs(),
+        None => return String::from("-"),
+    };
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, rem / 3600, rem % 3600 / 60, rem % 60
+    )
+}
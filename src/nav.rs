@@ -0,0 +1,97 @@
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::avl::{collect_avl_tree, AVLTreeNode};
+use crate::hash_table::HashTable;
+use crate::metadata::{name_key, same_path, FileMetadata};
+use crate::scan::expand_path;
+use crate::watch::tree_directory;
+
+// The current directory of the interactive `cd`/`ls` commands. It only ever points at a scan root
+// or a directory in the table, and moves through the index alone, so it works on a snapshot too.
+#[derive(Debug, Clone)]
+pub struct Navigator {
+    roots: Vec<PathBuf>,
+    current: PathBuf,
+}
+
+impl Navigator {
+    // Starts at the first root. Roots should come from `normalize_roots`.
+    pub fn new(roots: &[PathBuf]) -> Self {
+        Navigator {
+            roots: roots.to_vec(),
+            current: roots.first().cloned().unwrap_or_default(),
+        }
+    }
+
+    pub fn current(&self) -> &Path {
+        &self.current
+    }
+
+    // The scan root the current directory is under.
+    pub fn root(&self) -> &Path {
+        self.roots.iter().find(|root| self.current.starts_with(root)).map_or(&self.current, PathBuf::as_path)
+    }
+
+    // Where relative paths typed at other prompts are looked for, first to last: the current
+    // directory, then the roots, as `resolve_path` takes them.
+    pub fn bases(&self) -> Vec<PathBuf> {
+        let mut bases = vec![self.current.clone()];
+        bases.extend(self.roots.iter().filter(|root| **root != self.current).cloned());
+        bases
+    }
+
+    // Moves to `target`: absolute, or relative to the current directory with `.` and `..` taken
+    // as in a shell. A blank target goes back to the top of the current root. Fails without moving
+    // when the target isn't a directory in the index or lies above every root.
+    pub fn cd(&mut self, target: &str, table: &HashTable) -> io::Result<()> {
+        let target = expand_path(target.trim());
+        if target.is_empty() {
+            self.current = self.root().to_path_buf();
+            return Ok(());
+        }
+
+        let mut path = if Path::new(&target).is_absolute() { PathBuf::new() } else { self.current.clone() };
+        for component in Path::new(&target).components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    path.pop();
+                }
+                other => path.push(other),
+            }
+        }
+
+        if !self.roots.iter().any(|root| path.starts_with(root)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is outside the scanned roots", path.display())));
+        }
+        if !self.roots.iter().any(|root| same_path(root, &path)) {
+            match table.find_by_path(&path) {
+                // Stored as the table has it, so later lookups match exactly.
+                Some(directory) => path = directory.path.clone(),
+                None => {
+                    return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not an indexed directory", path.display())));
+                }
+            }
+        }
+        self.current = path;
+        Ok(())
+    }
+
+    // What the current directory holds, sorted by name: subdirectories from the table and files
+    // from the directory's own tree.
+    pub fn ls(&self, avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable) -> Vec<FileMetadata> {
+        let current = self.current.as_path();
+        let mut entries: Vec<FileMetadata> = table
+            .dirs_under(current)
+            .into_iter()
+            .filter(|directory| directory.path.parent().is_some_and(|parent| same_path(parent, current)))
+            .cloned()
+            .collect();
+        for root in avlvec.iter().filter(|root| tree_directory(root).is_some_and(|dir| same_path(dir, current))) {
+            collect_avl_tree(root, &mut entries);
+        }
+        entries.sort_by(|a, b| name_key(&a.name).cmp(&name_key(&b.name)).then_with(|| a.path.cmp(&b.path)));
+        entries
+    }
+}
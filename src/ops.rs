@@ -0,0 +1,309 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use crate::avl::{for_each_file_mut, AVLTreeNode};
+use crate::hash_table::HashTable;
+use crate::metadata::{extension_of, with_long_path, FileMetadata, FileType};
+use crate::watch::refresh_path;
+
+// Opening and removing go through `with_long_path`, so paths past the platform's limit are retried
+// in long form on Windows and reported as too long rather than as a bare OS error.
+pub fn read_file(path: &Path) -> io::Result<String> {
+    let mut file = with_long_path(path, |path| File::open(path))?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+// Reads the file at the indexed path. A symlink whose target is gone is reported as a broken
+// symlink rather than a plain "not found", since the index did find the link itself.
+pub fn read_indexed_file(file: &FileMetadata) -> io::Result<String> {
+    String::from_utf8(read_indexed_bytes(file)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// The raw contents, for files that may not be text.
+pub fn read_indexed_bytes(file: &FileMetadata) -> io::Result<Vec<u8>> {
+    if fs::symlink_metadata(&file.path)?.file_type().is_symlink() && fs::metadata(&file.path).is_err() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "broken symlink"));
+    }
+    with_long_path(&file.path, |path| fs::read(path))
+}
+
+// Up to `len` bytes starting at `offset`. A range running past the end is cut short, and one
+// starting past it comes back empty.
+pub fn read_range(path: &Path, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut reader = BufReader::new(with_long_path(path, |path| File::open(path))?);
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::with_capacity(len.min(64 * 1024));
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+// Lines `first` to `last` (counting from 1, both included) with their numbers, read one at a time
+// so only those lines are kept in memory. Stops at the end of the file; empty when `first` is
+// past `last`.
+pub fn read_lines(path: &Path, first: usize, last: usize) -> io::Result<Vec<(usize, String)>> {
+    let mut lines = Vec::new();
+    if first > last {
+        return Ok(lines);
+    }
+    let reader = BufReader::new(with_long_path(path, |path| File::open(path))?);
+    for (index, line) in reader.lines().enumerate().take(last) {
+        let number = index + 1;
+        let line = line?;
+        if number >= first {
+            lines.push((number, line));
+        }
+    }
+    Ok(lines)
+}
+
+pub fn hex_dump(bytes: &[u8]) -> String {
+    hex_dump_at(bytes, 0)
+}
+
+// `hexdump -C` style: 16 bytes per line with the offset of the first, counted from `base`, and
+// the printable ASCII characters alongside ('.' for the rest).
+pub fn hex_dump_at(bytes: &[u8], base: u64) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let mut hex = String::with_capacity(49);
+        for (index, byte) in chunk.iter().enumerate() {
+            if index == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", byte));
+        }
+        let ascii: String = chunk.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }).collect();
+        dump.push_str(&format!("{:08x}  {:<49} |{}|\n", base + line as u64 * 16, hex, ascii));
+    }
+    dump
+}
+
+fn check_writable(file: &FileMetadata) -> io::Result<()> {
+    if file.readonly {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "file is read-only"));
+    }
+    Ok(())
+}
+
+// Replaces the file's contents through `write_atomically`, so an interrupted write leaves either
+// the old contents or the new ones.
+pub fn write_file(file: &FileMetadata, contents: &str) -> io::Result<()> {
+    check_writable(file)?;
+    write_atomically(&file.path, contents.as_bytes())
+}
+
+// Writes `contents` to a temporary file next to `path` and renames it over `path`, which is atomic
+// when both are on the same filesystem. The temporary file takes the target's permissions and is
+// removed again if writing or renaming fails, leaving the target untouched. When no temporary file
+// can be created there (a directory the user can't write to, say), the target is written in place.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    // A symlink is followed so the file it points at is replaced rather than the link itself.
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let name = target.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let temp = target.with_file_name(format!(".{}.{}.tmp", name, process::id()));
+
+    let mut file = match File::create_new(&temp) {
+        Ok(file) => file,
+        Err(_) => return fs::write(&target, contents),
+    };
+    let written = file.write_all(contents).and_then(|_| file.sync_all());
+    // Closed before the rename, which Windows won't do over an open file.
+    drop(file);
+    let replaced = written
+        .and_then(|_| match fs::metadata(&target) {
+            Ok(metadata) => fs::set_permissions(&temp, metadata.permissions()),
+            Err(_) => Ok(()),
+        })
+        .and_then(|_| fs::rename(&temp, &target));
+    if replaced.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    replaced
+}
+
+pub fn append_file(file: &FileMetadata, contents: &str) -> io::Result<()> {
+    check_writable(file)?;
+    with_long_path(&file.path, |path| OpenOptions::new().append(true).open(path))?.write_all(contents.as_bytes())
+}
+
+// Reads everything up to end of input (Ctrl-D on a terminal), so the contents can span lines.
+pub fn read_contents(mut input: impl Read) -> io::Result<String> {
+    let mut contents = String::new();
+    input.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+// Opens `editor` (e.g. the value of $EDITOR, which may carry arguments like "code -w") on a
+// scratch file and returns what was saved. None when no editor is configured.
+pub fn compose_in_editor(editor: Option<&str>) -> io::Result<Option<String>> {
+    let mut words = match editor {
+        Some(editor) => editor.split_whitespace(),
+        None => return Ok(None),
+    };
+    let program = match words.next() {
+        Some(program) => program,
+        None => return Ok(None),
+    };
+
+    let scratch = std::env::temp_dir().join(format!("file-management-{}.txt", process::id()));
+    File::create(&scratch)?;
+    let status = Command::new(program).args(words).arg(&scratch).status();
+    let contents = fs::read_to_string(&scratch);
+    fs::remove_file(&scratch)?;
+
+    let status = status?;
+    if !status.success() {
+        return Err(io::Error::other(format!("editor exited with {}", status)));
+    }
+    contents.map(Some)
+}
+
+// Never replaces what's already at `path`, so creating can't truncate an existing file. A missing
+// parent is reported by name rather than as the bare NotFound the open would give.
+pub fn create_file(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        if !parent.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("parent directory {} does not exist", parent.display()),
+            ));
+        }
+    }
+    with_long_path(path, |path| File::create_new(path))?;
+    Ok(())
+}
+
+pub fn create_directory(path: &Path) -> io::Result<()> {
+    with_long_path(path, |path| fs::create_dir(path))
+}
+
+// Creates `path` along with any missing parents and returns the directories that didn't exist
+// before, outermost first. An existing directory isn't an error; nothing is created for it.
+pub fn create_directory_all(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut missing: Vec<PathBuf> = path
+        .ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && fs::symlink_metadata(ancestor).is_err())
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    with_long_path(path, |path| fs::create_dir_all(path))?;
+    Ok(missing)
+}
+
+pub fn delete_file(path: &Path) -> io::Result<()> {
+    fs_extra::file::remove(path).map_err(io::Error::other)
+}
+
+// Deletes the file at the path stored in the index, not whatever was typed to find it, and only if
+// it is still a regular file. Returns the path that was removed.
+pub fn delete_indexed_file(file: &FileMetadata) -> io::Result<&Path> {
+    if file.file_type != FileType::File || !fs::symlink_metadata(&file.path)?.is_file() {
+        return Err(io::Error::other("not a regular file"));
+    }
+    with_long_path(&file.path, |path| fs::remove_file(path))?;
+    Ok(&file.path)
+}
+
+// Removes the directory and everything in it.
+pub fn delete_directory(path: &Path) -> io::Result<()> {
+    with_long_path(path, |path| fs::remove_dir_all(path))
+}
+
+// Renames the directory at `path` to `new_name` in the same parent, then moves whatever the index
+// holds below it to the new path. Only the directory's own name changes, so every tree keeps its
+// order and its paths are rewritten in place; the table is keyed by path, so its entries are taken
+// out and put back. Sizes stay the same, so the directories above need no adjusting. Returns the
+// new path.
+pub fn rename_directory(avlvec: &mut [Option<Box<AVLTreeNode>>], table: &mut HashTable, path: &Path, new_name: &str) -> io::Result<PathBuf> {
+    if Path::new(new_name).file_name() != Some(new_name.as_ref()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{:?} is not a valid directory name", new_name)));
+    }
+    if !fs::symlink_metadata(path)?.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a directory", path.display())));
+    }
+    let renamed = path.with_file_name(new_name);
+    // `fs::rename` would quietly replace an empty directory on Unix.
+    if fs::symlink_metadata(&renamed).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", renamed.display())));
+    }
+    fs::rename(path, &renamed)?;
+
+    let moved = |old: &Path| match old.strip_prefix(path) {
+        Ok(rest) if rest.as_os_str().is_empty() => Some(renamed.clone()),
+        Ok(rest) => Some(renamed.join(rest)),
+        Err(_) => None,
+    };
+    for root in avlvec.iter_mut() {
+        for_each_file_mut(root, &mut |file| {
+            if let Some(new_path) = moved(&file.path) {
+                file.path = new_path;
+            }
+        });
+    }
+    for mut directory in table.remove_under(path) {
+        if directory.path == path {
+            directory.name = new_name.to_string();
+            directory.extension = extension_of(&renamed);
+        }
+        directory.path = moved(&directory.path).unwrap_or(directory.path);
+        table.insert(directory);
+    }
+    Ok(renamed)
+}
+
+// Only removes entries that are still empty, so nothing created since the scan is lost.
+pub fn delete_empty(file: &FileMetadata) -> io::Result<()> {
+    match file.file_type {
+        FileType::File if fs::metadata(&file.path)?.len() == 0 => with_long_path(&file.path, |path| fs::remove_file(path)),
+        FileType::File => Err(io::Error::other("file is no longer empty")),
+        FileType::Directory => with_long_path(&file.path, |path| fs::remove_dir(path)),
+        FileType::Special => Err(io::Error::other("not a file or directory")),
+    }
+}
+
+// What `delete_matches` managed to remove and what it couldn't, with the reason.
+#[derive(Debug, Default)]
+pub struct BulkDelete {
+    pub deleted: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, io::Error)>,
+}
+
+// Deletes every entry in `matches` and drops each one from the index, adjusting the sizes of the
+// directories above it. Directories are refused unless `allow_directories` is set; when one is
+// deleted, matches inside it go with it rather than being reported as missing.
+pub fn delete_matches(
+    avlvec: &mut Vec<Option<Box<AVLTreeNode>>>,
+    table: &mut HashTable,
+    roots: &[PathBuf],
+    matches: &[FileMetadata],
+    allow_directories: bool,
+) -> BulkDelete {
+    let mut matches: Vec<&FileMetadata> = matches.iter().collect();
+    matches.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut result = BulkDelete::default();
+    for file in matches {
+        if result.deleted.iter().any(|deleted| file.path.starts_with(deleted)) {
+            continue;
+        }
+        let deleted = match file.file_type {
+            FileType::Directory if allow_directories => delete_directory(&file.path),
+            FileType::Directory => Err(io::Error::other("is a directory")),
+            _ => delete_indexed_file(file).map(|_| ()),
+        };
+        match deleted {
+            Ok(()) => {
+                if let Some(root) = roots.iter().find(|root| file.path.starts_with(root)) {
+                    refresh_path(avlvec, table, root, &file.path);
+                }
+                result.deleted.push(file.path.clone());
+            }
+            Err(e) => result.failed.push((file.path.clone(), e)),
+        }
+    }
+    result
+}
@@ -0,0 +1,748 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use rayon::prelude::*;
+
+use crate::avl::{count, for_each_file_mut, insert_into_avl_tree, insert_into_avl_tree_by, visit_in_order, AVLTreeNode, OrderKey};
+use crate::hash_table::HashTable;
+use crate::metadata::{
+    allocated_size, detect_type, format_count, format_size, normal_path, with_long_path, FileMetadata, FileType,
+};
+
+// Counts entries as a scan visits them and calls `callback` with the running total every
+// `every` entries. Atomic so the parallel scan can share it between worker threads. With a cancel
+// flag attached, the builders check it before each entry and stop once it's set.
+pub struct Progress {
+    every: usize,
+    count: AtomicUsize,
+    callback: Box<dyn Fn(usize) + Send + Sync>,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Progress {
+    pub fn new(every: usize, callback: impl Fn(usize) + Send + Sync + 'static) -> Self {
+        Progress {
+            every: every.max(1),
+            count: AtomicUsize::new(0),
+            callback: Box::new(callback),
+            cancel: None,
+        }
+    }
+
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::SeqCst))
+    }
+
+    // Shares the cancel flag but counts and reports nothing, for a second pass over entries the
+    // first pass already counted.
+    fn quiet(&self) -> Progress {
+        Progress {
+            every: usize::MAX,
+            count: AtomicUsize::new(0),
+            callback: Box::new(|_| {}),
+            cancel: self.cancel.clone(),
+        }
+    }
+
+    pub fn tick(&self) {
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        if count.is_multiple_of(self.every) {
+            (self.callback)(count);
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+// "42% (8400/20000)" once the total is known from `count_entries`, otherwise just the count. The
+// tree can grow between the two passes, so the percentage stops at 100.
+pub fn format_progress(count: usize, total: Option<usize>) -> String {
+    match total {
+        Some(total) if total > 0 => format!("{}% ({}/{})", (count.saturating_mul(100) / total).min(100), count, total),
+        _ => format!("{} entries", count),
+    }
+}
+
+// Number of entries a scan of `path` will visit, which is what its `Progress` ends up counting.
+// Only reads directory listings, so it's much faster than the scan itself, and skips the same
+// directories the scan does under `options`.
+pub fn count_entries(path: &Path, options: ScanOptions) -> usize {
+    count_entries_up_to(path, options, usize::MAX)
+}
+
+// `count_entries`, but gives up once it reaches `limit`, so finding out whether a tree is at least
+// that big costs no more than reading `limit` entries.
+pub fn count_entries_up_to(path: &Path, options: ScanOptions, limit: usize) -> usize {
+    count_entries_in(path, &mut Visited::starting_at(path, options), limit)
+}
+
+fn count_entries_in(path: &Path, visited: &mut Visited, limit: usize) -> usize {
+    let read_dir = match with_long_path(path, |path| fs::read_dir(path)) {
+        Ok(read_dir) => read_dir,
+        Err(_) => return 0,
+    };
+
+    let mut count = 0;
+    for entry in read_dir.flatten() {
+        if count >= limit {
+            break;
+        }
+        count += 1;
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) && visited.enter(&entry.path()).is_ok() {
+            count += count_entries_in(&entry.path(), visited, limit - count);
+        }
+    }
+    count
+}
+
+// Entry count above which an interactive scan asks before going ahead.
+pub const LARGE_SCAN_ENTRIES: usize = 250_000;
+
+// Whether a scan estimated at `estimate` entries is big enough to ask about first.
+pub fn needs_confirmation(estimate: usize, threshold: usize) -> bool {
+    estimate > threshold
+}
+
+fn is_cancelled(progress: Option<&Progress>) -> bool {
+    progress.is_some_and(Progress::cancelled)
+}
+
+// What a directory holds in total, summed bottom-up during the scan.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Totals {
+    pub size: u64,
+    // Regular files only; symlinks and other special entries add to the size but aren't counted.
+    pub files: u64,
+    // Summed whether or not it's recorded; see `ScanOptions::allocated`.
+    pub allocated: u64,
+}
+
+impl Totals {
+    // What a non-directory entry adds to the directory holding it.
+    pub(crate) fn of_entry(metadata: &fs::Metadata) -> Totals {
+        Totals {
+            size: metadata.len(),
+            files: u64::from(metadata.is_file()),
+            allocated: allocated_size(metadata).unwrap_or(0),
+        }
+    }
+
+    fn add(&mut self, other: Totals) {
+        self.size += other.size;
+        self.files += other.files;
+        self.allocated += other.allocated;
+    }
+}
+
+pub(crate) fn directory_entry(path: PathBuf, totals: Totals, metadata: Option<&fs::Metadata>, options: ScanOptions) -> FileMetadata {
+    let mut directory = FileMetadata::new(path, totals.size, FileType::Directory, metadata);
+    directory.file_count = totals.files;
+    if options.allocated && cfg!(unix) {
+        directory.allocated = Some(totals.allocated);
+    }
+    directory
+}
+
+// A non-directory entry as the trees hold it.
+pub(crate) fn file_entry(path: PathBuf, file_type: FileType, metadata: &fs::Metadata, options: ScanOptions) -> FileMetadata {
+    let mut file = FileMetadata::new(path, metadata.len(), file_type, Some(metadata));
+    if options.allocated {
+        file.allocated = allocated_size(metadata);
+    }
+    file
+}
+
+// Identifies a directory however it was reached. On Unix that's the device and inode, which also
+// catches a directory showing up under a second path (a bind mount or a hardlinked directory),
+// something comparing canonical paths can't see; elsewhere it's the canonical path.
+#[cfg(unix)]
+type DirectoryId = (u64, u64);
+#[cfg(not(unix))]
+type DirectoryId = PathBuf;
+
+fn directory_id(path: &Path) -> Option<DirectoryId> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        fs::metadata(path).ok().map(|metadata| (metadata.dev(), metadata.ino()))
+    }
+    #[cfg(not(unix))]
+    {
+        fs::canonicalize(path).ok()
+    }
+}
+
+// The device a path is on, for `--one-file-system`. Always `None` off Unix, where that option
+// does nothing.
+pub fn device_id(path: &Path) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        fs::metadata(path).ok().map(|metadata| metadata.dev())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+// Whether a directory on `device` belongs to a scan rooted on `root_device`. A device that can't be
+// read is given the benefit of the doubt, as unidentifiable directories are below.
+pub fn same_device(root_device: u64, device: Option<u64>) -> bool {
+    device.is_none_or(|device| device == root_device)
+}
+
+// Settings that change which directories a scan walks into and what it records about them. The
+// default walks everything reachable and records apparent sizes only.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScanOptions {
+    // Stay on the scan root's device, like `du -x`, so mounts such as /proc are left out.
+    pub one_file_system: bool,
+    // Record allocated sizes (`FileMetadata::allocated`) as well. Unix only.
+    pub allocated: bool,
+}
+
+// What a traversal remembers about the directories it has entered, and the options it runs with.
+struct Visited {
+    ids: HashSet<DirectoryId>,
+    // The root's device, when the scan has to stay on it.
+    device: Option<u64>,
+    options: ScanOptions,
+}
+
+impl Visited {
+    // A traversal of `root`, which counts as entered.
+    fn starting_at(root: &Path, options: ScanOptions) -> Visited {
+        let mut visited = Visited {
+            ids: HashSet::new(),
+            device: if options.one_file_system { device_id(root) } else { None },
+            options,
+        };
+        if let Some(id) = directory_id(root) {
+            visited.ids.insert(id);
+        }
+        visited
+    }
+
+    // Records `path` as entered, or says why it shouldn't be.
+    fn enter(&mut self, path: &Path) -> Result<(), &'static str> {
+        if self.device.is_some_and(|device| !same_device(device, device_id(path))) {
+            return Err("on another filesystem");
+        }
+        match directory_id(path) {
+            Some(id) if !self.ids.insert(id) => Err("already scanned under another path"),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Records `path` as scanned. Returns false when it already was, so a filesystem loop is walked
+// once and its sizes aren't counted twice, or when it's on a device the scan stays off.
+// Directories that can't be identified are always walked.
+fn first_visit(path: &Path, visited: &mut Visited) -> bool {
+    match visited.enter(path) {
+        Ok(()) => true,
+        Err(reason) => {
+            println!("Skipping {}: {}", path.display(), reason);
+            false
+        }
+    }
+}
+
+pub fn build_hash_table(path: &Path, mut hash_table: HashTable, skipped: &mut Vec<PathBuf>, progress: Option<&Progress>) -> Option<HashTable>{
+    index_directory(path, &mut hash_table, skipped, progress)?;
+    Some(hash_table)
+}
+
+// Inserts every directory below `path` and returns the totals of `path`. Sizes are summed
+// bottom-up from the children, so each entry is visited once instead of once per ancestor; the
+// totals match `fs_extra::dir::get_size` (every non-directory entry's length, recursively).
+// Entries that can't be read are logged, added to `skipped` and left out of the totals.
+pub fn index_directory(path: &Path, hash_table: &mut HashTable, skipped: &mut Vec<PathBuf>, progress: Option<&Progress>) -> Option<Totals> {
+    index_directory_with(path, hash_table, skipped, progress, ScanOptions::default())
+}
+
+pub(crate) fn index_directory_with(path: &Path, hash_table: &mut HashTable, skipped: &mut Vec<PathBuf>, progress: Option<&Progress>, options: ScanOptions) -> Option<Totals> {
+    index_directory_in(path, hash_table, skipped, progress, &mut Visited::starting_at(path, options))
+}
+
+fn index_directory_in(path: &Path, hash_table: &mut HashTable, skipped: &mut Vec<PathBuf>, progress: Option<&Progress>, visited: &mut Visited) -> Option<Totals> {
+    let read_dir = match with_long_path(path, |path| fs::read_dir(path)) {
+        Ok(read_dir) => read_dir,
+        Err(e) => {
+            println!("Skipping {}: {}", path.display(), e);
+            skipped.push(path.to_path_buf());
+            return None;
+        }
+    };
+    let mut total = Totals::default();
+
+    for entry in read_dir {
+        if is_cancelled(progress) {
+            return None;
+        }
+        match entry {
+            Ok(entry) => {
+                if let Some(progress) = progress {
+                    progress.tick();
+                }
+                let file_type = match entry.file_type() {
+                    Ok(file_type) => file_type,
+                    Err(e) => {
+                        println!("Skipping {}: {}", entry.path().display(), e);
+                        skipped.push(entry.path());
+                        continue;
+                    }
+                };
+
+                if file_type.is_dir() {
+                    if !first_visit(&entry.path(), visited) {
+                        continue;
+                    }
+                    if let Some(totals) = index_directory_in(&entry.path(), hash_table, skipped, progress, visited) {
+                        let metadata = entry.metadata().ok();
+                        hash_table.insert(directory_entry(entry.path(), totals, metadata.as_ref(), visited.options));
+                        total.add(totals);
+                    }
+                } else {
+                    match entry.metadata() {
+                        Ok(metadata) => total.add(Totals::of_entry(&metadata)),
+                        Err(e) => {
+                            println!("Skipping {}: {}", entry.path().display(), e);
+                            skipped.push(entry.path());
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                println!("Error reading directory: {}", e);
+                continue;
+            }
+        }
+    }
+    Some(total)
+}
+
+pub fn build_avl_tree(path: &Path, avlvec: &mut Vec<Option<Box<AVLTreeNode>>>, skipped: &mut Vec<PathBuf>, progress: Option<&Progress>) -> Option<Box<AVLTreeNode>> {
+    build_avl_tree_by(path, avlvec, skipped, progress, OrderKey::Name)
+}
+
+pub(crate) fn build_avl_tree_with(path: &Path, avlvec: &mut Vec<Option<Box<AVLTreeNode>>>, skipped: &mut Vec<PathBuf>, progress: Option<&Progress>, options: ScanOptions) -> Option<Box<AVLTreeNode>> {
+    build_avl_tree_in(path, avlvec, skipped, progress, OrderKey::Name, &mut Visited::starting_at(path, options))
+}
+
+// `build_avl_tree` with the trees ordered by `key` instead of by name.
+pub fn build_avl_tree_by(path: &Path, avlvec: &mut Vec<Option<Box<AVLTreeNode>>>, skipped: &mut Vec<PathBuf>, progress: Option<&Progress>, key: OrderKey) -> Option<Box<AVLTreeNode>> {
+    build_avl_tree_in(path, avlvec, skipped, progress, key, &mut Visited::starting_at(path, ScanOptions::default()))
+}
+
+fn build_avl_tree_in(path: &Path, avlvec: &mut Vec<Option<Box<AVLTreeNode>>>, skipped: &mut Vec<PathBuf>, progress: Option<&Progress>, key: OrderKey, visited: &mut Visited) -> Option<Box<AVLTreeNode>> {
+    let mut root = None;
+
+    let read_dir = match with_long_path(path, |path| fs::read_dir(path)) {
+        Ok(read_dir) => read_dir,
+        Err(e) => {
+            println!("Skipping {}: {}", path.display(), e);
+            skipped.push(path.to_path_buf());
+            return None;
+        }
+    };
+
+    for entry in read_dir {
+        if is_cancelled(progress) {
+            return None;
+        }
+        match entry {
+            Ok(entry) => {
+                if let Some(progress) = progress {
+                    progress.tick();
+                }
+                let (file_type, metadata) = match (entry.file_type(), entry.metadata()) {
+                    (Ok(file_type), Ok(metadata)) => (file_type, metadata),
+                    (Err(e), _) | (_, Err(e)) => {
+                        println!("Skipping {}: {}", entry.path().display(), e);
+                        skipped.push(entry.path());
+                        continue;
+                    }
+                };
+
+                if file_type.is_file() {
+                    let file_metadata = file_entry(entry.path(), FileType::File, &metadata, visited.options);
+                    root = Some(insert_into_avl_tree_by(root, file_metadata, key));
+                } else if file_type.is_dir() {
+                    if first_visit(&entry.path(), visited) {
+                        build_avl_tree_in(&entry.path(), avlvec, skipped, progress, key, visited);
+                    }
+                }
+                else{
+                    let file_metadata = file_entry(entry.path(), FileType::Special, &metadata, visited.options);
+                    root = Some(insert_into_avl_tree_by(root, file_metadata, key));
+                }
+            }
+            Err(e) => {
+                println!("Error reading directory: {}", e);
+                continue;
+            }
+        }
+    }
+    //println!("AVL Tree for directory: {:?}:", path);
+    //print_avl_tree(&root, 0);
+    // Directories without files get no tree, so every entry in `avlvec` is `Some`.
+    if root.is_some() {
+        avlvec.push(root.clone());
+    }
+    root
+}
+
+// "Indexed 1,234 files and 56 directories totaling 9.8 GB." Files are everything in the trees and
+// directories everything in the table; the total is what the files add up to.
+pub fn index_summary(avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable) -> String {
+    let files: usize = avlvec.iter().map(count).sum();
+    let mut total = 0;
+    for root in avlvec {
+        visit_in_order(root, &mut |file| total += file.size);
+    }
+    let plural = |n: usize, one: &str, many: &str| format!("{} {}", format_count(n as u64), if n == 1 { one } else { many });
+    format!(
+        "Indexed {} and {} totaling {}.",
+        plural(files, "file", "files"),
+        plural(table.len(), "directory", "directories"),
+        format_size(total),
+    )
+}
+
+pub fn print_skipped(out: &mut impl Write, skipped: &[PathBuf]) -> io::Result<()> {
+    if skipped.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "Skipped {} unreadable path(s):", skipped.len())?;
+    for path in skipped {
+        writeln!(out, "  {}", path.display())?;
+    }
+    Ok(())
+}
+
+// Checks that `path` can be scanned: it has to exist and be a directory (symlinks to one are followed).
+pub fn validate_scan_root(path: &Path) -> io::Result<()> {
+    let metadata = with_long_path(path, |path| fs::metadata(path))?;
+    if !metadata.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotADirectory, "not a directory"));
+    }
+    with_long_path(path, |path| fs::read_dir(path))?;
+    Ok(())
+}
+
+// Splits a comma-separated list of directories ("/data1,/data2") as accepted at the prompt.
+pub fn parse_roots(input: &str) -> Vec<PathBuf> {
+    input.split(',').map(str::trim).filter(|root| !root.is_empty()).map(|root| PathBuf::from(expand_path(root))).collect()
+}
+
+// Expands what a shell would in a typed path: a leading `~` or `~/` becomes the home directory,
+// and `$VAR`, `${VAR}` and `%VAR%` become the variable's value. `~user` and variables that aren't
+// set are left as typed, so the error that follows names what couldn't be found.
+pub fn expand_path(input: &str) -> String {
+    expand_path_with(input, std::env::home_dir().as_deref(), &|name| std::env::var(name).ok())
+}
+
+// `expand_path` with the home directory and variables supplied by the caller.
+pub fn expand_path_with(input: &str, home: Option<&Path>, var: &dyn Fn(&str) -> Option<String>) -> String {
+    let input = match (input.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', std::path::MAIN_SEPARATOR]) => {
+            format!("{}{}", home.display(), rest)
+        }
+        _ => input.to_string(),
+    };
+
+    let mut expanded = String::new();
+    let mut rest = input.as_str();
+    while let Some(start) = rest.find(['$', '%']) {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, consumed) = if rest[start..].starts_with('%') {
+            match after.find('%') {
+                Some(end) => (&after[..end], end + 1),
+                None => ("", 0),
+            }
+        } else if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            }
+        } else {
+            let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+            (&after[..end], end)
+        };
+
+        match (!name.is_empty()).then(|| var(name)).flatten() {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&rest[start..start + 1 + consumed]),
+        }
+        rest = &after[consumed..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+// Every indexed path is the root joined with the names below it, so resolving the root once makes
+// all of them absolute. Falls back to a lexically absolute path when the root can't be resolved
+// (e.g. a broken symlink), and to the path as given if even the current directory is unknown.
+pub fn canonical_root(path: &Path) -> PathBuf {
+    let path = fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    normal_path(&path).into_owned()
+}
+
+// Resolves every root with `canonical_root`, so entries from different roots can't share a path,
+// and drops duplicates and roots nested inside another root, whose entries would be indexed twice.
+pub fn normalize_roots(roots: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut absolute: Vec<PathBuf> = roots.iter().map(|root| canonical_root(root)).collect();
+    absolute.sort();
+
+    let mut normalized: Vec<PathBuf> = Vec::new();
+    for root in absolute {
+        if !normalized.iter().any(|kept| root.starts_with(kept)) {
+            normalized.push(root);
+        }
+    }
+    normalized
+}
+
+// Turns a path typed at a prompt into the absolute form the index stores, so every handler looks
+// up and changes the same entry whichever way it was typed. Once `~` and variables are expanded,
+// absolute input is kept; relative input is taken against the first scan root it exists under,
+// then `cwd`, and when it exists nowhere (something about to be created) against the first root.
+// The parent is resolved the way roots are, so `..` and symlinked directories line up with stored
+// paths, but the last component is kept as typed so a symlink is found as itself rather than as
+// its target.
+pub fn resolve_path(input: &str, roots: &[PathBuf], cwd: &Path) -> PathBuf {
+    let input = expand_path(input.trim());
+    let input = Path::new(&input);
+    let path = if input.is_absolute() {
+        input.to_path_buf()
+    } else {
+        roots
+            .iter()
+            .map(PathBuf::as_path)
+            .chain(std::iter::once(cwd))
+            .map(|base| base.join(input))
+            .find(|candidate| fs::symlink_metadata(candidate).is_ok())
+            .unwrap_or_else(|| roots.first().map_or(cwd, PathBuf::as_path).join(input))
+    };
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => canonical_root(parent).join(name),
+        _ => canonical_root(&path),
+    }
+}
+
+// Opens every regular file in the trees to fill in `detected`. Kept out of the scan itself since
+// reading each file is far slower than reading its metadata.
+pub fn detect_types(avlvec: &mut [Option<Box<AVLTreeNode>>]) {
+    for root in avlvec {
+        for_each_file_mut(root, &mut |file| {
+            if file.file_type == FileType::File {
+                file.detected = Some(detect_type(&file.path));
+            }
+        });
+    }
+}
+
+// Counts the directories below `path` without reading any metadata, for sizing the table up front.
+pub fn count_directories(path: &Path) -> usize {
+    let read_dir = match with_long_path(path, |path| fs::read_dir(path)) {
+        Ok(read_dir) => read_dir,
+        Err(_) => return 0,
+    };
+
+    let mut count = 0;
+    for entry in read_dir.flatten() {
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            count += 1 + count_directories(&entry.path());
+        }
+    }
+    count
+}
+
+// Smallest power of two that keeps the load factor at or below 0.75 for `directories` entries.
+pub fn auto_bucket_count(directories: usize) -> usize {
+    directories.saturating_mul(4).div_ceil(3).next_power_of_two()
+}
+
+// Listings gathered by the worker threads of a parallel scan, merged into the index afterwards.
+struct ParallelScan {
+    // One (directory, entries) pair per visited directory, in the order each directory was read.
+    listings: Mutex<Vec<(PathBuf, Vec<FileMetadata>)>>,
+    directories: Mutex<Vec<FileMetadata>>,
+    skipped: Mutex<Vec<PathBuf>>,
+    visited: Mutex<Visited>,
+    options: ScanOptions,
+}
+
+// Returns the totals of `path`, summed bottom-up like `index_directory`.
+fn collect_parallel(path: &Path, scan: &ParallelScan, progress: Option<&Progress>) -> Option<Totals> {
+    let read_dir = match with_long_path(path, |path| fs::read_dir(path)) {
+        Ok(read_dir) => read_dir,
+        Err(e) => {
+            println!("Skipping {}: {}", path.display(), e);
+            scan.skipped.lock().unwrap().push(path.to_path_buf());
+            return None;
+        }
+    };
+
+    let mut files = Vec::new();
+    let mut subdirs = Vec::new();
+    let mut total = Totals::default();
+
+    for entry in read_dir {
+        if is_cancelled(progress) {
+            return None;
+        }
+        match entry {
+            Ok(entry) => {
+                if let Some(progress) = progress {
+                    progress.tick();
+                }
+                let (file_type, metadata) = match (entry.file_type(), entry.metadata()) {
+                    (Ok(file_type), Ok(metadata)) => (file_type, metadata),
+                    (Err(e), _) | (_, Err(e)) => {
+                        println!("Skipping {}: {}", entry.path().display(), e);
+                        scan.skipped.lock().unwrap().push(entry.path());
+                        continue;
+                    }
+                };
+
+                if file_type.is_file() {
+                    total.add(Totals::of_entry(&metadata));
+                    files.push(file_entry(entry.path(), FileType::File, &metadata, scan.options));
+                } else if file_type.is_dir() {
+                    if first_visit(&entry.path(), &mut scan.visited.lock().unwrap()) {
+                        subdirs.push(entry.path());
+                    }
+                } else {
+                    total.add(Totals::of_entry(&metadata));
+                    files.push(file_entry(entry.path(), FileType::Special, &metadata, scan.options));
+                }
+            }
+            Err(e) => {
+                println!("Error reading directory: {}", e);
+                continue;
+            }
+        }
+    }
+
+    let subtotals: Vec<Totals> = subdirs
+        .par_iter()
+        .filter_map(|subdir| {
+            let totals = collect_parallel(subdir, scan, progress)?;
+            let metadata = fs::symlink_metadata(subdir).ok();
+            scan.directories.lock().unwrap().push(directory_entry(subdir.clone(), totals, metadata.as_ref(), scan.options));
+            Some(totals)
+        })
+        .collect();
+    for totals in subtotals {
+        total.add(totals);
+    }
+
+    scan.listings.lock().unwrap().push((path.to_path_buf(), files));
+    Some(total)
+}
+
+// Same result as `build_avl_tree` + `build_hash_table`, but subdirectories are read on the rayon
+// thread pool. Only the collection is parallel; the trees and the table are built afterwards on
+// the calling thread, so the structures themselves need no locking.
+pub fn build_index_parallel(path: &Path, hash_table: HashTable, skipped: &mut Vec<PathBuf>, progress: Option<&Progress>) -> (Vec<Option<Box<AVLTreeNode>>>, HashTable) {
+    build_index_parallel_in(path, hash_table, skipped, progress, Visited::starting_at(path, ScanOptions::default()))
+}
+
+fn build_index_parallel_in(path: &Path, mut hash_table: HashTable, skipped: &mut Vec<PathBuf>, progress: Option<&Progress>, visited: Visited) -> (Vec<Option<Box<AVLTreeNode>>>, HashTable) {
+    let scan = ParallelScan {
+        options: visited.options,
+        listings: Mutex::new(Vec::new()),
+        directories: Mutex::new(Vec::new()),
+        skipped: Mutex::new(Vec::new()),
+        visited: Mutex::new(visited),
+    };
+    collect_parallel(path, &scan, progress);
+    if is_cancelled(progress) {
+        return (Vec::new(), hash_table);
+    }
+
+    let mut avlvec = Vec::new();
+    for (_, files) in scan.listings.into_inner().unwrap() {
+        let mut root = None;
+        for file in files {
+            root = Some(insert_into_avl_tree(root, file));
+        }
+        if root.is_some() {
+            avlvec.push(root);
+        }
+    }
+
+    for directory in scan.directories.into_inner().unwrap() {
+        hash_table.insert(directory);
+    }
+    skipped.extend(scan.skipped.into_inner().unwrap());
+    (avlvec, hash_table)
+}
+
+// Paths that couldn't be read are collected in `skipped`, sorted and without duplicates (the
+// sequential scan walks the tree twice and would otherwise report them twice). `progress` ticks
+// once per entry; the sequential scan only counts its first walk. Paths in the index are absolute
+// whatever form `path` was given in. If the scan is cancelled, whatever was gathered so far is
+// dropped and the index comes back empty; callers check `progress.cancelled()` to tell the two apart.
+pub fn build_index(path: &Path, hash_table: HashTable, parallel: bool, skipped: &mut Vec<PathBuf>, progress: Option<&Progress>) -> (Vec<Option<Box<AVLTreeNode>>>, HashTable) {
+    build_index_with(path, hash_table, parallel, skipped, progress, ScanOptions::default())
+}
+
+pub fn build_index_with(path: &Path, mut hash_table: HashTable, parallel: bool, skipped: &mut Vec<PathBuf>, progress: Option<&Progress>, options: ScanOptions) -> (Vec<Option<Box<AVLTreeNode>>>, HashTable) {
+    let path = &canonical_root(path);
+    let (mut avlvec, mut hash_table) = if parallel {
+        build_index_parallel_in(path, hash_table, skipped, progress, Visited::starting_at(path, options))
+    } else {
+        let mut avlvec = Vec::new();
+        build_avl_tree_in(path, &mut avlvec, skipped, progress, OrderKey::Name, &mut Visited::starting_at(path, options));
+        if !is_cancelled(progress) {
+            let quiet = progress.map(Progress::quiet);
+            index_directory_in(path, &mut hash_table, skipped, quiet.as_ref(), &mut Visited::starting_at(path, options));
+        }
+        (avlvec, hash_table)
+    };
+    if is_cancelled(progress) {
+        avlvec.clear();
+        hash_table.clear();
+    }
+    skipped.sort();
+    skipped.dedup();
+    (avlvec, hash_table)
+}
+
+// One combined index over several roots. Roots should come from `normalize_roots`.
+pub fn build_index_roots(roots: &[PathBuf], mut hash_table: HashTable, parallel: bool, skipped: &mut Vec<PathBuf>, progress: Option<&Progress>, options: ScanOptions) -> (Vec<Option<Box<AVLTreeNode>>>, HashTable) {
+    let mut avlvec = Vec::new();
+    for root in roots {
+        let (trees, table) = build_index_with(root, hash_table, parallel, skipped, progress, options);
+        avlvec.extend(trees);
+        hash_table = table;
+        if is_cancelled(progress) {
+            // Earlier roots are already in the index; drop them too.
+            avlvec.clear();
+            break;
+        }
+    }
+    skipped.sort();
+    skipped.dedup();
+    (avlvec, hash_table)
+}
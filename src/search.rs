@@ -0,0 +1,623 @@
+use std::cmp;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fs;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::avl::{collect_avl_tree, merge_avl_trees, search_avl_tree, visit_in_order, AVLTreeNode};
+use crate::color::paint;
+use crate::hash_table::HashTable;
+use crate::metadata::{detect_type, expected_type, format_allocated, format_time, name_key, DetectedType, FileMetadata, FileType};
+
+// Per-directory trees are stored separately, so a path lookup has to ask every root.
+pub fn search_all(avlvec: &[Option<Box<AVLTreeNode>>], path: &Path) -> Vec<FileMetadata> {
+    let mut matches = Vec::new();
+    for root in avlvec {
+        if let Some(file) = search_avl_tree(root, path.to_path_buf()) {
+            matches.push(file);
+        }
+    }
+    matches
+}
+
+// Writes each match to `out` as it is found, like the other printers here. Stops writing at the
+// first error and returns it.
+pub fn search_avl_by_name(out: &mut impl Write, root: &Option<Box<AVLTreeNode>>, file_name: String) -> io::Result<()> {
+    let name = name_key(&file_name);
+    let mut result = Ok(());
+    visit_in_order(root, &mut |file| {
+        if result.is_ok() && name_key(&file.name) == name {
+            result = writeln!(out, "Path: {:?}; {:?} Name: {} - {} bytes - modified {} - created {}", file.path, file.file_type, file.name, file.size, format_time(file.modified), format_time(file.created));
+        }
+    });
+    result
+}
+
+pub fn search_avl_by_extension(root: &Option<Box<AVLTreeNode>>, extension: &str, files: &mut Vec<FileMetadata>) {
+    visit_in_order(root, &mut |file| {
+        if file.extension.as_ref().is_some_and(|ext| ext.eq_ignore_ascii_case(extension)) {
+            files.push(file.clone());
+        }
+    });
+}
+
+pub fn search_by_regex(root: &Option<Box<AVLTreeNode>>, pattern: &str) -> Result<Vec<FileMetadata>, regex::Error> {
+    let regex = regex::Regex::new(pattern)?;
+    let mut files = Vec::new();
+    visit_in_order(root, &mut |file| {
+        if regex.is_match(&file.name) {
+            files.push(file.clone());
+        }
+    });
+    Ok(files)
+}
+
+// With `match_path` false the pattern is matched against base names only ("*.rs"). With it set the
+// pattern is matched against the full path, `*` doesn't cross '/', and a relative pattern may
+// start at any directory ("src/**/mod.rs" matches "/home/me/project/src/a/mod.rs"). A pattern
+// that doesn't parse is handed back as the error, as `search_by_regex` does.
+pub fn search_by_glob(avlvec: &[Option<Box<AVLTreeNode>>], pattern: &str, match_path: bool) -> Result<Vec<FileMetadata>, globset::Error> {
+    let mut files = Vec::new();
+    for root in avlvec {
+        collect_avl_tree(root, &mut files);
+    }
+    retain_glob_matches(files, pattern, match_path)
+}
+
+// Directories from the table matching `pattern`, with the same rules as `search_by_glob`.
+pub fn dirs_by_glob(table: &HashTable, pattern: &str, match_path: bool) -> Result<Vec<FileMetadata>, globset::Error> {
+    retain_glob_matches(table.entries().cloned().collect(), pattern, match_path)
+}
+
+fn retain_glob_matches(mut files: Vec<FileMetadata>, pattern: &str, match_path: bool) -> Result<Vec<FileMetadata>, globset::Error> {
+    let pattern = if match_path && !pattern.starts_with('/') && !pattern.starts_with("**") {
+        format!("**/{}", pattern)
+    } else {
+        pattern.to_string()
+    };
+    let matcher = globset::GlobBuilder::new(&pattern).literal_separator(match_path).build()?.compile_matcher();
+
+    files.retain(|file| {
+        if match_path {
+            matcher.is_match(&file.path)
+        } else {
+            matcher.is_match(&file.name)
+        }
+    });
+    Ok(files)
+}
+
+// Filters for `run_query`; only the ones set are applied, and an entry has to pass all of them.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    // Glob on the base name, or on the full path when it contains '/' (as `search_by_glob`).
+    pub name: Option<String>,
+    // Inclusive bounds in bytes.
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub file_type: Option<FileType>,
+    // Compared ignoring ASCII case, without the dot.
+    pub extension: Option<String>,
+}
+
+impl Query {
+    fn matches(&self, file: &FileMetadata) -> bool {
+        self.min_size.is_none_or(|min| file.size >= min)
+            && self.max_size.is_none_or(|max| file.size <= max)
+            && self.file_type.as_ref().is_none_or(|file_type| file.file_type == *file_type)
+            && self.extension.as_ref().is_none_or(|extension| {
+                file.extension.as_ref().is_some_and(|ext| ext.eq_ignore_ascii_case(extension.trim_start_matches('.')))
+            })
+    }
+}
+
+// Everything in the index passing every filter `query` sets, sorted by path. Directories come
+// from the table and are only considered when the query doesn't ask for another type.
+pub fn run_query(avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable, query: &Query) -> Result<Vec<FileMetadata>, globset::Error> {
+    let mut entries = Vec::new();
+    for root in avlvec {
+        visit_in_order(root, &mut |file| {
+            if query.matches(file) {
+                entries.push(file.clone());
+            }
+        });
+    }
+    if query.file_type.as_ref().is_none_or(|file_type| *file_type == FileType::Directory) {
+        entries.extend(table.entries().filter(|directory| query.matches(directory)).cloned());
+    }
+    if let Some(pattern) = &query.name {
+        entries = retain_glob_matches(entries, pattern, pattern.contains('/'))?;
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+// Files at or above `min_bytes`, largest first.
+pub fn find_larger_than(root: &Option<Box<AVLTreeNode>>, min_bytes: u64) -> Vec<FileMetadata> {
+    let mut files = Vec::new();
+    visit_in_order(root, &mut |file| {
+        if file.size >= min_bytes {
+            files.push(file.clone());
+        }
+    });
+    files.sort_by_key(|file| cmp::Reverse(file.size));
+    files
+}
+
+// Orders files by size, with the smaller path winning a tie, so the "largest" of equal sizes is
+// the one a sorted listing shows first.
+struct BySize(FileMetadata);
+
+impl Ord for BySize {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.0.size.cmp(&other.0.size).then_with(|| other.0.path.cmp(&self.0.path))
+    }
+}
+
+impl PartialOrd for BySize {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for BySize {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl Eq for BySize {}
+
+// The `n` largest files, largest first and ties by path. One walk with a min-heap of at most `n`
+// entries, so it takes O(files * log n) and only clones files that make it into the heap, rather
+// than collecting and sorting everything.
+pub fn top_n_largest(root: &Option<Box<AVLTreeNode>>, n: usize) -> Vec<FileMetadata> {
+    if n == 0 {
+        return Vec::new();
+    }
+    let mut heap: BinaryHeap<cmp::Reverse<BySize>> = BinaryHeap::with_capacity(n + 1);
+    visit_in_order(root, &mut |file| {
+        let beats_smallest = match heap.peek() {
+            Some(cmp::Reverse(smallest)) if heap.len() == n => {
+                file.size > smallest.0.size || (file.size == smallest.0.size && file.path < smallest.0.path)
+            }
+            _ => true,
+        };
+        if beats_smallest {
+            heap.push(cmp::Reverse(BySize(file.clone())));
+            if heap.len() > n {
+                heap.pop();
+            }
+        }
+    });
+    // Ascending order of `Reverse` is descending order of size.
+    heap.into_sorted_vec().into_iter().map(|cmp::Reverse(BySize(file))| file).collect()
+}
+
+// Parses sizes like "500MB", "1.5 GB" or "2048". Units are powers of 1024, matching the listings.
+pub fn parse_size(input: &str) -> Option<u64> {
+    let input = input.trim();
+    let split = input.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1024,
+        "M" | "MB" => 1024 * 1024,
+        "G" | "GB" => 1024 * 1024 * 1024,
+        "T" | "TB" => 1024 * 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    Some((number * multiplier as f64) as u64)
+}
+
+// Files breaking one of `rules`, each an extension (without the dot, any case) and the most bytes a
+// file with it may have. Largest first, ties by path. A file matching several rules is listed once
+// if it breaks any of them.
+pub fn scan_limits(root: &Option<Box<AVLTreeNode>>, rules: &[(String, u64)]) -> Vec<FileMetadata> {
+    let mut violations = Vec::new();
+    visit_in_order(root, &mut |file| {
+        let Some(extension) = &file.extension else { return };
+        if rules.iter().any(|(rule, max_bytes)| rule.eq_ignore_ascii_case(extension) && file.size > *max_bytes) {
+            violations.push(file.clone());
+        }
+    });
+    violations.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    violations
+}
+
+// Reads `scan_limits` rules, one "extension size" per line ("mp4 2GB", ".log = 500MB"), with sizes
+// as `parse_size` takes them. Blank lines and lines starting with '#' are skipped. The error names
+// the first line that isn't a rule.
+pub fn parse_limit_rules(text: &str) -> Result<Vec<(String, u64)>, String> {
+    let mut rules = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (extension, size) = line.split_once(['=', ' ', '\t']).unwrap_or((line, ""));
+        let extension = extension.trim().trim_start_matches('.');
+        match parse_size(size.trim().trim_start_matches('=')) {
+            Some(max_bytes) if !extension.is_empty() => rules.push((extension.to_string(), max_bytes)),
+            _ => return Err(format!("line {}: expected an extension and a size, got {:?}", number + 1, line)),
+        }
+    }
+    Ok(rules)
+}
+
+pub fn load_limit_rules(path: &Path) -> io::Result<Vec<(String, u64)>> {
+    parse_limit_rules(&fs::read_to_string(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// The directories directly inside `root`, largest first (ties by name), with the sizes stored at
+// scan time. Directories of size 0 are included.
+pub fn top_level_sizes(table: &HashTable, root: &Path) -> Vec<FileMetadata> {
+    let mut directories: Vec<FileMetadata> = table
+        .entries()
+        .filter(|file| file.path.parent() == Some(root))
+        .cloned()
+        .collect();
+    directories.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    directories
+}
+
+// One bar per directory, scaled against the largest. `root_total` also counts the files stored
+// directly in the root, which don't belong to any of the directories.
+pub fn print_size_breakdown(out: &mut impl Write, root: &Path, directories: &[FileMetadata], root_total: u64) -> io::Result<()> {
+    const WIDTH: u64 = 40;
+    let largest = directories.iter().map(|file| file.size).max().unwrap_or(0);
+
+    writeln!(out, "{} - {} bytes in total", root.display(), root_total)?;
+    for file in directories {
+        let bar = if largest == 0 { 0 } else { (file.size as u128 * WIDTH as u128 / largest as u128) as usize };
+        let share = if root_total == 0 { 0.0 } else { file.size as f64 * 100.0 / root_total as f64 };
+        writeln!(out, "  {:<40} {:>5.1}% {} bytes  {}", "#".repeat(bar), share, file.size, file.name)?;
+    }
+    Ok(())
+}
+
+// The hierarchy below `root` drawn like `tree`: entries sorted by name at every level, with the
+// sizes from the scan. `max_depth` of 1 shows only what `root` holds directly; `None` shows all.
+pub fn directory_tree_lines(avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable, root: &Path, max_depth: Option<usize>) -> Vec<String> {
+    tree_lines_colored(avlvec, table, root, max_depth, false)
+}
+
+fn tree_lines_colored(avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable, root: &Path, max_depth: Option<usize>, color: bool) -> Vec<String> {
+    let mut children: BTreeMap<PathBuf, Vec<FileMetadata>> = BTreeMap::new();
+    let mut entries = Vec::new();
+    for tree in avlvec {
+        collect_avl_tree(tree, &mut entries);
+    }
+    entries.extend(table.entries().cloned());
+    for file in entries {
+        if let Some(parent) = file.path.parent() {
+            children.entry(parent.to_path_buf()).or_default().push(file);
+        }
+    }
+    for files in children.values_mut() {
+        files.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.path.cmp(&b.path)));
+    }
+
+    let mut lines = vec![root.display().to_string()];
+    tree_lines(&children, root, "", 1, max_depth, color, &mut lines);
+    lines
+}
+
+fn tree_lines(children: &BTreeMap<PathBuf, Vec<FileMetadata>>, dir: &Path, prefix: &str, depth: usize, max_depth: Option<usize>, color: bool, lines: &mut Vec<String>) {
+    if max_depth.is_some_and(|max_depth| depth > max_depth) {
+        return;
+    }
+    let files = match children.get(dir) {
+        Some(files) => files,
+        None => return,
+    };
+
+    for (index, file) in files.iter().enumerate() {
+        let last = index + 1 == files.len();
+        let is_directory = file.file_type == FileType::Directory;
+        lines.push(format!(
+            "{}{}{}{} ({} bytes{})",
+            prefix,
+            if last { "└── " } else { "├── " },
+            paint(file, &file.name, color),
+            if is_directory { "/" } else { "" },
+            file.size,
+            file.allocated.map(|allocated| format!(", {} allocated", allocated)).unwrap_or_default(),
+        ));
+        if is_directory {
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            tree_lines(children, &file.path, &prefix, depth + 1, max_depth, color, lines);
+        }
+    }
+}
+
+pub fn print_directory_tree(out: &mut impl Write, avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable, root: &Path, max_depth: Option<usize>) -> io::Result<()> {
+    print_directory_tree_colored(out, avlvec, table, root, max_depth, false)
+}
+
+// `print_directory_tree` with names colored by type when `color` is on (see `color::paint`).
+pub fn print_directory_tree_colored(out: &mut impl Write, avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable, root: &Path, max_depth: Option<usize>, color: bool) -> io::Result<()> {
+    for line in tree_lines_colored(avlvec, table, root, max_depth, color) {
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+// Number of files and total bytes per extension. Extensions are grouped without regard to ASCII
+// case, as `search_avl_by_extension` matches them, and files without one go under "(none)".
+pub fn extension_histogram(root: &Option<Box<AVLTreeNode>>) -> BTreeMap<String, (usize, u64)> {
+    let mut histogram: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+    visit_in_order(root, &mut |file| {
+        let extension = file.extension.as_ref().map(|ext| ext.to_ascii_lowercase()).unwrap_or_else(|| "(none)".to_string());
+        let entry = histogram.entry(extension).or_default();
+        entry.0 += 1;
+        entry.1 += file.size;
+    });
+    histogram
+}
+
+// Largest total first, ties by extension.
+pub fn print_extension_histogram(out: &mut impl Write, histogram: &BTreeMap<String, (usize, u64)>) -> io::Result<()> {
+    let mut rows: Vec<(&String, &(usize, u64))> = histogram.iter().collect();
+    rows.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then_with(|| a.0.cmp(b.0)));
+    for (extension, (count, total)) in rows {
+        writeln!(out, "{:<12} {:>8} files {:>14} bytes", extension, count, total)?;
+    }
+    Ok(())
+}
+
+// Files whose contents aren't what their extension claims, with what they were detected as. Uses
+// `detected` where `detect_types` already filled it in and reads the file otherwise. Extensions
+// without a mapping are skipped, as are files detected as `Unknown`, which gives nothing to compare.
+pub fn mismatched_types(root: &Option<Box<AVLTreeNode>>) -> Vec<(FileMetadata, DetectedType)> {
+    let mut mismatched = Vec::new();
+    visit_in_order(root, &mut |file| {
+        if file.file_type != FileType::File {
+            return;
+        }
+        let Some(expected) = file.extension.as_deref().and_then(expected_type) else { return };
+        let detected = file.detected.clone().unwrap_or_else(|| detect_type(&file.path));
+        if detected != expected && detected != DetectedType::Unknown {
+            mismatched.push((file.clone(), detected));
+        }
+    });
+    mismatched
+}
+
+// Newest first; entries without a modification time go last.
+pub fn files_by_modified(avlvec: &[Option<Box<AVLTreeNode>>]) -> Vec<FileMetadata> {
+    let mut files = Vec::new();
+    for root in avlvec {
+        collect_avl_tree(root, &mut files);
+    }
+    files.sort_by_key(|file| std::cmp::Reverse(file.modified));
+    files
+}
+
+// Files modified at or after `since`, in tree order. Files without a modification time are left out.
+pub fn modified_since(root: &Option<Box<AVLTreeNode>>, since: SystemTime) -> Vec<FileMetadata> {
+    let mut files = Vec::new();
+    visit_in_order(root, &mut |file| {
+        if file.modified.is_some_and(|modified| modified >= since) {
+            files.push(file.clone());
+        }
+    });
+    files
+}
+
+// Entries from the trees and the table whose path no longer exists on disk, in path order. A
+// broken symlink still exists, so only entries that are gone entirely are reported.
+pub fn verify_index(avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable) -> Vec<FileMetadata> {
+    let mut missing = Vec::new();
+    for root in avlvec {
+        visit_in_order(root, &mut |file| {
+            if fs::symlink_metadata(&file.path).is_err() {
+                missing.push(file.clone());
+            }
+        });
+    }
+    missing.extend(table.entries().filter(|file| fs::symlink_metadata(&file.path).is_err()).cloned());
+    missing.sort_by(|a, b| a.path.cmp(&b.path));
+    missing
+}
+
+// Zero-byte files from the trees and empty directories from the table. A directory's size is
+// also 0 when it only holds empty files, so directories are confirmed empty on disk.
+pub fn find_empty(avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable) -> Vec<FileMetadata> {
+    let mut files = Vec::new();
+    for root in avlvec {
+        collect_avl_tree(root, &mut files);
+    }
+    files.retain(|file| file.file_type == FileType::File && file.size == 0);
+
+    for directory in table.entries() {
+        if directory.size == 0 && fs::read_dir(&directory.path).is_ok_and(|mut entries| entries.next().is_none()) {
+            files.push(directory.clone());
+        }
+    }
+    files
+}
+
+// Files live in the trees and directories in the table, so a name lookup that shouldn't care
+// which one it is has to ask both. Files come first, then directories.
+pub fn search_everything(avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable, name: &str) -> Vec<FileMetadata> {
+    let mut files = Vec::new();
+    collect_avl_tree(&merge_avl_trees(avlvec), &mut files);
+    files.retain(|file| name_key(&file.name) == name_key(name));
+    files.extend(table.find_by_name(name).into_iter().cloned());
+    files
+}
+
+// Names used by more than one file, each with every path it appears at. Sorted by name, and
+// paths within a group by path, so the report is stable between runs.
+pub fn duplicate_names(avlvec: &[Option<Box<AVLTreeNode>>]) -> Vec<(String, Vec<PathBuf>)> {
+    let mut files = Vec::new();
+    for root in avlvec {
+        collect_avl_tree(root, &mut files);
+    }
+
+    let mut groups: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for file in files {
+        groups.entry(file.name).or_default().push(file.path);
+    }
+    groups
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|(name, mut paths)| {
+            paths.sort();
+            (name, paths)
+        })
+        .collect()
+}
+
+// How closely `candidate` matches `query`, from 0.0 (no resemblance) to 1.0 (same name, ignoring
+// case). Candidates containing the query as a subsequence score above 0.5, more so when the matched
+// characters are adjacent or start a word and when the candidate is short. Anything else is ranked
+// below 0.5 by edit distance, so typos still turn up.
+pub fn fuzzy_score(query: &str, candidate: &str) -> f64 {
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    if query.is_empty() || candidate.is_empty() {
+        return 0.0;
+    }
+    if query == candidate {
+        return 1.0;
+    }
+
+    match subsequence_score(&query, &candidate) {
+        Some(score) => 0.5 + 0.5 * score,
+        None => {
+            let longest = cmp::max(query.len(), candidate.len());
+            0.5 * (1.0 - levenshtein(&query, &candidate) as f64 / longest as f64)
+        }
+    }
+}
+
+// Below 1.0 for any candidate other than the query itself.
+fn subsequence_score(query: &[char], candidate: &[char]) -> Option<f64> {
+    let mut points = 0;
+    let mut previous: Option<usize> = None;
+    let mut start = 0;
+    for &wanted in query {
+        let index = start + candidate[start..].iter().position(|&c| c == wanted)?;
+        points += 1;
+        if previous.is_some_and(|previous| previous + 1 == index) {
+            points += 1;
+        }
+        if index == 0 || matches!(candidate[index - 1], '.' | '_' | '-' | ' ') {
+            points += 1;
+        }
+        previous = Some(index);
+        start = index + 1;
+    }
+    let closeness = points as f64 / (3 * query.len()) as f64;
+    let coverage = query.len() as f64 / candidate.len() as f64;
+    Some(0.8 * closeness.min(0.99) + 0.2 * coverage.min(0.99))
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, &ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = cmp::min(substitution, cmp::min(row[j], row[j + 1]) + 1);
+        }
+    }
+    row[b.len()]
+}
+
+// The `limit` best fuzzy matches across all trees, best first; ties keep name order.
+pub fn fuzzy_search(avlvec: &[Option<Box<AVLTreeNode>>], query: &str, limit: usize) -> Vec<(f64, FileMetadata)> {
+    let mut files = Vec::new();
+    collect_avl_tree(&merge_avl_trees(avlvec), &mut files);
+
+    let mut scored: Vec<(f64, FileMetadata)> = files
+        .into_iter()
+        .map(|file| (fuzzy_score(query, &file.name), file))
+        .filter(|(score, _)| *score > 0.0)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(limit);
+    scored
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Name,
+    Size,
+    Path,
+    Files,
+}
+
+impl SortKey {
+    pub fn parse(input: &str) -> Option<SortKey> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "name" => Some(SortKey::Name),
+            "size" => Some(SortKey::Size),
+            "path" => Some(SortKey::Path),
+            "files" => Some(SortKey::Files),
+            _ => None,
+        }
+    }
+}
+
+// Stable, so entries that tie on the key keep their traversal order.
+pub fn sort_entries(entries: &mut [FileMetadata], key: SortKey, descending: bool) {
+    entries.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Name => a.name.cmp(&b.name),
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Path => a.path.cmp(&b.path),
+            SortKey::Files => a.file_count.cmp(&b.file_count),
+        };
+        if descending { ordering.reverse() } else { ordering }
+    });
+}
+
+// Splits `len` entries into pages of `page_size`; the last page may be shorter. A page size of 0
+// means no paging, so everything lands on one page.
+pub fn page_ranges(len: usize, page_size: usize) -> Vec<Range<usize>> {
+    let page_size = if page_size == 0 { len.max(1) } else { page_size };
+    (0..len).step_by(page_size).map(|start| start..cmp::min(start + page_size, len)).collect()
+}
+
+pub fn print_entries(out: &mut impl Write, entries: &[FileMetadata]) -> io::Result<()> {
+    print_entries_colored(out, entries, false)
+}
+
+// `print_entries` with paths colored by type when `color` is on (see `color::paint`).
+pub fn print_entries_colored(out: &mut impl Write, entries: &[FileMetadata], color: bool) -> io::Result<()> {
+    for file in entries {
+        let path = file.path.display().to_string();
+        let path = paint(file, &path, color);
+        if file.file_type == FileType::Directory {
+            writeln!(
+                out,
+                "{:?} {} - {} bytes{} - {} files - {}",
+                file.file_type,
+                path,
+                file.size,
+                format_allocated(file),
+                file.file_count,
+                format_time(file.modified),
+            )?;
+        } else {
+            writeln!(
+                out,
+                "{:?} {} - {} bytes{} - {}",
+                file.file_type,
+                path,
+                file.size,
+                format_allocated(file),
+                format_time(file.modified),
+            )?;
+        }
+    }
+    Ok(())
+}
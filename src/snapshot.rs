@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::avl::{collect_avl_tree, insert_into_avl_tree, AVLTreeNode};
+use crate::hash_table::HashTable;
+use crate::metadata::{format_time, FileMetadata};
+use crate::scan::auto_bucket_count;
+
+// Written as the first byte of a binary snapshot. Bump it whenever `Snapshot` or `FileMetadata`
+// change shape, since bincode has no field names to fall back on and would misread older files.
+// 1 was the first layout, 2 added `FileMetadata::allocated`, 3 the snapshot's roots and capture time.
+pub const BINARY_VERSION: u8 = 3;
+
+// The index written out as JSON, so a later scan can be compared against it. Both lists are kept
+// in path order, so snapshots of an unchanged tree differ only in when they were captured.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    // Where and when the scan was taken. Snapshots written before these were recorded load with
+    // no roots and no time; `roots()` works the roots out from the entries instead.
+    #[serde(default)]
+    pub roots: Vec<PathBuf>,
+    #[serde(default)]
+    pub captured: Option<SystemTime>,
+    pub files: Vec<FileMetadata>,
+    pub directories: Vec<FileMetadata>,
+}
+
+impl Snapshot {
+    // A snapshot of the index as it is now, with `roots()` as the roots it was scanned from.
+    pub fn from_index(avlvec: &[Option<Box<AVLTreeNode>>], table: &HashTable) -> Self {
+        let mut files = Vec::new();
+        for root in avlvec {
+            collect_avl_tree(root, &mut files);
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut directories: Vec<FileMetadata> = table.entries().cloned().collect();
+        directories.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut snapshot = Snapshot { roots: Vec::new(), captured: Some(SystemTime::now()), files, directories };
+        snapshot.roots = snapshot.roots();
+        snapshot
+    }
+
+    // Records the roots the index was scanned from, for a scan that had roots with nothing in them.
+    pub fn with_roots(mut self, roots: &[PathBuf]) -> Self {
+        self.roots = roots.to_vec();
+        self
+    }
+
+    // "index of /data captured 2024-05-01 12:00:00", for showing what a loaded snapshot holds.
+    pub fn describe(&self) -> String {
+        let roots: Vec<String> = self.roots().iter().map(|root| root.display().to_string()).collect();
+        let roots = if roots.is_empty() { String::from("nothing") } else { roots.join(", ") };
+        match self.captured {
+            Some(captured) => format!("index of {} captured {}", roots, format_time(Some(captured))),
+            None => format!("index of {} (capture time not recorded)", roots),
+        }
+    }
+
+    pub fn save(&self, out: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(out)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    // The compact form for large indexes: `BINARY_VERSION`, then the snapshot in bincode.
+    pub fn save_binary(&self, out: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(out)?);
+        writer.write_all(&[BINARY_VERSION])?;
+        bincode::serialize_into(&mut writer, self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        writer.flush()
+    }
+
+    // Refuses files written with another `BINARY_VERSION` rather than guess at their layout.
+    pub fn load_binary(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut version = [0];
+        reader.read_exact(&mut version)?;
+        if version[0] != BINARY_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("snapshot format version {} is not supported (expected {})", version[0], BINARY_VERSION),
+            ));
+        }
+        bincode::deserialize_from(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // The directories the snapshot was scanned from. Older snapshots didn't record them, so they're
+    // worked out as the parents of entries whose parent isn't itself a directory in the snapshot.
+    // Sorted, without duplicates.
+    pub fn roots(&self) -> Vec<PathBuf> {
+        if !self.roots.is_empty() {
+            return self.roots.clone();
+        }
+        let directories: BTreeSet<&Path> = self.directories.iter().map(|directory| directory.path.as_path()).collect();
+        let roots: BTreeSet<PathBuf> = self
+            .files
+            .iter()
+            .chain(&self.directories)
+            .filter_map(|entry| entry.path.parent())
+            .filter(|parent| !directories.contains(parent))
+            .map(Path::to_path_buf)
+            .collect();
+        roots.into_iter().collect()
+    }
+
+    // Rebuilds the trees and table the snapshot was taken from, one tree per directory as the
+    // scan lays them out, with the table sized as `--buckets auto` would size it.
+    pub fn into_index(self) -> (Vec<Option<Box<AVLTreeNode>>>, HashTable) {
+        let mut table = HashTable::new(auto_bucket_count(self.directories.len()));
+        for directory in self.directories {
+            table.insert(directory);
+        }
+
+        let mut trees: BTreeMap<Option<PathBuf>, Option<Box<AVLTreeNode>>> = BTreeMap::new();
+        for file in self.files {
+            let tree = trees.entry(file.path.parent().map(Path::to_path_buf)).or_default();
+            *tree = Some(insert_into_avl_tree(tree.take(), file));
+        }
+        (trees.into_values().collect(), table)
+    }
+}
+
+// What changed between two indexes, matched up by path. Each list is in path order.
+#[derive(Debug, Default, PartialEq)]
+pub struct IndexDiff {
+    pub added: Vec<FileMetadata>,
+    pub removed: Vec<FileMetadata>,
+    // (before, after) for entries still present whose size changed.
+    pub resized: Vec<(FileMetadata, FileMetadata)>,
+}
+
+impl IndexDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.resized.is_empty()
+    }
+}
+
+fn diff_entries(old: Vec<FileMetadata>, mut new: Vec<FileMetadata>) -> IndexDiff {
+    let mut old: BTreeMap<PathBuf, FileMetadata> = old.into_iter().map(|file| (file.path.clone(), file)).collect();
+    let mut diff = IndexDiff::default();
+
+    new.sort_by(|a, b| a.path.cmp(&b.path));
+    for file in new {
+        match old.remove(&file.path) {
+            Some(before) if before.size != file.size => diff.resized.push((before, file)),
+            Some(_) => {}
+            None => diff.added.push(file),
+        }
+    }
+    diff.removed = old.into_values().collect();
+    diff
+}
+
+// Directories added, removed or resized between two tables.
+pub fn diff_indexes(old: &HashTable, new: &HashTable) -> IndexDiff {
+    diff_entries(old.entries().cloned().collect(), new.entries().cloned().collect())
+}
+
+// Files added, removed or resized between two sets of trees.
+pub fn diff_trees(old: &[Option<Box<AVLTreeNode>>], new: &[Option<Box<AVLTreeNode>>]) -> IndexDiff {
+    let mut old_files = Vec::new();
+    for root in old {
+        collect_avl_tree(root, &mut old_files);
+    }
+    let mut new_files = Vec::new();
+    for root in new {
+        collect_avl_tree(root, &mut new_files);
+    }
+    diff_entries(old_files, new_files)
+}
+
+pub fn print_diff(out: &mut impl Write, diff: &IndexDiff) -> io::Result<()> {
+    for file in &diff.added {
+        writeln!(out, "+ {} ({} bytes)", file.path.display(), file.size)?;
+    }
+    for file in &diff.removed {
+        writeln!(out, "- {} ({} bytes)", file.path.display(), file.size)?;
+    }
+    for (before, after) in &diff.resized {
+        let delta = after.size as i128 - before.size as i128;
+        writeln!(out, "~ {} {} -> {} bytes ({:+})", after.path.display(), before.size, after.size, delta)?;
+    }
+    Ok(())
+}
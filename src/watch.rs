@@ -0,0 +1,228 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::avl::{insert_into_avl_tree, remove_from_avl_tree, AVLTreeNode};
+use crate::hash_table::HashTable;
+use crate::metadata::{FileMetadata, FileType};
+use crate::scan::{build_avl_tree_with, directory_entry, file_entry, index_directory_with, ScanOptions, Totals};
+
+// Every entry of a per-directory tree shares the same parent, so the root identifies the directory.
+pub(crate) fn tree_directory(root: &Option<Box<AVLTreeNode>>) -> Option<&Path> {
+    root.as_ref()?.file.as_deref()?.path.parent()
+}
+
+// Whether the scan behind this index recorded allocated sizes, so refreshed entries can too.
+fn records_allocated(avlvec: &[Option<Box<AVLTreeNode>>], hash_table: &HashTable) -> bool {
+    let file = avlvec.iter().flatten().find_map(|node| node.file.as_deref());
+    let directory = hash_table.entries().next();
+    file.or(directory).is_some_and(|entry| entry.allocated.is_some())
+}
+
+// What an entry contributed to the totals of the directories above it.
+fn totals_of(entry: &FileMetadata) -> Totals {
+    Totals {
+        size: entry.size,
+        files: if entry.file_type == FileType::Directory { entry.file_count } else { u64::from(entry.file_type == FileType::File) },
+        allocated: entry.allocated.unwrap_or(0),
+    }
+}
+
+// Re-reads `path` (and everything under it) from disk, replacing whatever the index held for it.
+// Directories above it inside `scan_root` have their sizes adjusted by the difference.
+pub fn refresh_path(avlvec: &mut Vec<Option<Box<AVLTreeNode>>>, hash_table: &mut HashTable, scan_root: &Path, path: &Path) {
+    let name: String = match path.file_name() {
+        Some(name) => name.to_string_lossy().into(),
+        None => return,
+    };
+    let options = ScanOptions { allocated: records_allocated(avlvec, hash_table), ..ScanOptions::default() };
+
+    avlvec.retain(|root| !tree_directory(root).is_some_and(|dir| dir.starts_with(path)));
+    let mut old = hash_table
+        .remove_under(path)
+        .into_iter()
+        .find(|file| file.path == path)
+        .map(|file| totals_of(&file))
+        .unwrap_or_default();
+    for root in avlvec.iter_mut() {
+        if tree_directory(root) == path.parent() {
+            let (new_root, removed) = remove_from_avl_tree(root.take(), &name, path);
+            *root = new_root;
+            if let Some(removed) = removed {
+                old = totals_of(&removed);
+            }
+        }
+    }
+    // Keep `avlvec` free of empty trees, as the scan leaves it.
+    avlvec.retain(Option::is_some);
+
+    let mut new = Totals::default();
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.is_dir() {
+            let mut skipped = Vec::new();
+            if let Some(totals) = index_directory_with(path, hash_table, &mut skipped, None, options) {
+                hash_table.insert(directory_entry(path.to_path_buf(), totals, Some(&metadata), options));
+                build_avl_tree_with(path, avlvec, &mut skipped, None, options);
+                new = totals;
+            }
+        } else {
+            // Anything that isn't a regular file is kept in the trees the same way `build_avl_tree` does.
+            let file_type = if metadata.is_file() { FileType::File } else { FileType::Special };
+            let file = file_entry(path.to_path_buf(), file_type, &metadata, options);
+            new = Totals::of_entry(&metadata);
+            match avlvec.iter_mut().find(|root| tree_directory(root) == path.parent()) {
+                Some(root) => *root = Some(insert_into_avl_tree(root.take(), file)),
+                None => avlvec.push(Some(insert_into_avl_tree(None, file))),
+            }
+        }
+    }
+
+    if new == old {
+        return;
+    }
+    for ancestor in path.ancestors().skip(1).take_while(|ancestor| *ancestor != scan_root && ancestor.starts_with(scan_root)) {
+        if let Some(mut directory) = hash_table.remove_path(ancestor) {
+            directory.size = (directory.size + new.size).saturating_sub(old.size);
+            directory.file_count = (directory.file_count + new.files).saturating_sub(old.files);
+            directory.allocated = directory.allocated.map(|allocated| (allocated + new.allocated).saturating_sub(old.allocated));
+            hash_table.insert(directory);
+        }
+    }
+}
+
+// Re-walks `subdir` after its contents changed, replacing every index entry under it and leaving
+// the rest of the index alone. `subdir` has to be a directory in the table or a scan root, since
+// any other directory would be added as if it were a root. Directories above it have their totals
+// adjusted as with `refresh_path`.
+pub fn rescan_subtree(avlvec: &mut Vec<Option<Box<AVLTreeNode>>>, hash_table: &mut HashTable, subdir: &Path) {
+    if let Some(directory) = hash_table.find_by_path(subdir) {
+        // Every directory below a root is in the table, so the first ancestor missing from it is the root.
+        let subdir = directory.path.clone();
+        let scan_root = subdir
+            .ancestors()
+            .skip(1)
+            .find(|ancestor| hash_table.find_by_path(ancestor).is_none())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        refresh_path(avlvec, hash_table, &scan_root, &subdir);
+        return;
+    }
+    // A scan root has no entry of its own, so only what's below it is replaced.
+    let options = ScanOptions { allocated: records_allocated(avlvec, hash_table), ..ScanOptions::default() };
+    avlvec.retain(|root| !tree_directory(root).is_some_and(|dir| dir.starts_with(subdir)));
+    hash_table.remove_under(subdir);
+    let mut skipped = Vec::new();
+    index_directory_with(subdir, hash_table, &mut skipped, None, options);
+    build_avl_tree_with(subdir, avlvec, &mut skipped, None, options);
+}
+
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+pub struct IndexWatcher {
+    // Kept alive for as long as events should be delivered.
+    _watcher: RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+}
+
+impl IndexWatcher {
+    pub fn new(roots: &[PathBuf]) -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        for root in roots {
+            watcher.watch(root, RecursiveMode::Recursive)?;
+        }
+        Ok(IndexWatcher { _watcher: watcher, events })
+    }
+
+    // Returns every path touched since the last call. Once an event arrives, keeps collecting until
+    // the filesystem has been quiet for `WATCH_DEBOUNCE`, so a burst is applied as one batch.
+    pub fn changed_paths(&self) -> BTreeSet<PathBuf> {
+        match self.events.try_recv() {
+            Ok(first) => self.collect_burst(first),
+            Err(_) => BTreeSet::new(),
+        }
+    }
+
+    fn collect_burst(&self, first: notify::Result<notify::Event>) -> BTreeSet<PathBuf> {
+        let mut paths = BTreeSet::new();
+        let mut next = Some(first);
+        while let Some(result) = next {
+            match result {
+                Ok(event) => paths.extend(event.paths),
+                Err(e) => println!("Watch error: {}", e),
+            }
+            next = self.events.recv_timeout(WATCH_DEBOUNCE).ok();
+        }
+        paths
+    }
+
+    // Paths are applied in sorted order, so a directory is refreshed before anything inside it.
+    pub fn apply(&self, avlvec: &mut Vec<Option<Box<AVLTreeNode>>>, hash_table: &mut HashTable, scan_roots: &[PathBuf]) -> usize {
+        let paths = self.changed_paths();
+        apply_paths(&paths, avlvec, hash_table, scan_roots);
+        paths.len()
+    }
+
+    // Applies changes to `index` from a background thread as they happen, until the watcher stops
+    // delivering events.
+    pub fn spawn(self, index: SharedIndex, scan_roots: Vec<PathBuf>) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            while let Ok(first) = self.events.recv() {
+                let paths = self.collect_burst(first);
+                index.write(|avlvec, hash_table| apply_paths(&paths, avlvec, hash_table, &scan_roots));
+                index.updates.fetch_add(paths.len(), Ordering::SeqCst);
+            }
+        })
+    }
+}
+
+fn apply_paths(paths: &BTreeSet<PathBuf>, avlvec: &mut Vec<Option<Box<AVLTreeNode>>>, hash_table: &mut HashTable, scan_roots: &[PathBuf]) {
+    for path in paths {
+        if let Some(scan_root) = scan_roots.iter().find(|root| path.starts_with(root)) {
+            refresh_path(avlvec, hash_table, scan_root, path);
+        }
+    }
+}
+
+// The index behind locks, so a watcher thread can update it while another thread reads it.
+// Cloning gives another handle to the same index. Both locks are always taken trees first, then
+// the table, so readers and writers can't deadlock.
+#[derive(Clone)]
+pub struct SharedIndex {
+    trees: Arc<RwLock<Vec<Option<Box<AVLTreeNode>>>>>,
+    table: Arc<RwLock<HashTable>>,
+    updates: Arc<AtomicUsize>,
+}
+
+impl SharedIndex {
+    pub fn new(avlvec: Vec<Option<Box<AVLTreeNode>>>, hash_table: HashTable) -> Self {
+        SharedIndex {
+            trees: Arc::new(RwLock::new(avlvec)),
+            table: Arc::new(RwLock::new(hash_table)),
+            updates: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn read<R>(&self, f: impl FnOnce(&[Option<Box<AVLTreeNode>>], &HashTable) -> R) -> R {
+        let trees = self.trees.read().unwrap();
+        let table = self.table.read().unwrap();
+        f(&trees, &table)
+    }
+
+    pub fn write<R>(&self, f: impl FnOnce(&mut Vec<Option<Box<AVLTreeNode>>>, &mut HashTable) -> R) -> R {
+        let mut trees = self.trees.write().unwrap();
+        let mut table = self.table.write().unwrap();
+        f(&mut trees, &mut table)
+    }
+
+    // Number of changed paths applied by the watcher thread since the last call.
+    pub fn take_updates(&self) -> usize {
+        self.updates.swap(0, Ordering::SeqCst)
+    }
+}
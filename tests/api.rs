@@ -0,0 +1,40 @@
+use std::fs;
+
+use file_management::avl::collect_avl_tree;
+use file_management::ops;
+use file_management::scan::build_index;
+use file_management::search::search_all;
+use file_management::{FileType, HashTable};
+
+#[test]
+fn index_search_and_modify_through_the_public_api() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("docs")).unwrap();
+    fs::write(dir.path().join("docs").join("notes.txt"), "hello").unwrap();
+    fs::write(dir.path().join("top.txt"), "abc").unwrap();
+
+    let (avlvec, hash_table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+
+    let mut files = Vec::new();
+    for root in &avlvec {
+        collect_avl_tree(root, &mut files);
+    }
+    assert_eq!(files.len(), 2);
+    assert!(files.iter().all(|file| file.file_type == FileType::File));
+
+    let docs = hash_table.find_by_path(&dir.path().join("docs")).unwrap();
+    assert_eq!(docs.file_type, FileType::Directory);
+    assert_eq!(docs.size, 5);
+
+    let notes = dir.path().join("docs").join("notes.txt");
+    let matches = search_all(&avlvec, &notes);
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].size, 5);
+
+    ops::write_file(&matches[0], "updated").unwrap();
+    assert_eq!(ops::read_file(&notes).unwrap(), "updated");
+
+    ops::delete_file(&notes).unwrap();
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    assert!(search_all(&avlvec, &notes).is_empty());
+}
@@ -0,0 +1,456 @@
+mod common;
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::{assert_balanced, fixture};
+use file_management::avl::{
+    collect_avl_tree, compare_metadata, delete_by_name, detect_cycle, files_in_size_range, for_each_file_mut,
+    files_with_prefix, height_bound, insert_into_avl_tree, insert_into_avl_tree_by, merge_avl_trees, predecessor,
+    predecessor_by, remove_from_avl_tree, remove_from_avl_tree_by, search_avl_tree, search_avl_tree_by, search_range,
+    select_kth, subtree_size, successor, successor_by, tree_height, visit_in_order, OrderKey,
+};
+use file_management::scan::{build_avl_tree_by, build_index};
+use file_management::{AVLTreeNode, FileMetadata, FileType, HashTable};
+
+fn file(directory: &str, name: &str) -> FileMetadata {
+    FileMetadata::new(PathBuf::from(directory).join(name), name.len() as u64, FileType::File, None)
+}
+
+fn tree_of(names: &[&str]) -> Option<Box<AVLTreeNode>> {
+    let mut root = None;
+    for name in names {
+        root = Some(insert_into_avl_tree(root, file("/d", name)));
+    }
+    root
+}
+
+fn in_order(root: &Option<Box<AVLTreeNode>>) -> Vec<String> {
+    let mut files = Vec::new();
+    collect_avl_tree(root, &mut files);
+    files.into_iter().map(|file| file.name).collect()
+}
+
+#[test]
+fn sorted_inserts_stay_balanced() {
+    let names: Vec<String> = (0..200).map(|i| format!("f{:03}", i)).collect();
+    let refs: Vec<&str> = names.iter().map(String::as_str).collect();
+    let root = tree_of(&refs);
+
+    let height = assert_balanced(&root);
+    assert!(height <= 9, "height {}", height);
+    assert_eq!(subtree_size(&root), 200);
+}
+
+#[test]
+fn large_sorted_trees_build_without_copying_subtrees() {
+    let build = |n: usize| {
+        let files: Vec<FileMetadata> = (0..n).map(|i| file("/d", &format!("f{:06}", i))).collect();
+        let started = Instant::now();
+        let mut root = None;
+        for file in files {
+            root = Some(insert_into_avl_tree(root, file));
+        }
+        (root, started.elapsed())
+    };
+    let (_, small_time) = build(5_000);
+    let (root, large_time) = build(40_000);
+    assert_eq!(subtree_size(&root), 40_000);
+    assert!(f64::from(assert_balanced(&root)) <= height_bound(40_000));
+    // Eight times the files costs about ten times as long; cloning the subtree at every level on
+    // the way down made it sixty-four.
+    assert!(large_time < small_time * 25 + Duration::from_millis(200), "{:?} against {:?}", large_time, small_time);
+}
+
+#[test]
+fn in_order_traversal_is_sorted_by_name() {
+    let root = tree_of(&["m", "c", "x", "a", "e", "z", "b"]);
+    assert_eq!(in_order(&root), ["a", "b", "c", "e", "m", "x", "z"]);
+}
+
+#[test]
+fn search_finds_files_by_path() {
+    let root = tree_of(&["m", "c", "x"]);
+    assert_eq!(search_avl_tree(&root, PathBuf::from("/d/c")).unwrap().name, "c");
+    assert!(search_avl_tree(&root, PathBuf::from("/d/q")).is_none());
+    assert!(search_avl_tree(&None, PathBuf::from("/d/c")).is_none());
+}
+
+#[test]
+fn search_finds_files_across_directories() {
+    // Path order and name order disagree here, so a walk by path would take wrong turns.
+    let paths = ["/e/a.txt", "/d/b.txt", "/c/c.txt", "/b/d.txt", "/a/e.txt", "/a/a.txt", "/e/e.txt"];
+    let mut root = None;
+    for path in paths {
+        root = Some(insert_into_avl_tree(root, FileMetadata::new(PathBuf::from(path), 1, FileType::File, None)));
+    }
+    for path in paths {
+        assert_eq!(search_avl_tree(&root, PathBuf::from(path)).map(|file| file.path), Some(PathBuf::from(path)), "{}", path);
+    }
+    assert!(search_avl_tree(&root, PathBuf::from("/b/a.txt")).is_none());
+
+    let merged = merge_avl_trees(&[tree_of(&["x", "y"]), Some(insert_into_avl_tree(None, file("/other", "m")))]);
+    assert!(search_avl_tree(&merged, PathBuf::from("/other/m")).is_some());
+    assert!(search_avl_tree(&merged, PathBuf::from("/d/y")).is_some());
+}
+
+#[test]
+fn remove_keeps_the_tree_balanced() {
+    let names: Vec<String> = (0..64).map(|i| format!("f{:02}", i)).collect();
+    let refs: Vec<&str> = names.iter().map(String::as_str).collect();
+    let mut root = tree_of(&refs);
+
+    for name in names.iter().step_by(2) {
+        let (rest, removed) = remove_from_avl_tree(root, name, &PathBuf::from("/d").join(name));
+        assert_eq!(removed.unwrap().name, *name);
+        root = rest;
+        assert_balanced(&root);
+    }
+    assert_eq!(subtree_size(&root), 32);
+    assert_eq!(in_order(&root), names.iter().skip(1).step_by(2).cloned().collect::<Vec<_>>());
+}
+
+#[test]
+fn remove_of_a_missing_file_leaves_the_tree_alone() {
+    let root = tree_of(&["a", "b", "c"]);
+    let (root, removed) = remove_from_avl_tree(root, "q", &PathBuf::from("/d/q"));
+    assert!(removed.is_none());
+    assert_eq!(in_order(&root), ["a", "b", "c"]);
+}
+
+#[test]
+fn remove_picks_the_right_file_among_equal_names() {
+    let mut root = None;
+    for directory in ["/a", "/b", "/c", "/d", "/e"] {
+        root = Some(insert_into_avl_tree(root, file(directory, "same")));
+    }
+
+    let (root, removed) = remove_from_avl_tree(root, "same", &PathBuf::from("/c/same"));
+    assert_eq!(removed.unwrap().path, PathBuf::from("/c/same"));
+
+    let mut left = Vec::new();
+    collect_avl_tree(&root, &mut left);
+    let mut paths: Vec<PathBuf> = left.into_iter().map(|file| file.path).collect();
+    paths.sort();
+    assert_eq!(paths, ["/a/same", "/b/same", "/d/same", "/e/same"].map(PathBuf::from));
+}
+
+#[test]
+fn order_statistics() {
+    let root = tree_of(&["d", "b", "f", "a", "c", "e", "g"]);
+    assert_eq!(select_kth(&root, 1).unwrap().name, "a");
+    assert_eq!(select_kth(&root, 7).unwrap().name, "g");
+    assert!(select_kth(&root, 8).is_none());
+
+    assert_eq!(predecessor(&root, "d").unwrap().name, "c");
+    assert_eq!(successor(&root, "d").unwrap().name, "e");
+    assert_eq!(predecessor(&root, "cc").unwrap().name, "c");
+    assert!(predecessor(&root, "a").is_none());
+    assert!(successor(&root, "g").is_none());
+}
+
+#[test]
+fn kth_selection_matches_a_sorted_list_after_shuffled_changes() {
+    // 37 and 101 are coprime, so this visits every number below 101 once, out of order.
+    let names: Vec<String> = (0..101).map(|i| format!("f{:03}", i * 37 % 101)).collect();
+    let mut root = None;
+    for name in &names {
+        root = Some(insert_into_avl_tree(root, file("/d", name)));
+    }
+    let mut sorted = names.clone();
+    sorted.sort();
+    // Every third name, also in shuffled order, so the removals rotate all over the tree.
+    for name in names.iter().step_by(3) {
+        let (new_root, removed) = remove_from_avl_tree(root, name, &PathBuf::from("/d").join(name));
+        assert!(removed.is_some(), "{}", name);
+        root = new_root;
+        sorted.retain(|kept| kept != name);
+    }
+
+    assert_eq!(subtree_size(&root), sorted.len());
+    for (k, name) in (1..).zip(&sorted) {
+        assert_eq!(select_kth(&root, k).unwrap().name, *name, "k = {}", k);
+    }
+    assert!(select_kth(&root, 0).is_none());
+    assert!(select_kth(&root, sorted.len() + 1).is_none());
+    assert_balanced(&root);
+}
+
+#[test]
+fn equal_names_are_ordered_by_path() {
+    let directories = ["/d/m", "/d/b", "/d/z", "/d/a", "/d/k", "/d/c"];
+    let mut forward = None;
+    for directory in directories {
+        forward = Some(insert_into_avl_tree(forward, file(directory, "same.txt")));
+        forward = Some(insert_into_avl_tree(forward, file(directory, "other.txt")));
+    }
+    let mut backward = None;
+    for directory in directories.iter().rev() {
+        backward = Some(insert_into_avl_tree(backward, file(directory, "other.txt")));
+        backward = Some(insert_into_avl_tree(backward, file(directory, "same.txt")));
+    }
+
+    let paths = |root: &Option<Box<AVLTreeNode>>| {
+        let mut files = Vec::new();
+        collect_avl_tree(root, &mut files);
+        files.into_iter().map(|file| file.path).collect::<Vec<_>>()
+    };
+    let mut expected: Vec<PathBuf> = Vec::new();
+    for name in ["other.txt", "same.txt"] {
+        let mut sorted = directories;
+        sorted.sort();
+        expected.extend(sorted.iter().map(|directory| PathBuf::from(directory).join(name)));
+    }
+    assert_eq!(paths(&forward), expected);
+    assert_eq!(paths(&backward), expected);
+
+    let (root, removed) = remove_from_avl_tree(forward, "same.txt", &PathBuf::from("/d/k/same.txt"));
+    assert_eq!(removed.unwrap().path, PathBuf::from("/d/k/same.txt"));
+    expected.retain(|path| path != &PathBuf::from("/d/k/same.txt"));
+    assert_eq!(paths(&root), expected);
+    assert_balanced(&root);
+}
+
+#[test]
+fn visitors_see_every_file_in_order() {
+    let root = tree_of(&["m", "c", "xyz", "a", "ee"]);
+
+    let mut total = 0;
+    visit_in_order(&root, &mut |file| total += file.size);
+    assert_eq!(total, 1 + 1 + 3 + 1 + 2);
+
+    let mut names = Vec::new();
+    visit_in_order(&root, &mut |file| names.push(file.name.clone()));
+    assert_eq!(names, ["a", "c", "ee", "m", "xyz"]);
+
+    let mut visited = 0;
+    visit_in_order(&None, &mut |_| visited += 1);
+    assert_eq!(visited, 0);
+}
+
+fn sized(name: &str, size: u64) -> FileMetadata {
+    FileMetadata::new(PathBuf::from("/d").join(name), size, FileType::File, None)
+}
+
+#[test]
+fn size_ordered_trees_prune_range_queries() {
+    let mut root = None;
+    for i in 0..256u64 {
+        // Sizes scattered over 0..1000 so insertion order isn't size order.
+        root = Some(insert_into_avl_tree_by(root, sized(&format!("f{:03}", i), i * 389 % 1000), OrderKey::Size));
+    }
+    assert_balanced(&root);
+
+    let mut sizes = Vec::new();
+    visit_in_order(&root, &mut |file| sizes.push(file.size));
+    assert!(sizes.windows(2).all(|pair| pair[0] <= pair[1]));
+
+    let found: Vec<u64> = files_in_size_range(&root, 100, 140).iter().map(|file| file.size).collect();
+    let expected: Vec<u64> = sizes.iter().copied().filter(|size| (100..=140).contains(size)).collect();
+    assert_eq!(found, expected);
+    assert!(files_in_size_range(&root, 1000, u64::MAX).is_empty());
+
+    // The narrow range only looks at the path down to it and the matches.
+    let looked_at = Cell::new(0);
+    search_range(&root, &mut |file| {
+        looked_at.set(looked_at.get() + 1);
+        file.size.cmp(&120)
+    });
+    assert!(looked_at.get() < 30, "looked at {} of 256", looked_at.get());
+}
+
+#[test]
+fn size_ordered_trees_are_searched_and_changed_by_size() {
+    let files: Vec<FileMetadata> = (0..64u64).map(|i| sized(&format!("f{:02}", i), i * 37 % 50)).collect();
+    let mut root = None;
+    for file in &files {
+        root = Some(insert_into_avl_tree_by(root, file.clone(), OrderKey::Size));
+    }
+
+    for file in &files {
+        assert_eq!(search_avl_tree_by(&root, file, OrderKey::Size).map(|found| found.path), Some(file.path.clone()));
+    }
+    // Same path, different size: not where the tree would have put it.
+    let mut stale = files[5].clone();
+    stale.size += 1;
+    assert!(search_avl_tree_by(&root, &stale, OrderKey::Size).is_none());
+
+    let mut sorted = files.clone();
+    sorted.sort_by(|a, b| a.size.cmp(&b.size).then_with(|| a.path.cmp(&b.path)));
+    assert_eq!(predecessor_by(&root, &sorted[10], OrderKey::Size).unwrap().path, sorted[9].path);
+    assert_eq!(successor_by(&root, &sorted[10], OrderKey::Size).unwrap().path, sorted[11].path);
+    assert!(predecessor_by(&root, &sorted[0], OrderKey::Size).is_none());
+    assert!(successor_by(&root, &sorted[63], OrderKey::Size).is_none());
+
+    for file in &files[..32] {
+        let (rest, removed) = remove_from_avl_tree_by(root, file, OrderKey::Size);
+        root = rest;
+        assert_eq!(removed.map(|removed| removed.path), Some(file.path.clone()));
+    }
+    assert_eq!(subtree_size(&root), 32);
+    assert_balanced(&root);
+    assert!(files[32..].iter().all(|file| search_avl_tree_by(&root, file, OrderKey::Size).is_some()));
+}
+
+#[test]
+fn name_ordered_trees_prune_prefix_queries() {
+    let names: Vec<String> = (0..200).map(|i| format!("{}{:03}", ["log", "img", "doc", "lib"][i % 4], i)).collect();
+    let refs: Vec<&str> = names.iter().map(String::as_str).collect();
+    let root = tree_of(&refs);
+
+    let found: Vec<String> = files_with_prefix(&root, "li").into_iter().map(|file| file.name).collect();
+    let mut expected: Vec<String> = names.iter().filter(|name| name.starts_with("li")).cloned().collect();
+    expected.sort();
+    assert_eq!(found, expected);
+    assert_eq!(files_with_prefix(&root, "log1").len(), 25);
+    assert!(files_with_prefix(&root, "zzz").is_empty());
+
+    // The same key is used when trees are built from disk.
+    let dir = fixture();
+    let mut avlvec = Vec::new();
+    build_avl_tree_by(dir.path(), &mut avlvec, &mut Vec::new(), None, OrderKey::Size);
+    let root_tree = avlvec.iter().find(|root| root.as_ref().unwrap().file.as_ref().unwrap().path.parent() == Some(dir.path())).unwrap();
+    let sizes: Vec<u64> = files_in_size_range(root_tree, 0, u64::MAX).iter().map(|file| file.size).collect();
+    assert_eq!(sizes, [3, 12]);
+}
+
+#[test]
+fn random_inserts_stay_under_the_avl_height_bound() {
+    assert_eq!(tree_height(&None), 0);
+
+    // xorshift, so the order is scrambled but the same on every run.
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut root = None;
+    for n in 1..=2000 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        root = Some(insert_into_avl_tree(root, file("/d", &format!("{:016x}", state))));
+        assert_eq!(tree_height(&root), assert_balanced(&root));
+        assert!(f64::from(tree_height(&root)) <= height_bound(n), "height {} for {} files", tree_height(&root), n);
+    }
+    assert!(tree_height(&root) <= 15);
+}
+
+fn shared_files(a: &Option<Box<AVLTreeNode>>, b: &Option<Box<AVLTreeNode>>, shared: &mut usize) {
+    if let (Some(a), Some(b)) = (a, b) {
+        if Arc::ptr_eq(a.file.as_ref().unwrap(), b.file.as_ref().unwrap()) {
+            *shared += 1;
+        }
+        shared_files(&a.left, &b.left, shared);
+        shared_files(&a.right, &b.right, shared);
+    }
+}
+
+#[test]
+fn clones_share_file_metadata_until_changed() {
+    let dir = fixture();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+
+    let mut copy = avlvec.clone();
+    let mut shared = 0;
+    for (original, cloned) in avlvec.iter().zip(&copy) {
+        shared_files(original, cloned, &mut shared);
+    }
+    assert_eq!(shared, 6);
+    let table_copy = table.clone();
+    for (original, cloned) in table.buckets.iter().flatten().zip(table_copy.buckets.iter().flatten()) {
+        assert!(Arc::ptr_eq(original, cloned));
+    }
+
+    // Changing the copy gives it its own entries and leaves the original as it was.
+    for_each_file_mut(&mut copy[0], &mut |file| file.size += 100);
+    let mut after = 0;
+    for (original, cloned) in avlvec.iter().zip(&copy) {
+        shared_files(original, cloned, &mut after);
+    }
+    assert_eq!(after, 6 - subtree_size(&copy[0]));
+    let mut sizes = Vec::new();
+    for tree in &avlvec {
+        visit_in_order(tree, &mut |file| sizes.push(file.size));
+    }
+    assert!(sizes.iter().all(|size| *size < 100));
+}
+
+#[test]
+fn cycles_are_caught_by_the_node_bound() {
+    let root = tree_of(&["a", "b", "c", "d", "e"]);
+    assert!(!detect_cycle(&root, 5));
+    assert!(!detect_cycle(&None, 0));
+    assert!(detect_cycle(&root, 4));
+
+    // `Box` can't point back at an ancestor, so the loop is unrolled instead: the root hangs off
+    // its own copy again and again, the way a walk of a real cycle would keep meeting it, while
+    // the cached sizes still say five nodes.
+    let mut looped = root.unwrap();
+    for _ in 0..20 {
+        let mut copy = looped.clone();
+        copy.left = Some(looped);
+        looped = copy;
+    }
+    let looped = Some(looped);
+    assert_eq!(subtree_size(&looped), 5);
+    assert!(detect_cycle(&looped, subtree_size(&looped)));
+}
+
+#[test]
+fn deleting_by_name_removes_every_directory_copy() {
+    let mut root = None;
+    for directory in ["/a", "/a/b", "/c", "/c/d/e", "/f"] {
+        for name in ["Thumbs.db", "photo.jpg", "notes.txt"] {
+            root = Some(insert_into_avl_tree(root, file(directory, name)));
+        }
+    }
+    for i in 0..20 {
+        root = Some(insert_into_avl_tree(root, file("/g", &format!("img{:02}.png", i))));
+    }
+
+    let (root, removed) = delete_by_name(root, "Thumbs.db");
+    assert_eq!(removed, 5);
+    assert_eq!(subtree_size(&root), 30);
+    assert!(!in_order(&root).iter().any(|name| name == "Thumbs.db"));
+    assert_balanced(&root);
+
+    let (root, removed) = delete_by_name(root, "missing.txt");
+    assert_eq!(removed, 0);
+    assert_eq!(subtree_size(&root), 30);
+    let (root, removed) = delete_by_name(root, "photo.jpg");
+    assert_eq!(removed, 5);
+    assert_eq!(in_order(&root).iter().filter(|name| *name == "notes.txt").count(), 5);
+    assert_balanced(&root);
+}
+
+#[test]
+fn the_comparator_orders_by_each_key_and_breaks_ties_by_path() {
+    let sized = |path: &str, size: u64| FileMetadata::new(PathBuf::from(path), size, FileType::File, None);
+    let a = sized("/x/a.txt", 30);
+    let b = sized("/w/b.txt", 10);
+    let a_elsewhere = sized("/y/a.txt", 10);
+
+    assert_eq!(compare_metadata(&a, &b, OrderKey::Name), Ordering::Less);
+    assert_eq!(compare_metadata(&a, &b, OrderKey::Path), Ordering::Greater);
+    assert_eq!(compare_metadata(&a, &b, OrderKey::Size), Ordering::Greater);
+
+    // Equal names or sizes fall back to the path.
+    assert_eq!(compare_metadata(&a, &a_elsewhere, OrderKey::Name), Ordering::Less);
+    assert_eq!(compare_metadata(&b, &a_elsewhere, OrderKey::Size), Ordering::Less);
+    assert_eq!(compare_metadata(&a_elsewhere, &b, OrderKey::Size), Ordering::Greater);
+
+    // Only the same path is equal, whatever else differs, and "." components don't count.
+    let a_resized = sized("/x/./a.txt", 99);
+    for key in [OrderKey::Name, OrderKey::Path] {
+        assert_eq!(compare_metadata(&a, &a_resized, key), Ordering::Equal);
+    }
+    assert_eq!(compare_metadata(&a, &a_resized, OrderKey::Size), Ordering::Less);
+    for key in [OrderKey::Name, OrderKey::Path, OrderKey::Size] {
+        assert_eq!(compare_metadata(&a, &a.clone(), key), Ordering::Equal);
+    }
+
+    // An entry comparing equal to one already in the tree goes after it.
+    let root = Some(insert_into_avl_tree(None, a.clone()));
+    let root = Some(insert_into_avl_tree(root, a_resized));
+    assert_eq!(root.as_ref().unwrap().right.as_ref().unwrap().file.as_ref().unwrap().size, 99);
+}
@@ -0,0 +1,61 @@
+// Shared fixtures for the integration tests.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::Path;
+
+use file_management::avl::collect_avl_tree;
+use file_management::{AVLTreeNode, FileMetadata};
+use tempfile::TempDir;
+
+// Layout used by most tests:
+//
+//   a.txt            "aaa"
+//   b.rs             "fn main() {}"
+//   empty/
+//   src/main.rs      "fn main() {}"
+//   src/lib/mod.rs   "pub mod x;"
+//   src/lib/a.txt    "nested"
+//   docs/a.txt       "docs"
+pub fn fixture() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("empty")).unwrap();
+    fs::create_dir_all(root.join("src").join("lib")).unwrap();
+    fs::create_dir_all(root.join("docs")).unwrap();
+
+    write(root, "a.txt", "aaa");
+    write(root, "b.rs", "fn main() {}");
+    write(root, "src/main.rs", "fn main() {}");
+    write(root, "src/lib/mod.rs", "pub mod x;");
+    write(root, "src/lib/a.txt", "nested");
+    write(root, "docs/a.txt", "docs");
+    dir
+}
+
+pub fn write(root: &Path, relative: &str, contents: &str) {
+    fs::write(root.join(relative), contents).unwrap();
+}
+
+pub fn all_files(avlvec: &[Option<Box<AVLTreeNode>>]) -> Vec<FileMetadata> {
+    let mut files = Vec::new();
+    for root in avlvec {
+        collect_avl_tree(root, &mut files);
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    files
+}
+
+// Fails if any node's height is stale or its subtrees differ in height by more than one.
+pub fn assert_balanced(root: &Option<Box<AVLTreeNode>>) -> i32 {
+    match root {
+        Some(node) => {
+            let left = assert_balanced(&node.left);
+            let right = assert_balanced(&node.right);
+            assert!((left - right).abs() <= 1, "unbalanced at {:?}", node.file.as_ref().map(|f| &f.name));
+            assert_eq!(node.height, 1 + left.max(right));
+            node.height
+        }
+        None => 0,
+    }
+}
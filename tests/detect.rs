@@ -0,0 +1,87 @@
+use std::fs;
+
+use file_management::avl::collect_avl_tree;
+use file_management::metadata::{detect_type, expected_type};
+use file_management::scan::{build_index, detect_types};
+use file_management::search::mismatched_types;
+use file_management::{DetectedType, FileType, HashTable};
+
+#[test]
+fn magic_bytes_decide_the_type() {
+    let dir = tempfile::tempdir().unwrap();
+    let cases: [(&str, &[u8], DetectedType); 9] = [
+        // Named to mislead: the contents decide.
+        ("image.txt", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", DetectedType::Png),
+        ("photo.jpg", b"\xff\xd8\xff\xe0\0\x10JFIF", DetectedType::Jpeg),
+        ("anim.gif", b"GIF89a\x01\0\x01\0", DetectedType::Gif),
+        ("doc.bin", b"%PDF-1.7\n%\xe2\xe3\xcf\xd3", DetectedType::Pdf),
+        ("program", b"\x7fELF\x02\x01\x01\0", DetectedType::Elf),
+        ("archive.docx", b"PK\x03\x04\x14\0", DetectedType::Zip),
+        ("notes", "plain text, caf\u{e9}\n".as_bytes(), DetectedType::Text),
+        ("blob.txt", b"\x00\x01\x02\xff", DetectedType::Unknown),
+        ("empty", b"", DetectedType::Unknown),
+    ];
+    for (name, contents, _) in &cases {
+        fs::write(dir.path().join(name), contents).unwrap();
+    }
+
+    for (name, _, expected) in &cases {
+        assert_eq!(detect_type(&dir.path().join(name)), *expected, "{}", name);
+    }
+    assert_eq!(detect_type(&dir.path().join("missing")), DetectedType::Unknown);
+}
+
+#[test]
+fn detection_only_happens_when_asked() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("image.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(dir.path().join("sub/readme"), "hello").unwrap();
+
+    let (mut avlvec, _) = build_index(dir.path(), HashTable::new(4), false, &mut Vec::new(), None);
+    let mut files = Vec::new();
+    for root in &avlvec {
+        collect_avl_tree(root, &mut files);
+    }
+    assert!(files.iter().all(|file| file.detected.is_none()));
+
+    detect_types(&mut avlvec);
+    let mut files = Vec::new();
+    for root in &avlvec {
+        collect_avl_tree(root, &mut files);
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    assert!(files.iter().all(|file| file.file_type == FileType::File));
+    assert_eq!(files[0].detected, Some(DetectedType::Png));
+    assert_eq!(files[1].detected, Some(DetectedType::Text));
+}
+
+#[test]
+fn files_that_are_not_what_their_extension_says_are_flagged() {
+    let dir = tempfile::tempdir().unwrap();
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    fs::write(dir.path().join("image.txt"), png).unwrap();
+    fs::write(dir.path().join("image.png"), png).unwrap();
+    fs::write(dir.path().join("holiday.JPG"), b"PK\x03\x04\x14\0").unwrap();
+    fs::write(dir.path().join("report.docx"), b"PK\x03\x04\x14\0").unwrap();
+    fs::write(dir.path().join("notes.txt"), "just text").unwrap();
+    // No mapping for .dat, and nothing to compare against for the binary .txt.
+    fs::write(dir.path().join("data.dat"), png).unwrap();
+    fs::write(dir.path().join("blob.txt"), b"\x00\x01\xff").unwrap();
+
+    let (mut avlvec, _) = build_index(dir.path(), HashTable::new(4), false, &mut Vec::new(), None);
+    let flagged = |avlvec: &[Option<_>]| {
+        let mut flagged: Vec<_> = avlvec.iter().flat_map(mismatched_types).map(|(file, detected)| (file.name, detected)).collect();
+        flagged.sort_by(|a, b| a.0.cmp(&b.0));
+        flagged
+    };
+    let expected = vec![("holiday.JPG".to_string(), DetectedType::Zip), ("image.txt".to_string(), DetectedType::Png)];
+    assert_eq!(flagged(&avlvec), expected);
+
+    // Detecting up front gives the same answer from the stored types.
+    detect_types(&mut avlvec);
+    assert_eq!(flagged(&avlvec), expected);
+
+    assert_eq!(expected_type("Docx"), Some(DetectedType::Zip));
+    assert_eq!(expected_type("dat"), None);
+}
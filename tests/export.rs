@@ -0,0 +1,182 @@
+mod common;
+
+use std::fs;
+use std::sync::Arc;
+
+use common::fixture;
+use file_management::export::{collect_entries, export_csv, export_report, to_nested_json};
+use file_management::hash_table::Djb2Hasher;
+use file_management::metadata::format_size;
+use file_management::scan::build_index;
+use file_management::{FileType, HashTable};
+
+#[test]
+fn sorted_exports_of_the_same_tree_are_identical() {
+    let dir = fixture();
+    let out = tempfile::tempdir().unwrap();
+
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(3), false, &mut Vec::new(), None);
+    export_csv(&avlvec, &table, &out.path().join("first.csv"), true).unwrap();
+
+    let other_table = HashTable::with_hasher(17, Arc::new(Djb2Hasher));
+    let (avlvec, table) = build_index(dir.path(), other_table, true, &mut Vec::new(), None);
+    export_csv(&avlvec, &table, &out.path().join("second.csv"), true).unwrap();
+
+    let first = fs::read(out.path().join("first.csv")).unwrap();
+    let second = fs::read(out.path().join("second.csv")).unwrap();
+    assert_eq!(first, second);
+
+    let text = String::from_utf8(first).unwrap();
+    let lines: Vec<&str> = text.split("\r\n").filter(|line| !line.is_empty()).collect();
+    assert_eq!(lines[0], "name,path,type,size,file_count");
+    assert_eq!(lines.len(), 11);
+    assert!(lines[1].starts_with("a.txt,"));
+}
+
+// Splits RFC 4180 text into records: fields are separated by commas and records by CRLF, and a
+// quoted field may hold either, with `""` standing for one quote.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') => {
+                assert!(field.is_empty(), "quote inside an unquoted field: {:?}", field);
+                quoted = true;
+            }
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') => {
+                assert_eq!(chars.next(), Some('\n'), "bare CR outside quotes");
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => {
+                assert_ne!(c, '\n', "bare LF outside quotes");
+                field.push(c);
+            }
+        }
+    }
+    assert!(!quoted && field.is_empty() && record.is_empty(), "unterminated record");
+    records
+}
+
+#[test]
+fn csv_exports_quote_awkward_names_and_parse_back() {
+    let dir = fixture();
+    common::write(dir.path(), "a,\"b\".txt", "quoted");
+    common::write(dir.path(), "docs/two\nlines.txt", "split");
+    let out = tempfile::tempdir().unwrap();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    export_csv(&avlvec, &table, &out.path().join("index.csv"), true).unwrap();
+
+    let text = fs::read_to_string(out.path().join("index.csv")).unwrap();
+    assert!(text.contains("\r\n\"a,\"\"b\"\".txt\",\""));
+    assert!(text.contains("\r\n\"two\nlines.txt\",\""));
+    assert!(text.contains(&format!("\r\nb.rs,{},File,12,0\r\n", dir.path().join("b.rs").display())));
+
+    let records = parse_csv(&text);
+    assert_eq!(records[0], ["name", "path", "type", "size", "file_count"]);
+    let expected: Vec<Vec<String>> = collect_entries(&avlvec, &table, true)
+        .iter()
+        .map(|file| {
+            vec![
+                file.name.clone(),
+                file.path.to_string_lossy().into(),
+                format!("{:?}", file.file_type),
+                file.size.to_string(),
+                file.file_count.to_string(),
+            ]
+        })
+        .collect();
+    assert_eq!(expected.len(), 12);
+    assert_eq!(records[1..], expected);
+}
+
+#[test]
+fn unsorted_entries_list_files_before_directories() {
+    let dir = fixture();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(3), false, &mut Vec::new(), None);
+
+    let entries = collect_entries(&avlvec, &table, false);
+    assert_eq!(entries.len(), 10);
+    let first_directory = entries.iter().position(|file| file.file_type == FileType::Directory).unwrap();
+    assert_eq!(first_directory, 6);
+
+    let sorted = collect_entries(&avlvec, &table, true);
+    assert!(sorted.windows(2).all(|pair| pair[0].path <= pair[1].path));
+}
+
+// Every directory's size and file count agree with what's nested inside it.
+fn check_totals(node: &serde_json::Value) -> (u64, u64) {
+    if node["type"] == "File" {
+        return (node["size"].as_u64().unwrap(), 1);
+    }
+    let mut totals = (0, 0);
+    for child in node["files"].as_array().unwrap().iter().chain(node["directories"].as_array().unwrap()) {
+        let (size, files) = check_totals(child);
+        totals = (totals.0 + size, totals.1 + files);
+    }
+    assert_eq!(totals, (node["size"].as_u64().unwrap(), node["file_count"].as_u64().unwrap()), "{}", node["path"]);
+    totals
+}
+
+#[test]
+fn nested_json_rebuilds_the_hierarchy() {
+    let dir = fixture();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    let root = fs::canonicalize(dir.path()).unwrap();
+    let report = to_nested_json(&root, &avlvec, &table);
+
+    let names = |nodes: &serde_json::Value| -> Vec<String> {
+        nodes.as_array().unwrap().iter().map(|node| node["name"].as_str().unwrap().to_string()).collect()
+    };
+    assert_eq!(report["path"], root.to_string_lossy().as_ref());
+    assert_eq!(report["type"], "Directory");
+    assert_eq!(names(&report["files"]), ["a.txt", "b.rs"]);
+    assert_eq!(names(&report["directories"]), ["docs", "empty", "src"]);
+    assert_eq!(check_totals(&report), (47, 6));
+
+    let src = &report["directories"][2];
+    assert_eq!(names(&src["files"]), ["main.rs"]);
+    assert_eq!(names(&src["directories"][0]["files"]), ["a.txt", "mod.rs"]);
+    assert_eq!(src["directories"][0]["size"], 16);
+    assert!(report["directories"][1]["files"].as_array().unwrap().is_empty());
+    assert!(report["files"][0].get("files").is_none());
+
+    // A directory inside the scan can be reported on its own, using its stored totals.
+    let lib = to_nested_json(&root.join("src/lib"), &avlvec, &table);
+    assert_eq!(check_totals(&lib), (16, 2));
+}
+
+#[test]
+fn reports_list_the_largest_entries_first_with_a_total() {
+    let dir = fixture();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    let out = tempfile::tempdir().unwrap();
+    let report = out.path().join("report.txt");
+    export_report(&avlvec, &table, &report).unwrap();
+
+    let written = fs::read_to_string(&report).unwrap();
+    let lines: Vec<&str> = written.lines().collect();
+    assert_eq!(lines[0], "Size report: 6 files and 4 directories, largest first");
+    assert!(lines[1].trim_start().starts_with("Size  Type"));
+    assert_eq!(lines.len(), 2 + 10 + 1);
+    assert!(lines[2].ends_with(&format!("Directory  {}", dir.path().join("src").display())));
+    assert!(lines[2].trim_start().starts_with("28 B"));
+    // b.rs and src/main.rs tie at 12 bytes and come in path order.
+    assert!(lines[4].ends_with("b.rs") && lines[5].ends_with("main.rs"));
+    assert!(lines[11].ends_with("empty"));
+    assert_eq!(lines[12], "Total: 47 B (47 bytes)");
+
+    assert_eq!(format_size(1536), "1.5 KB");
+    assert_eq!(format_size(20 * 1024 * 1024 * 1024), "20.0 GB");
+}
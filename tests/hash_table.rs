@@ -0,0 +1,319 @@
+mod common;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::fixture;
+use file_management::hash_table::{hash_table_lines, print_largest_directories, Djb2Hasher, FnvHasher, Hasher};
+use file_management::scan::build_hash_table;
+use file_management::{FileMetadata, FileType, HashTable};
+
+fn directory(name: &str) -> FileMetadata {
+    FileMetadata::new(PathBuf::from("/root").join(name), 0, FileType::Directory, None)
+}
+
+// Sends every key to the same bucket, so tests can see exactly where the table puts entries.
+#[derive(Debug)]
+struct FixedBucket(u64);
+
+impl Hasher for FixedBucket {
+    fn hash(&self, _key: &str) -> u64 {
+        self.0
+    }
+}
+
+fn bucket_of(table: &HashTable, entry: &FileMetadata) -> usize {
+    table.buckets.iter().position(|files| files.iter().any(|file| **file == *entry)).unwrap()
+}
+
+#[test]
+fn lookups_borrow_the_stored_entries() {
+    let mut table = HashTable::new(8);
+    for name in ["docs", "src", "empty"] {
+        table.insert(directory(name));
+    }
+    let docs = directory("docs");
+    // Held here as well as in the table, so the count below shows whether anything else keeps one.
+    let stored = Arc::clone(table.buckets[bucket_of(&table, &docs)].iter().find(|file| ***file == docs).unwrap());
+    assert_eq!(Arc::strong_count(&stored), 2);
+
+    // Every lookup hands back the entry in the bucket rather than a copy of it.
+    assert!(std::ptr::eq(table.get("docs", &docs.path, 0).unwrap(), &*stored));
+    assert!(std::ptr::eq(table.find_by_path(&docs.path).unwrap(), &*stored));
+    assert!(std::ptr::eq(table.dirs_under(&docs.path)[0], &*stored));
+    assert!(table.entries().any(|file| std::ptr::eq(file, &*stored)));
+    assert_eq!(Arc::strong_count(&stored), 2);
+
+    // A cloned table shares its entries instead of copying them.
+    let cloned = table.clone();
+    assert!(cloned.buckets.iter().flatten().any(|file| Arc::ptr_eq(file, &stored)));
+    assert_eq!(Arc::strong_count(&stored), 3);
+}
+
+#[test]
+fn load_factor_counts_entries_not_used_buckets() {
+    let mut table = HashTable::new(4);
+    assert!(table.is_empty());
+    assert_eq!(table.load_factor(), 0.0);
+
+    for i in 0..10 {
+        table.insert(directory(&format!("dir{}", i)));
+    }
+    assert_eq!(table.len(), 10);
+    assert!(!table.is_empty());
+    assert_eq!(table.load_factor(), 2.5);
+    assert!(table.bucket_usage() > 0.0 && table.bucket_usage() <= 1.0);
+}
+
+#[test]
+fn a_table_without_buckets_reports_zero() {
+    let table = HashTable::new(0);
+    assert_eq!(table.len(), 0);
+    assert_eq!(table.load_factor(), 0.0);
+    assert_eq!(table.bucket_usage(), 0.0);
+}
+
+#[test]
+fn a_table_without_buckets_still_takes_entries() {
+    let mut table = HashTable::new(0);
+    assert!(table.get("docs", &PathBuf::from("/root/docs"), 0).is_none());
+    assert!(table.remove("docs", &PathBuf::from("/root/docs"), 0).is_none());
+
+    table.insert(directory("docs"));
+    assert_eq!(table.len(), 1);
+    assert!(table.get("docs", &PathBuf::from("/root/docs"), 0).is_some());
+    assert!(table.remove("docs", &PathBuf::from("/root/docs"), 0).is_some());
+    assert_eq!(table.len(), 0);
+}
+
+#[test]
+fn collision_stats_measure_the_chains() {
+    let fnv_bucket = |entry: &FileMetadata| FnvHasher.hash(&HashTable::key(&entry.name, &entry.path, entry.size)) % 4;
+    let landing_in = |bucket: u64, n: usize| -> Vec<FileMetadata> {
+        (0..1000).map(|i| directory(&format!("dir{}", i))).filter(|entry| fnv_bucket(entry) == bucket).take(n).collect()
+    };
+
+    let mut table = HashTable::new(4);
+    assert_eq!(table.collision_stats(), (0, 4, 0.0));
+    // Five entries sharing one bucket and two sharing another leave two buckets empty.
+    for entry in landing_in(1, 5).into_iter().chain(landing_in(3, 2)) {
+        table.insert(entry);
+    }
+    assert_eq!(table.collision_stats(), (5, 2, 3.5));
+
+    let first = landing_in(1, 1).remove(0);
+    assert!(table.remove(&first.name, &first.path, first.size).is_some());
+    assert_eq!(table.collision_stats(), (4, 2, 3.0));
+}
+
+#[test]
+fn keys_are_built_from_name_path_and_size() {
+    assert_eq!(HashTable::key("a", &PathBuf::from("/x/a"), 3), "a\0/x/a\x003");
+    assert_eq!(HashTable::key("a", &PathBuf::from("x/a"), 0), "a\0x/a\x000");
+    // A trailing separator or a `.` component names the same path, so the key doesn't change.
+    assert_eq!(HashTable::key("a", &PathBuf::from("/x/./a/"), 3), "a\0/x/a\x003");
+}
+
+#[test]
+fn the_hasher_decides_the_bucket() {
+    let entries: Vec<FileMetadata> = (0..20).map(|i| directory(&format!("dir{}", i))).collect();
+    let mut fixed = HashTable::with_hasher(5, Arc::new(FixedBucket(7)));
+    for entry in &entries {
+        fixed.insert(entry.clone());
+    }
+    assert_eq!(fixed.buckets[2].len(), 20);
+    assert_eq!(fixed.collision_stats(), (20, 4, 20.0));
+    assert!(entries.iter().all(|entry| fixed.get(&entry.name, &entry.path, entry.size) == Some(entry)));
+
+    let mut fnv = HashTable::new(7);
+    let mut djb2 = HashTable::with_hasher(7, Arc::new(Djb2Hasher));
+    for entry in &entries {
+        fnv.insert(entry.clone());
+        djb2.insert(entry.clone());
+    }
+    assert!(entries.iter().any(|entry| bucket_of(&fnv, entry) != bucket_of(&djb2, entry)));
+    for entry in &entries {
+        let key = HashTable::key(&entry.name, &entry.path, entry.size);
+        assert_eq!(bucket_of(&fnv, entry) as u64, FnvHasher.hash(&key) % 7);
+        assert_eq!(bucket_of(&djb2, entry) as u64, Djb2Hasher.hash(&key) % 7);
+        assert_eq!(djb2.get(&entry.name, &entry.path, entry.size), Some(entry));
+    }
+}
+
+#[test]
+fn entries_are_displayed_in_the_bucket_they_are_found_in() {
+    let mut table = HashTable::new(7);
+    for i in 0..20 {
+        table.insert(directory(&format!("dir{}", i)));
+    }
+
+    let lines = hash_table_lines(&table);
+    for i in 0..20 {
+        let entry = directory(&format!("dir{}", i));
+        assert_eq!(table.get(&entry.name, &entry.path, entry.size), Some(&entry));
+
+        let bucket = bucket_of(&table, &entry);
+        let prefix = format!("Bucket {}: {} ", bucket, entry.path.display());
+        assert!(lines.iter().any(|line| line.starts_with(&prefix)), "{}", prefix);
+    }
+    assert!(table.get("dir0", &PathBuf::from("/root/dir0"), 1).is_none());
+}
+
+#[test]
+fn dirs_under_returns_the_subtree() {
+    let mut table = HashTable::new(5);
+    for path in ["/data", "/data/projects", "/data/projects/a", "/data/projects/a/b", "/data/projects-old", "/other"] {
+        table.insert(FileMetadata::new(PathBuf::from(path), 0, FileType::Directory, None));
+    }
+
+    let mut found: Vec<_> = table.dirs_under(&PathBuf::from("/data/projects")).iter().map(|file| file.path.clone()).collect();
+    found.sort();
+    assert_eq!(found, ["/data/projects", "/data/projects/a", "/data/projects/a/b"].map(PathBuf::from));
+    assert!(table.dirs_under(&PathBuf::from("/missing")).is_empty());
+    assert_eq!(table.dirs_under(&PathBuf::from("/")).len(), 6);
+}
+
+#[test]
+fn inserting_the_same_entry_twice_keeps_one_copy() {
+    let mut table = HashTable::new(4);
+    table.insert(directory("src"));
+    let mut again = directory("src");
+    again.file_count = 3;
+    let replaced = table.insert_or_replace(again);
+
+    assert_eq!(replaced.map(|file| file.file_count), Some(0));
+    assert_eq!(table.len(), 1);
+    assert_eq!(table.buckets.iter().filter(|files| !files.is_empty()).count(), 1);
+    assert_eq!(table.find_by_name("src")[0].file_count, 3);
+    assert!(table.insert_or_replace(directory("docs")).is_none());
+    assert_eq!(table.len(), 2);
+}
+
+#[test]
+fn largest_directories_come_first_with_their_share() {
+    let dir = fixture();
+    let table = build_hash_table(dir.path(), HashTable::new(8), &mut Vec::new(), None).unwrap();
+
+    let names: Vec<_> = table.largest(10).iter().map(|file| (file.name.clone(), file.size)).collect();
+    assert_eq!(names, [("src".to_string(), 28), ("lib".to_string(), 16), ("docs".to_string(), 4), ("empty".to_string(), 0)]);
+    assert_eq!(table.largest(2).len(), 2);
+
+    // 47 bytes scanned in all; lib is inside src, so it doesn't add to the running share.
+    let mut out = Vec::new();
+    print_largest_directories(&mut out, &table.largest(3), 47).unwrap();
+    let lines: Vec<String> = String::from_utf8(out).unwrap().lines().map(|line| line.split_once(" - ").unwrap().1.to_string()).collect();
+    assert_eq!(lines, [
+        "28 bytes - 59.6% (cumulative 59.6%)",
+        "16 bytes - 34.0% (cumulative 59.6%)",
+        "4 bytes - 8.5% (cumulative 68.1%)",
+    ]);
+}
+
+#[test]
+fn growing_tables_double_past_the_load_limit() {
+    let mut table = HashTable::new(1).growing(0.75);
+    for i in 0..20 {
+        table.insert(directory(&format!("dir{}", i)));
+    }
+    // Doubles at the 1st, 2nd, 4th, 7th and 13th insert.
+    assert_eq!(table.resizes(), 5);
+    assert_eq!(table.buckets.len(), 32);
+    assert_eq!(table.len(), 20);
+    for i in 0..20 {
+        let entry = directory(&format!("dir{}", i));
+        assert_eq!(table.get(&entry.name, &entry.path, entry.size), Some(&entry));
+    }
+
+    // Replacing an entry doesn't add to the load.
+    table.insert(directory("dir0"));
+    assert_eq!(table.resizes(), 5);
+
+    let mut fixed = HashTable::new(1);
+    for i in 0..20 {
+        fixed.insert(directory(&format!("dir{}", i)));
+    }
+    assert_eq!((fixed.resizes(), fixed.buckets.len()), (0, 1));
+}
+
+#[test]
+fn the_entry_count_follows_every_change() {
+    let mut table = HashTable::new(4).growing(0.75);
+    let check = |table: &HashTable| assert_eq!(table.len(), table.entries().count());
+    for i in 0..30 {
+        table.insert(directory(&format!("dir{}", i)));
+    }
+    table.insert(directory("dir3"));
+    check(&table);
+    assert_eq!(table.len(), 30);
+
+    assert!(table.remove("dir1", &PathBuf::from("/root/dir1"), 0).is_some());
+    assert!(table.remove("dir1", &PathBuf::from("/root/dir1"), 0).is_none());
+    assert!(table.remove_path(&PathBuf::from("/root/dir2")).is_some());
+    check(&table);
+    assert_eq!(table.len(), 28);
+
+    table.insert(FileMetadata::new(PathBuf::from("/root/dir5/inner"), 0, FileType::Directory, None));
+    assert_eq!(table.remove_under(&PathBuf::from("/root/dir5")).len(), 2);
+    table.shrink_to(2);
+    check(&table);
+    assert_eq!(table.len(), 27);
+
+    let copy = table.clone();
+    table.clear();
+    assert!(table.is_empty());
+    check(&table);
+    assert_eq!(copy.len(), 27);
+}
+
+#[test]
+fn growing_tables_build_in_linear_time() {
+    let entries: Vec<FileMetadata> = (0..40_000).map(|i| directory(&format!("dir{}", i))).collect();
+    let build = |table: HashTable| {
+        let started = Instant::now();
+        let mut table = table;
+        for entry in &entries {
+            table.insert(entry.clone());
+        }
+        (table, started.elapsed())
+    };
+    let (fixed, fixed_time) = build(HashTable::new(65_536));
+    let (grown, grown_time) = build(HashTable::new(1).growing(0.75));
+    assert_eq!(grown.buckets.len(), fixed.buckets.len());
+    assert_eq!((grown.len(), grown.entries().count()), (40_000, 40_000));
+    // Rehashing on the way up costs a small multiple of the fixed build; counting every entry on
+    // each insert made it hundreds of times slower at this size.
+    assert!(grown_time < fixed_time * 10 + Duration::from_millis(200), "{:?} against {:?}", grown_time, fixed_time);
+}
+
+#[test]
+fn cleared_tables_keep_their_buckets_and_shrunk_ones_rehash() {
+    let mut table = HashTable::new(16);
+    for i in 0..12 {
+        table.insert(directory(&format!("dir{}", i)));
+    }
+    let mut cleared = table.clone();
+    let capacity: usize = cleared.buckets.iter().map(Vec::capacity).sum();
+    cleared.clear();
+    assert!(cleared.is_empty());
+    assert_eq!(cleared.buckets.len(), 16);
+    assert_eq!(cleared.buckets.iter().map(Vec::capacity).sum::<usize>(), capacity);
+    cleared.insert(directory("again"));
+    assert_eq!(cleared.len(), 1);
+
+    table.shrink_to(3);
+    assert_eq!(table.buckets.len(), 3);
+    assert_eq!(table.len(), 12);
+    for i in 0..12 {
+        let entry = directory(&format!("dir{}", i));
+        assert_eq!(table.get(&entry.name, &entry.path, entry.size), Some(&entry));
+    }
+    assert_eq!(hash_table_lines(&table).len(), 12);
+
+    // Never grows, and never drops to no buckets at all.
+    table.shrink_to(64);
+    assert_eq!(table.buckets.len(), 3);
+    table.shrink_to(0);
+    assert_eq!(table.buckets.len(), 1);
+    assert_eq!(table.len(), 12);
+}
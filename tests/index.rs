@@ -0,0 +1,548 @@
+mod common;
+
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use common::{all_files, fixture};
+use file_management::avl::{count, merge_avl_trees};
+use file_management::scan::{
+    auto_bucket_count, build_avl_tree, build_hash_table, build_index, build_index_roots, build_index_with,
+    count_directories, count_entries, count_entries_up_to, device_id, format_progress, index_summary, needs_confirmation, normalize_roots,
+    parse_roots, same_device, validate_scan_root, Progress, ScanOptions, LARGE_SCAN_ENTRIES,
+};
+use file_management::metadata::format_count;
+use file_management::search::search_all;
+use file_management::{FileMetadata, FileType, HashTable};
+
+#[test]
+fn avl_trees_hold_every_file_once() {
+    let dir = fixture();
+    let mut avlvec = Vec::new();
+    build_avl_tree(dir.path(), &mut avlvec, &mut Vec::new(), None);
+
+    let files = all_files(&avlvec);
+    let relative: Vec<_> = files.iter().map(|file| file.path.strip_prefix(dir.path()).unwrap().to_path_buf()).collect();
+    let expected = ["a.txt", "b.rs", "docs/a.txt", "src/lib/a.txt", "src/lib/mod.rs", "src/main.rs"];
+    assert_eq!(relative, expected.iter().map(|path| path.into()).collect::<Vec<std::path::PathBuf>>());
+    assert!(files.iter().all(|file| file.file_type == FileType::File));
+    assert_eq!(files.iter().find(|file| file.path == dir.path().join("a.txt")).unwrap().size, 3);
+}
+
+#[test]
+fn each_directory_gets_its_own_tree() {
+    let dir = fixture();
+    let mut avlvec = Vec::new();
+    build_avl_tree(dir.path(), &mut avlvec, &mut Vec::new(), None);
+
+    // root, src, src/lib and docs; "empty" has no files and gets no tree.
+    assert_eq!(avlvec.len(), 4);
+    assert!(avlvec.iter().all(Option::is_some));
+}
+
+#[test]
+fn only_empty_subdirectories_yield_no_trees() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("a").join("b")).unwrap();
+    fs::create_dir(dir.path().join("c")).unwrap();
+
+    for parallel in [false, true] {
+        let (avlvec, table) = build_index(dir.path(), HashTable::new(4), parallel, &mut Vec::new(), None);
+        assert!(avlvec.is_empty());
+        assert!(merge_avl_trees(&avlvec).is_none());
+        assert_eq!(table.len(), 3);
+        assert!(table.entries().all(|file| file.size == 0));
+    }
+}
+
+#[test]
+fn hash_table_holds_directories_with_recursive_sizes() {
+    let dir = fixture();
+    let table = build_hash_table(dir.path(), HashTable::new(16), &mut Vec::new(), None).unwrap();
+
+    let directories: Vec<_> = table.entries().collect();
+    assert_eq!(directories.len(), 4);
+    assert!(directories.iter().all(|file| file.file_type == FileType::Directory));
+
+    assert_eq!(table.find_by_path(&dir.path().join("empty")).unwrap().size, 0);
+    assert_eq!(table.find_by_path(&dir.path().join("src").join("lib")).unwrap().size, 16);
+    assert_eq!(table.find_by_path(&dir.path().join("src")).unwrap().size, 28);
+    assert_eq!(table.find_by_path(&dir.path().join("docs")).unwrap().size, 4);
+}
+
+#[test]
+fn sizes_match_fs_extra() {
+    let dir = fixture();
+    let table = build_hash_table(dir.path(), HashTable::new(16), &mut Vec::new(), None).unwrap();
+
+    for directory in table.entries() {
+        assert_eq!(directory.size, fs_extra::dir::get_size(&directory.path).unwrap(), "{:?}", directory.path);
+    }
+}
+
+#[test]
+fn entries_carry_the_times_recorded_on_disk() {
+    let dir = fixture();
+    let file = dir.path().join("src/lib/a.txt");
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    fs::File::options().write(true).open(&file).unwrap().set_modified(modified).unwrap();
+    let on_disk = fs::metadata(&file).unwrap();
+
+    let entry = FileMetadata::new(file.clone(), on_disk.len(), FileType::File, Some(&on_disk));
+    assert_eq!(entry.modified, Some(modified));
+    assert_eq!(entry.created, on_disk.created().ok());
+    // Without metadata there is nothing to record.
+    let bare = FileMetadata::new(file.clone(), 0, FileType::File, None);
+    assert_eq!((bare.modified, bare.created), (None, None));
+
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    let scanned = &search_all(&avlvec, &file)[0];
+    assert_eq!((scanned.modified, scanned.created), (Some(modified), on_disk.created().ok()));
+    let docs = fs::metadata(dir.path().join("docs")).unwrap();
+    let directory = table.find_by_path(&dir.path().join("docs")).unwrap();
+    assert_eq!((directory.modified, directory.created), (docs.modified().ok(), docs.created().ok()));
+}
+
+#[test]
+fn deep_trees_are_sized_in_one_walk() {
+    // A chain of `depth` folders with one file in each.
+    let chain = |depth: usize| {
+        let dir = tempfile::tempdir().unwrap();
+        let mut folder = dir.path().to_path_buf();
+        for _ in 0..depth {
+            folder.push("d");
+            fs::create_dir(&folder).unwrap();
+            fs::write(folder.join("f"), "x").unwrap();
+        }
+        dir
+    };
+    let timed = |dir: &tempfile::TempDir| {
+        let started = Instant::now();
+        let table = build_hash_table(dir.path(), HashTable::new(1024), &mut Vec::new(), None).unwrap();
+        (table, started.elapsed())
+    };
+    let (shallow, deep) = (chain(100), chain(400));
+    let (_, shallow_time) = timed(&shallow);
+    let (table, deep_time) = timed(&deep);
+
+    let top = table.find_by_path(&deep.path().join("d")).unwrap();
+    assert_eq!((top.size, top.file_count), (400, 400));
+    assert_eq!(top.size, fs_extra::dir::get_size(&top.path).unwrap());
+    // Four times the depth costs about four times as much; sizing each folder by walking it again
+    // made it sixteen.
+    assert!(deep_time < shallow_time * 8 + Duration::from_millis(100), "{:?} against {:?}", deep_time, shallow_time);
+}
+
+#[test]
+fn identical_names_in_different_folders_are_kept_apart() {
+    let dir = fixture();
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(16), false, &mut Vec::new(), None);
+
+    let named: Vec<_> = all_files(&avlvec).into_iter().filter(|file| file.name == "a.txt").collect();
+    assert_eq!(named.len(), 3);
+    let sizes: Vec<u64> = named.iter().map(|file| file.size).collect();
+    assert_eq!(sizes, [3, 4, 6]);
+}
+
+#[test]
+fn empty_root_yields_an_empty_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(4), false, &mut Vec::new(), None);
+
+    assert!(all_files(&avlvec).is_empty());
+    assert!(table.buckets.iter().all(|bucket| bucket.is_empty()));
+}
+
+#[test]
+fn deeply_nested_directories_are_indexed() {
+    let dir = tempfile::tempdir().unwrap();
+    let deep = dir.path().join("1").join("2").join("3").join("4");
+    fs::create_dir_all(&deep).unwrap();
+    fs::write(deep.join("leaf"), "12345").unwrap();
+
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(4), false, &mut Vec::new(), None);
+    assert_eq!(all_files(&avlvec).len(), 1);
+    for depth in ["1", "1/2", "1/2/3", "1/2/3/4"] {
+        assert_eq!(table.find_by_path(&dir.path().join(depth)).unwrap().size, 5);
+    }
+}
+
+#[test]
+fn parallel_scan_matches_sequential_scan() {
+    // Wide and deep enough that the parallel walk actually splits the work: 2,560 files in 72 folders.
+    let dir = tempfile::tempdir().unwrap();
+    for top in 0..8 {
+        for sub in 0..8 {
+            let folder = dir.path().join(format!("top{}", top)).join(format!("sub{}", sub));
+            fs::create_dir_all(&folder).unwrap();
+            for i in 0..40 {
+                fs::write(folder.join(format!("file{}.txt", i)), "x".repeat((top * 7 + sub * 3 + i) % 17)).unwrap();
+            }
+        }
+    }
+    let (sequential_trees, sequential_table) = build_index(dir.path(), HashTable::new(16), false, &mut Vec::new(), None);
+    let (parallel_trees, parallel_table) = build_index(dir.path(), HashTable::new(16), true, &mut Vec::new(), None);
+
+    assert_eq!(all_files(&sequential_trees).len(), 2560);
+    assert_eq!(all_files(&sequential_trees), all_files(&parallel_trees));
+    assert_eq!(sequential_table.len(), 72);
+
+    let mut sequential: Vec<_> = sequential_table.entries().map(|file| (&file.path, file.size, file.file_count)).collect();
+    let mut parallel: Vec<_> = parallel_table.entries().map(|file| (&file.path, file.size, file.file_count)).collect();
+    sequential.sort();
+    parallel.sort();
+    assert_eq!(sequential, parallel);
+}
+
+#[test]
+fn hash_table_insert_and_remove() {
+    let dir = fixture();
+    let mut table = build_hash_table(dir.path(), HashTable::new(3), &mut Vec::new(), None).unwrap();
+
+    let docs = table.find_by_path(&dir.path().join("docs")).unwrap().clone();
+    let removed = table.remove(&docs.name, &docs.path, docs.size).unwrap();
+    assert_eq!(removed, docs);
+    assert!(table.find_by_path(&docs.path).is_none());
+    assert!(table.remove(&docs.name, &docs.path, docs.size).is_none());
+
+    table.insert(docs.clone());
+    assert_eq!(table.find_by_name("docs"), [&docs]);
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_directories_are_skipped_and_reported() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = fixture();
+    let locked = dir.path().join("src");
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    if fs::read_dir(&locked).is_ok() {
+        // Running as root (or with CAP_DAC_READ_SEARCH): permissions can't lock us out.
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        return;
+    }
+
+    for parallel in [false, true] {
+        let mut skipped = Vec::new();
+        let (avlvec, table) = build_index(dir.path(), HashTable::new(16), parallel, &mut skipped, None);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0], locked);
+        assert_eq!(all_files(&avlvec).len(), 3);
+        assert!(table.find_by_path(&locked).is_none());
+        assert_eq!(table.find_by_path(&dir.path().join("docs")).unwrap().size, 4);
+    }
+
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn progress_is_reported_every_n_entries() {
+    let dir = fixture();
+
+    // 6 files and 4 directories, counted once per scan in both modes.
+    for parallel in [false, true] {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let progress = Progress::new(3, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        build_index(dir.path(), HashTable::new(16), parallel, &mut Vec::new(), Some(&progress));
+        assert_eq!(progress.count(), 10);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    let totals = Arc::new(Mutex::new(Vec::new()));
+    let seen = Arc::clone(&totals);
+    let progress = Progress::new(1, move |count| seen.lock().unwrap().push(count));
+    build_hash_table(dir.path(), HashTable::new(16), &mut Vec::new(), Some(&progress));
+    assert_eq!(*totals.lock().unwrap(), (1..=10).collect::<Vec<_>>());
+}
+
+#[test]
+fn cancelling_a_scan_stops_it_and_discards_the_partial_index() {
+    let dir = fixture();
+    for parallel in [false, true] {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&cancel);
+        let progress = Progress::new(2, move |_| flag.store(true, Ordering::SeqCst)).with_cancel(Arc::clone(&cancel));
+
+        let (avlvec, table) = build_index(dir.path(), HashTable::new(16), parallel, &mut Vec::new(), Some(&progress));
+        assert!(progress.cancelled());
+        assert!(progress.count() < 10, "scan went on to {} entries", progress.count());
+        assert!(avlvec.is_empty());
+        assert_eq!(table.len(), 0);
+    }
+
+    let cancel = Arc::new(AtomicBool::new(true));
+    let progress = Progress::new(1, |_| {}).with_cancel(cancel);
+    assert!(build_hash_table(dir.path(), HashTable::new(16), &mut Vec::new(), Some(&progress)).is_none());
+    assert_eq!(progress.count(), 0);
+}
+
+#[test]
+fn auto_sizing_keeps_the_load_factor_under_three_quarters() {
+    let dir = fixture();
+    assert_eq!(count_directories(dir.path()), 4);
+    assert_eq!(auto_bucket_count(4), 8);
+
+    assert_eq!(auto_bucket_count(0), 1);
+    assert_eq!(auto_bucket_count(1), 2);
+    assert_eq!(auto_bucket_count(3), 4);
+    assert_eq!(auto_bucket_count(6), 8);
+    assert_eq!(auto_bucket_count(7), 16);
+    assert_eq!(auto_bucket_count(100), 256);
+    assert_eq!(auto_bucket_count(1536), 2048);
+}
+
+#[test]
+fn only_existing_directories_can_be_scanned() {
+    let dir = fixture();
+    assert!(validate_scan_root(dir.path()).is_ok());
+
+    let file = validate_scan_root(&dir.path().join("a.txt")).unwrap_err();
+    assert_eq!(file.kind(), std::io::ErrorKind::NotADirectory);
+
+    let missing = validate_scan_root(&dir.path().join("missing")).unwrap_err();
+    assert_eq!(missing.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn counts_match_what_was_indexed() {
+    let empty = tempfile::tempdir().unwrap();
+    let flat = tempfile::tempdir().unwrap();
+    for i in 0..25 {
+        fs::write(flat.path().join(format!("f{}", i)), "").unwrap();
+    }
+    let nested = fixture();
+
+    for (dir, files, directories) in [(&empty, 0, 0), (&flat, 25, 0), (&nested, 6, 4)] {
+        let (avlvec, table) = build_index(dir.path(), HashTable::new(4), false, &mut Vec::new(), None);
+        assert_eq!(avlvec.iter().map(count).sum::<usize>(), files);
+        assert_eq!(table.len(), directories);
+        assert_eq!(table.is_empty(), directories == 0);
+    }
+}
+
+#[test]
+fn several_roots_share_one_index() {
+    let first = tempfile::tempdir().unwrap();
+    let second = tempfile::tempdir().unwrap();
+    for root in [first.path(), second.path()] {
+        fs::create_dir(root.join("shared")).unwrap();
+        fs::write(root.join("shared").join("file.txt"), root.to_string_lossy().as_bytes()).unwrap();
+    }
+
+    let input = format!("{}, {}", first.path().display(), second.path().display());
+    let roots = normalize_roots(parse_roots(&input));
+    assert_eq!(roots.len(), 2);
+    let (avlvec, table) = build_index_roots(&roots, HashTable::new(8), false, &mut Vec::new(), None, ScanOptions::default());
+
+    for root in [first.path(), second.path()] {
+        let file = root.join("shared").join("file.txt");
+        assert_eq!(search_all(&avlvec, &file).len(), 1);
+        assert!(table.find_by_path(&root.join("shared")).is_some());
+    }
+    assert_eq!(table.find_by_name("shared").len(), 2);
+    assert_eq!(all_files(&avlvec).len(), 2);
+}
+
+#[test]
+fn nested_and_repeated_roots_are_indexed_once() {
+    let dir = fixture();
+    let roots = vec![dir.path().join("src"), dir.path().to_path_buf(), dir.path().to_path_buf()];
+    assert_eq!(normalize_roots(roots), [dir.path().to_path_buf()]);
+
+    let relative = normalize_roots(vec!["some/dir".into()]);
+    assert!(relative[0].is_absolute());
+}
+
+#[cfg(unix)]
+#[test]
+fn fifos_and_symlinks_are_special() {
+    let dir = fixture();
+    let fifo = dir.path().join("pipe");
+    let status = std::process::Command::new("mkfifo").arg(&fifo).status().unwrap();
+    assert!(status.success());
+    std::os::unix::fs::symlink(dir.path().join("src"), dir.path().join("link")).unwrap();
+
+    for parallel in [false, true] {
+        let (avlvec, table) = build_index(dir.path(), HashTable::new(16), parallel, &mut Vec::new(), None);
+        let files = all_files(&avlvec);
+        for path in [&fifo, &dir.path().join("link")] {
+            let entry = files.iter().find(|file| file.path == *path).unwrap();
+            assert_eq!(entry.file_type, FileType::Special);
+        }
+        // Neither is descended into or counted as a directory.
+        assert_eq!(files.len(), 8);
+        assert_eq!(table.len(), 4);
+    }
+}
+
+#[cfg(unix)]
+fn count_regular_files(dir: &std::path::Path) -> u64 {
+    fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            let file_type = entry.file_type().unwrap();
+            if file_type.is_dir() {
+                count_regular_files(&entry.path())
+            } else {
+                u64::from(file_type.is_file())
+            }
+        })
+        .sum()
+}
+
+#[cfg(unix)]
+#[test]
+fn directories_count_the_regular_files_below_them() {
+    let dir = fixture();
+    std::os::unix::fs::symlink(dir.path().join("a.txt"), dir.path().join("src/link")).unwrap();
+
+    for parallel in [false, true] {
+        let (_, table) = build_index(dir.path(), HashTable::new(16), parallel, &mut Vec::new(), None);
+        assert_eq!(table.len(), 4);
+        for directory in table.entries() {
+            assert_eq!(directory.file_count, count_regular_files(&directory.path), "{:?}", directory.path);
+        }
+        assert_eq!(table.find_by_path(&dir.path().join("src")).unwrap().file_count, 3);
+        assert_eq!(table.find_by_path(&dir.path().join("empty")).unwrap().file_count, 0);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_back_to_an_ancestor_are_not_followed() {
+    let dir = fixture();
+    std::os::unix::fs::symlink(dir.path(), dir.path().join("src/lib/up")).unwrap();
+    std::os::unix::fs::symlink("..", dir.path().join("docs/parent")).unwrap();
+
+    for parallel in [false, true] {
+        let (avlvec, table) = build_index(dir.path(), HashTable::new(16), parallel, &mut Vec::new(), None);
+        assert_eq!(table.len(), 4);
+        assert_eq!(all_files(&avlvec).len(), 8);
+        let src = table.find_by_path(&fs::canonicalize(dir.path()).unwrap().join("src")).unwrap();
+        assert_eq!(src.file_count, 3);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn prescan_counts_what_the_scan_visits() {
+    let dir = fixture();
+    std::os::unix::fs::symlink(dir.path(), dir.path().join("src/up")).unwrap();
+    let total = count_entries(dir.path(), ScanOptions::default());
+    assert_eq!(total, 11);
+
+    for parallel in [false, true] {
+        let progress = Progress::new(100, |_| {});
+        build_index(dir.path(), HashTable::new(16), parallel, &mut Vec::new(), Some(&progress));
+        assert_eq!(progress.count(), total);
+    }
+}
+
+#[test]
+fn progress_shows_a_percentage_once_the_total_is_known() {
+    assert_eq!(format_progress(8400, Some(20000)), "42% (8400/20000)");
+    assert_eq!(format_progress(30, Some(20)), "100% (30/20)");
+    assert_eq!(format_progress(7, None), "7 entries");
+}
+
+#[test]
+fn one_file_system_only_walks_the_root_device() {
+    assert!(same_device(7, Some(7)));
+    assert!(!same_device(7, Some(8)));
+    // A device that can't be read doesn't stop the scan.
+    assert!(same_device(7, None));
+
+    // Everything in the fixture is on one device, so staying on it leaves the index unchanged.
+    let dir = fixture();
+    let options = ScanOptions { one_file_system: true, ..ScanOptions::default() };
+    for parallel in [false, true] {
+        let (avlvec, table) = build_index_with(dir.path(), HashTable::new(8), parallel, &mut Vec::new(), None, options);
+        assert_eq!(all_files(&avlvec).len(), 6);
+        assert_eq!(table.len(), 4);
+    }
+    assert_eq!(count_entries(dir.path(), options), 10);
+
+    #[cfg(unix)]
+    {
+        let root = device_id(dir.path()).unwrap();
+        assert!(same_device(root, device_id(&dir.path().join("src/lib"))));
+    }
+}
+
+#[test]
+fn only_scans_past_the_threshold_are_confirmed() {
+    assert!(!needs_confirmation(0, LARGE_SCAN_ENTRIES));
+    assert!(!needs_confirmation(LARGE_SCAN_ENTRIES, LARGE_SCAN_ENTRIES));
+    assert!(needs_confirmation(LARGE_SCAN_ENTRIES + 1, LARGE_SCAN_ENTRIES));
+
+    // The estimate stops just past the limit instead of counting everything.
+    let dir = fixture();
+    let options = ScanOptions::default();
+    assert_eq!(count_entries_up_to(dir.path(), options, 4), 4);
+    assert_eq!(count_entries_up_to(dir.path(), options, 100), 10);
+    assert!(needs_confirmation(count_entries_up_to(dir.path(), options, 6), 5));
+    assert!(!needs_confirmation(count_entries_up_to(dir.path(), options, 11), 10));
+}
+
+#[cfg(unix)]
+#[test]
+fn allocated_sizes_are_recorded_next_to_apparent_sizes() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = fixture();
+    // A 1 MiB hole: on filesystems with sparse files it takes up (almost) nothing.
+    let sparse = fs::File::create(dir.path().join("src/sparse.bin")).unwrap();
+    sparse.set_len(1 << 20).unwrap();
+    drop(sparse);
+
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    assert!(all_files(&avlvec).iter().all(|file| file.allocated.is_none()));
+    assert!(table.entries().all(|directory| directory.allocated.is_none()));
+
+    let options = ScanOptions { allocated: true, ..ScanOptions::default() };
+    for parallel in [false, true] {
+        let (avlvec, table) = build_index_with(dir.path(), HashTable::new(8), parallel, &mut Vec::new(), None, options);
+        let files = all_files(&avlvec);
+        for file in &files {
+            let blocks = fs::symlink_metadata(&file.path).unwrap().blocks();
+            assert_eq!(file.allocated, Some(blocks * 512), "{:?}", file.path);
+        }
+        let sparse = files.iter().find(|file| file.name == "sparse.bin").unwrap();
+        assert_eq!(sparse.size, 1 << 20);
+
+        for directory in table.entries() {
+            let below: u64 = files.iter().filter(|file| file.path.starts_with(&directory.path)).map(|file| file.allocated.unwrap()).sum();
+            assert_eq!(directory.allocated, Some(below), "{:?}", directory.path);
+        }
+        let src = table.find_by_path(&dir.path().join("src")).unwrap();
+        assert_eq!(src.size, 28 + (1 << 20));
+        if sparse.allocated.unwrap() < sparse.size {
+            assert!(src.allocated.unwrap() < src.size);
+        }
+    }
+}
+
+#[test]
+fn the_summary_counts_files_and_directories() {
+    let dir = fixture();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    assert_eq!(index_summary(&avlvec, &table), "Indexed 6 files and 4 directories totaling 47 B.");
+
+    let single = tempfile::tempdir().unwrap();
+    fs::create_dir(single.path().join("only")).unwrap();
+    fs::write(single.path().join("only/big.bin"), vec![0; 3 * 1024]).unwrap();
+    let (avlvec, table) = build_index(single.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    assert_eq!(index_summary(&avlvec, &table), "Indexed 1 file and 1 directory totaling 3.0 KB.");
+
+    assert_eq!(format_count(0), "0");
+    assert_eq!(format_count(999), "999");
+    assert_eq!(format_count(1234), "1,234");
+    assert_eq!(format_count(1234567), "1,234,567");
+}
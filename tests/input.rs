@@ -0,0 +1,27 @@
+use std::io::Cursor;
+
+use file_management::input::{parse_number, read_number, Answer};
+
+#[test]
+fn blank_lines_parse_as_nothing() {
+    assert!(parse_number::<usize>("").is_none());
+    assert!(parse_number::<usize>("  \n").is_none());
+}
+
+#[test]
+fn numbers_parse_and_other_text_is_rejected() {
+    assert_eq!(parse_number::<usize>(" 12\n").unwrap().ok(), Some(12));
+    assert!(parse_number::<usize>("twelve").unwrap().is_err());
+    assert!(parse_number::<usize>("-1").unwrap().is_err());
+}
+
+#[test]
+fn reading_asks_again_until_a_number_or_blank_line() {
+    let mut input = Cursor::new("abc\n3.5\n7\n");
+    assert_eq!(read_number::<usize>(&mut input), Answer::Value(7));
+
+    let mut input = Cursor::new("abc\n\n7\n");
+    assert_eq!(read_number::<usize>(&mut input), Answer::Blank);
+    assert_eq!(read_number::<usize>(&mut input), Answer::Value(7));
+    assert_eq!(read_number::<usize>(&mut input), Answer::Closed);
+}
@@ -0,0 +1,176 @@
+mod common;
+
+use std::path::Path;
+
+use common::{all_files, fixture};
+use file_management::avl::{avl_tree_lines, insert_into_avl_tree, merge_avl_trees, print_avl_tree, print_avl_tree_bfs};
+use file_management::hash_table::{hash_table_lines, print_hash_table};
+use file_management::scan::build_index;
+use file_management::color::ColorChoice;
+use file_management::search::{
+    print_directory_tree, print_directory_tree_colored, print_entries, print_entries_colored, search_avl_by_name,
+};
+use file_management::{AVLTreeNode, FileMetadata, FileType, HashTable};
+
+#[test]
+fn relative_scans_list_absolute_paths() {
+    // Created inside the working directory so it can be named relative to it.
+    let dir = tempfile::tempdir_in(".").unwrap();
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    common::write(dir.path(), "sub/file.txt", "x");
+    common::write(dir.path(), "top.txt", "y");
+    let relative = Path::new(".").join(dir.path().file_name().unwrap());
+
+    let (avlvec, table) = build_index(&relative, HashTable::new(4), false, &mut Vec::new(), None);
+    let absolute = dir.path().canonicalize().unwrap();
+
+    let files = all_files(&avlvec);
+    assert_eq!(files.len(), 2);
+    assert!(files.iter().all(|file| file.path.is_absolute() && file.path.starts_with(&absolute)));
+
+    let mut lines = Vec::new();
+    for root in &avlvec {
+        avl_tree_lines(root, 0, &mut lines);
+    }
+    assert!(lines.iter().any(|line| line.contains(&format!("{:?}", absolute.join("top.txt")))));
+
+    let lines = hash_table_lines(&table);
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].contains(&absolute.join("sub").display().to_string()));
+}
+
+#[test]
+fn printers_write_to_any_writer() {
+    let dir = fixture();
+    let root = std::fs::canonicalize(dir.path()).unwrap();
+    let (avlvec, table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+
+    let mut out = Vec::new();
+    print_directory_tree(&mut out, &avlvec, &table, &root, Some(1)).unwrap();
+    let rendered = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = rendered.lines().collect();
+    assert_eq!(lines[0], root.display().to_string());
+    assert_eq!(lines[1..], ["├── a.txt (3 bytes)", "├── b.rs (12 bytes)", "├── docs/ (4 bytes)", "├── empty/ (0 bytes)", "└── src/ (28 bytes)"]);
+
+    let merged = merge_avl_trees(&avlvec);
+    let mut out = Vec::new();
+    print_avl_tree(&mut out, &merged, 0).unwrap();
+    let rendered = String::from_utf8(out).unwrap();
+    assert_eq!(rendered.lines().count(), 6);
+    assert!(rendered.contains("Name: mod.rs"));
+    assert!(!rendered.contains("UNBALANCED"));
+
+    let mut out = Vec::new();
+    print_avl_tree_bfs(&mut out, &merged).unwrap();
+    assert!(String::from_utf8(out).unwrap().starts_with("Level 0: "));
+
+    let mut out = Vec::new();
+    search_avl_by_name(&mut out, &merged, "a.txt".to_string()).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap().lines().count(), 3);
+
+    let mut out = Vec::new();
+    print_hash_table(&mut out, &table).unwrap();
+    let rendered = String::from_utf8(out).unwrap();
+    assert!(rendered.contains("Entries: 4"));
+
+    let mut out = Vec::new();
+    print_entries(&mut out, &all_files(&avlvec)[..1]).unwrap();
+    assert!(String::from_utf8(out).unwrap().starts_with(&format!("File {} - 3 bytes - ", root.join("a.txt").display())));
+}
+
+// A tree of empty files with these names, inserted in order.
+fn tree_of(names: &[&str]) -> Option<Box<AVLTreeNode>> {
+    names.iter().fold(None, |root, name| {
+        Some(insert_into_avl_tree(root, FileMetadata::new(Path::new("/t").join(name), 0, FileType::File, None)))
+    })
+}
+
+#[test]
+fn breadth_first_printing_lists_each_level_left_to_right() {
+    let mut out = Vec::new();
+    print_avl_tree_bfs(&mut out, &tree_of(&["a", "b", "c", "d", "e", "f", "g"])).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "Level 0: d (h=3)\nLevel 1: b (h=2) f (h=2)\nLevel 2: a (h=1) c (h=1) e (h=1) g (h=1)\n"
+    );
+
+    // Levels with gaps still list only the nodes that are there.
+    let mut out = Vec::new();
+    print_avl_tree_bfs(&mut out, &tree_of(&["b", "a", "c", "d"])).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "Level 0: b (h=3)\nLevel 1: a (h=1) c (h=2)\nLevel 2: d (h=1)\n");
+
+    let mut out = Vec::new();
+    print_avl_tree_bfs(&mut out, &None).unwrap();
+    assert!(out.is_empty());
+}
+
+#[test]
+fn tree_lines_show_each_nodes_height_and_balance() {
+    let annotations = |root: &Option<Box<AVLTreeNode>>| -> Vec<String> {
+        let mut lines = Vec::new();
+        avl_tree_lines(root, 0, &mut lines);
+        lines.iter().map(|line| line[line.rfind(" [").unwrap() + 1..].to_string()).collect()
+    };
+    // Printed right subtree first: d, c, b, a.
+    assert_eq!(
+        annotations(&tree_of(&["b", "a", "c", "d"])),
+        ["[height 1, balance 0]", "[height 2, balance -1]", "[height 3, balance -1]", "[height 1, balance 0]"]
+    );
+
+    let mut root = tree_of(&["b", "a"]);
+    assert_eq!(annotations(&root), ["[height 2, balance 1]", "[height 1, balance 0]"]);
+    // A node whose subtrees differ by more than one is flagged.
+    if let Some(node) = root.as_mut() {
+        node.height = 3;
+        node.left.as_mut().unwrap().left = tree_of(&["0"]);
+        node.left.as_mut().unwrap().height = 2;
+    }
+    assert_eq!(annotations(&root), ["[height 3, balance 2] <-- UNBALANCED", "[height 2, balance 1]", "[height 1, balance 0]"]);
+}
+
+#[test]
+fn color_is_only_added_when_enabled() {
+    assert_eq!(ColorChoice::parse("always"), Some(ColorChoice::Always));
+    assert_eq!(ColorChoice::parse("sometimes"), None);
+    assert!(ColorChoice::Auto.enabled(true, false));
+    assert!(!ColorChoice::Auto.enabled(false, false));
+    assert!(!ColorChoice::Auto.enabled(true, true));
+    assert!(ColorChoice::Always.enabled(false, true));
+    assert!(!ColorChoice::Never.enabled(true, false));
+
+    let dir = fixture();
+    let root = std::fs::canonicalize(dir.path()).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+        std::fs::set_permissions(root.join("b.rs"), std::fs::Permissions::from_mode(0o755)).unwrap();
+        symlink(root.join("a.txt"), root.join("link")).unwrap();
+    }
+    let (avlvec, table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+    let mut entries = all_files(&avlvec);
+    entries.extend(table.entries().cloned());
+
+    let render = |color: bool| {
+        let mut out = Vec::new();
+        print_entries_colored(&mut out, &entries, color).unwrap();
+        print_directory_tree_colored(&mut out, &avlvec, &table, &root, None, color).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    let plain = render(false);
+    assert!(!plain.contains('\x1b'));
+    let mut out = Vec::new();
+    print_entries(&mut out, &entries).unwrap();
+    print_directory_tree(&mut out, &avlvec, &table, &root, None).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), plain);
+
+    let colored = render(true);
+    assert!(colored.contains(&format!("\x1b[34m{}\x1b[0m", root.join("src").display())));
+    assert!(colored.contains("\x1b[34msrc\x1b[0m/"));
+    // Plain files keep their names as they are.
+    assert!(colored.contains(&format!("File {} - ", root.join("a.txt").display())));
+    #[cfg(unix)]
+    {
+        assert!(colored.contains("\x1b[32mb.rs\x1b[0m"));
+        assert!(colored.contains("\x1b[36mlink\x1b[0m"));
+    }
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use std::fs;
+use std::io::ErrorKind;
+
+use common::fixture;
+use file_management::nav::Navigator;
+use file_management::scan::{build_index, resolve_path};
+use file_management::{AVLTreeNode, HashTable};
+
+fn names(navigator: &Navigator, index: &(Vec<Option<Box<AVLTreeNode>>>, HashTable)) -> Vec<String> {
+    navigator.ls(&index.0, &index.1).into_iter().map(|entry| entry.name).collect()
+}
+
+#[test]
+fn cd_and_ls_walk_the_index() {
+    let dir = fixture();
+    let root = fs::canonicalize(dir.path()).unwrap();
+    let index = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+    let table = &index.1;
+    let mut navigator = Navigator::new(std::slice::from_ref(&root));
+
+    assert_eq!(navigator.current(), root);
+    assert_eq!(names(&navigator, &index), ["a.txt", "b.rs", "docs", "empty", "src"]);
+
+    navigator.cd("src", table).unwrap();
+    assert_eq!(names(&navigator, &index), ["lib", "main.rs"]);
+    navigator.cd("./lib", table).unwrap();
+    assert_eq!(navigator.current(), root.join("src/lib"));
+    assert_eq!(names(&navigator, &index), ["a.txt", "mod.rs"]);
+
+    navigator.cd("../../docs", table).unwrap();
+    assert_eq!(names(&navigator, &index), ["a.txt"]);
+    navigator.cd("../empty", table).unwrap();
+    assert!(names(&navigator, &index).is_empty());
+
+    // Files and missing directories can't be entered, and the current directory stays put.
+    assert_eq!(navigator.cd("../a.txt", table).unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(navigator.cd("missing", table).unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(navigator.cd("../..", table).unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(navigator.current(), root.join("empty"));
+
+    navigator.cd(&root.join("src/lib").display().to_string(), table).unwrap();
+    assert_eq!(navigator.current(), root.join("src/lib"));
+    navigator.cd("..", table).unwrap();
+    assert_eq!(navigator.current(), root.join("src"));
+    navigator.cd("", table).unwrap();
+    assert_eq!(navigator.current(), root);
+}
+
+#[test]
+fn relative_paths_resolve_against_the_current_directory() {
+    let dir = fixture();
+    let root = fs::canonicalize(dir.path()).unwrap();
+    let (_, table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+    let mut navigator = Navigator::new(std::slice::from_ref(&root));
+    let cwd = std::env::current_dir().unwrap();
+
+    // At the root, "a.txt" is the one there; inside src/lib it's that directory's own.
+    assert_eq!(resolve_path("a.txt", &navigator.bases(), &cwd), root.join("a.txt"));
+    navigator.cd("src/lib", &table).unwrap();
+    assert_eq!(resolve_path("a.txt", &navigator.bases(), &cwd), root.join("src/lib/a.txt"));
+    // Names missing from the current directory still fall back to the roots.
+    assert_eq!(resolve_path("b.rs", &navigator.bases(), &cwd), root.join("b.rs"));
+    // Something about to be created lands in the current directory.
+    assert_eq!(resolve_path("new.txt", &navigator.bases(), &cwd), root.join("src/lib/new.txt"));
+}
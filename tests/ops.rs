@@ -0,0 +1,311 @@
+use std::fs;
+use std::io::{self, Cursor};
+
+use file_management::ops;
+use file_management::scan::build_index;
+use file_management::search::{dirs_by_glob, search_all, search_by_glob};
+use file_management::{FileMetadata, FileType, HashTable};
+
+#[test]
+fn multi_line_input_is_written_and_appended() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "old").unwrap();
+    let file = FileMetadata::new(path.clone(), 3, FileType::File, None);
+
+    let contents = ops::read_contents(Cursor::new("first line\nsecond line\n")).unwrap();
+    ops::write_file(&file, &contents).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "first line\nsecond line\n");
+
+    let more = ops::read_contents(Cursor::new("third\nfourth")).unwrap();
+    ops::append_file(&file, &more).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "first line\nsecond line\nthird\nfourth");
+}
+
+#[test]
+fn writes_replace_the_file_without_leaving_a_temporary_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "old contents that are longer").unwrap();
+    let file = FileMetadata::new(path.clone(), 28, FileType::File, None);
+
+    ops::write_file(&file, "new").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+    let names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(names, ["notes.txt"]);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        ops::write_atomically(&path, b"again").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap(), "again");
+
+    // A target that's a directory can't be renamed over; the temporary file goes away regardless.
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    assert!(ops::write_atomically(&dir.path().join("sub"), b"x").is_err());
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+}
+
+#[test]
+fn read_only_files_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("locked.txt");
+    fs::write(&path, "keep").unwrap();
+    let mut permissions = fs::metadata(&path).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&path, permissions).unwrap();
+
+    // Both flags come from the file's own permissions, the same way a scan reads them.
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(4), false, &mut Vec::new(), None);
+    let file = search_all(&avlvec, &path).remove(0);
+    assert!(file.readonly);
+    #[cfg(unix)]
+    assert_eq!(file.mode & 0o222, 0);
+
+    assert!(ops::write_file(&file, "x").is_err());
+    assert!(ops::append_file(&file, "x").is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "keep");
+
+    // Made writable again, the same file is accepted.
+    let mut permissions = fs::metadata(&path).unwrap().permissions();
+    // On Unix this makes the file writable by everyone, which is fine inside a temporary directory.
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    fs::set_permissions(&path, permissions).unwrap();
+    let file = FileMetadata::new(path.clone(), 4, FileType::File, Some(&fs::metadata(&path).unwrap()));
+    assert!(!file.readonly);
+    ops::append_file(&file, "!").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "keep!");
+}
+
+#[test]
+fn no_editor_falls_back_to_stdin() {
+    assert!(ops::compose_in_editor(None).unwrap().is_none());
+    assert!(ops::compose_in_editor(Some("  ")).unwrap().is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn editor_output_is_returned() {
+    assert_eq!(ops::compose_in_editor(Some("true")).unwrap().as_deref(), Some(""));
+    assert!(ops::compose_in_editor(Some("false")).is_err());
+}
+
+#[test]
+fn delete_uses_the_indexed_path() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("docs")).unwrap();
+    fs::write(dir.path().join("docs").join("a.txt"), "a").unwrap();
+    fs::write(dir.path().join("docs").join("b.txt"), "b").unwrap();
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(4), false, &mut Vec::new(), None);
+
+    let typed = dir.path().join("docs").join(".").join("a.txt");
+    let matches = search_all(&avlvec, &typed);
+    assert_eq!(matches.len(), 1);
+
+    let deleted = ops::delete_indexed_file(&matches[0]).unwrap();
+    assert_eq!(deleted, matches[0].path);
+    assert_eq!(deleted, dir.path().join("docs").join("a.txt"));
+    assert!(!deleted.exists());
+    assert!(dir.path().join("docs").join("b.txt").exists());
+}
+
+#[test]
+fn delete_refuses_entries_that_are_no_longer_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("was-a-file");
+    fs::write(&path, "x").unwrap();
+    let file = FileMetadata::new(path.clone(), 1, FileType::File, None);
+
+    fs::remove_file(&path).unwrap();
+    fs::create_dir(&path).unwrap();
+    assert!(ops::delete_indexed_file(&file).is_err());
+    assert!(path.is_dir());
+
+    let directory = FileMetadata::new(path.clone(), 0, FileType::Directory, None);
+    assert!(ops::delete_indexed_file(&directory).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn dangling_symlinks_are_reported_not_read() {
+    let dir = tempfile::tempdir().unwrap();
+    std::os::unix::fs::symlink(dir.path().join("gone"), dir.path().join("link")).unwrap();
+    fs::write(dir.path().join("real"), "contents").unwrap();
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(4), false, &mut Vec::new(), None);
+
+    let link = &search_all(&avlvec, &dir.path().join("link"))[0];
+    assert_eq!(link.file_type, FileType::Special);
+    let error = ops::read_indexed_file(link).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    assert_eq!(error.to_string(), "broken symlink");
+
+    let real = &search_all(&avlvec, &dir.path().join("real"))[0];
+    assert_eq!(ops::read_indexed_file(real).unwrap(), "contents");
+
+    fs::remove_file(dir.path().join("real")).unwrap();
+    assert!(ops::read_indexed_file(real).is_err());
+}
+
+#[test]
+fn creating_a_file_never_replaces_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("new.txt");
+    ops::create_file(&path).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+    fs::write(&path, "keep me").unwrap();
+    let err = ops::create_file(&path).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "keep me");
+
+    let orphan = dir.path().join("missing/new.txt");
+    let err = ops::create_file(&orphan).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert!(err.to_string().contains("missing"), "{}", err);
+    assert!(!orphan.exists());
+}
+
+#[test]
+fn nested_directories_are_created_in_one_step() {
+    let dir = tempfile::tempdir().unwrap();
+    let nested = dir.path().join("a/b/c");
+
+    let created = ops::create_directory_all(&nested).unwrap();
+    assert_eq!(created, [dir.path().join("a"), dir.path().join("a/b"), nested.clone()]);
+    assert!(nested.is_dir());
+
+    // Creating it again is fine and creates nothing.
+    assert!(ops::create_directory_all(&nested).unwrap().is_empty());
+    assert_eq!(ops::create_directory(&nested).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+}
+
+#[test]
+fn bulk_delete_removes_only_matching_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = fs::canonicalize(dir.path()).unwrap();
+    fs::create_dir_all(root.join("sub")).unwrap();
+    fs::create_dir_all(root.join("cache.tmp")).unwrap();
+    for (name, contents) in [("a.tmp", "aa"), ("sub/b.tmp", "bbb"), ("keep.txt", "k"), ("sub/keep.rs", "kk"), ("cache.tmp/inner", "i")] {
+        fs::write(root.join(name), contents).unwrap();
+    }
+    let (mut avlvec, mut table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+    let roots = vec![root.clone()];
+
+    // The directory matches too, but isn't deleted without permission.
+    let mut matches = search_by_glob(&avlvec, "*.tmp", false).unwrap();
+    matches.extend(dirs_by_glob(&table, "*.tmp", false).unwrap());
+    assert_eq!(matches.len(), 3);
+    let result = ops::delete_matches(&mut avlvec, &mut table, &roots, &matches, false);
+
+    assert_eq!(result.deleted, [root.join("a.tmp"), root.join("sub/b.tmp")]);
+    assert_eq!(result.failed.len(), 1);
+    assert_eq!(result.failed[0].0, root.join("cache.tmp"));
+    for kept in ["keep.txt", "sub/keep.rs", "cache.tmp/inner"] {
+        assert!(root.join(kept).exists(), "{}", kept);
+    }
+    assert!(!root.join("a.tmp").exists() && !root.join("sub/b.tmp").exists());
+
+    // The index no longer has them and directory sizes were adjusted.
+    assert!(search_by_glob(&avlvec, "*.tmp", false).unwrap().is_empty());
+    assert_eq!(table.find_by_path(&root.join("sub")).unwrap().size, 2);
+
+    let directories = dirs_by_glob(&table, "*.tmp", false).unwrap();
+    let result = ops::delete_matches(&mut avlvec, &mut table, &roots, &directories, true);
+    assert_eq!(result.deleted, [root.join("cache.tmp")]);
+    assert!(!root.join("cache.tmp").exists());
+    assert!(table.find_by_path(&root.join("cache.tmp")).is_none());
+}
+
+#[test]
+fn byte_ranges_are_cut_at_the_end_of_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.bin");
+    let bytes: Vec<u8> = (0..=255).collect();
+    fs::write(&path, &bytes).unwrap();
+
+    assert_eq!(ops::read_range(&path, 16, 8).unwrap(), &bytes[16..24]);
+    assert_eq!(ops::read_range(&path, 0, 0).unwrap(), Vec::<u8>::new());
+    assert_eq!(ops::read_range(&path, 250, 100).unwrap(), &bytes[250..]);
+    assert!(ops::read_range(&path, 256, 10).unwrap().is_empty());
+    assert!(ops::read_range(&path, 10_000, 10).unwrap().is_empty());
+    assert!(ops::read_range(&dir.path().join("missing"), 0, 10).is_err());
+
+    let dump = ops::hex_dump_at(&ops::read_range(&path, 0x41, 3).unwrap(), 0x41);
+    assert!(dump.starts_with("00000041  41 42 43 "), "{}", dump);
+    assert!(dump.ends_with("|ABC|\n"), "{}", dump);
+}
+
+#[test]
+fn hex_dump_shows_offsets_bytes_and_ascii() {
+    let bytes: Vec<u8> = b"Hello, world!\n\x00\x01\xffABC".to_vec();
+    assert_eq!(
+        ops::hex_dump(&bytes),
+        "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 01  |Hello, world!...|\n\
+         00000010  ff 41 42 43                                       |.ABC|\n"
+    );
+    assert_eq!(ops::hex_dump(&[]), "");
+}
+
+#[test]
+fn line_ranges_are_numbered_and_cut_at_the_end() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log.txt");
+    let text: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+    fs::write(&path, text).unwrap();
+
+    assert_eq!(
+        ops::read_lines(&path, 3, 5).unwrap(),
+        [(3, "line 3".to_string()), (4, "line 4".to_string()), (5, "line 5".to_string())]
+    );
+    assert_eq!(ops::read_lines(&path, 9, 100).unwrap(), [(9, "line 9".to_string()), (10, "line 10".to_string())]);
+    assert!(ops::read_lines(&path, 6, 5).unwrap().is_empty());
+    assert!(ops::read_lines(&path, 11, 20).unwrap().is_empty());
+    assert_eq!(ops::read_lines(&path, 0, 1).unwrap(), [(1, "line 1".to_string())]);
+}
+
+#[test]
+fn renaming_a_directory_moves_everything_below_it_in_the_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = fs::canonicalize(dir.path()).unwrap();
+    fs::create_dir_all(root.join("photos/2023/trip")).unwrap();
+    fs::create_dir_all(root.join("photos-old")).unwrap();
+    for (name, contents) in [("photos/a.jpg", "aa"), ("photos/2023/b.jpg", "bbb"), ("photos/2023/trip/c.jpg", "c"), ("photos-old/d.jpg", "dd")] {
+        fs::write(root.join(name), contents).unwrap();
+    }
+    let (mut avlvec, mut table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+
+    let renamed = ops::rename_directory(&mut avlvec, &mut table, &root.join("photos"), "pictures.d").unwrap();
+    assert_eq!(renamed, root.join("pictures.d"));
+    assert!(root.join("pictures.d/2023/trip/c.jpg").exists());
+
+    // The index now matches a fresh scan, down to the renamed directory's name and extension.
+    let (fresh_trees, fresh_table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+    let paths = |avlvec: &[Option<Box<file_management::AVLTreeNode>>]| {
+        let mut paths: Vec<_> = search_by_glob(avlvec, "*", false).unwrap().into_iter().map(|file| file.path).collect();
+        paths.sort();
+        paths
+    };
+    assert_eq!(paths(&avlvec), paths(&fresh_trees));
+    assert!(paths(&avlvec).contains(&root.join("photos-old/d.jpg")));
+    for path in ["pictures.d", "pictures.d/2023", "pictures.d/2023/trip", "photos-old"] {
+        let stored = table.find_by_path(&root.join(path)).unwrap();
+        let expected = fresh_table.find_by_path(&root.join(path)).unwrap();
+        assert_eq!((&stored.name, &stored.extension, stored.size), (&expected.name, &expected.extension, expected.size), "{}", path);
+    }
+    assert_eq!(table.len(), fresh_table.len());
+    assert!(table.find_by_path(&root.join("photos")).is_none());
+    assert_eq!(search_all(&avlvec, &root.join("pictures.d/2023/b.jpg")).len(), 1);
+
+    // Bad names and existing targets are refused before anything is touched.
+    for name in ["", "..", "a/b"] {
+        let e = ops::rename_directory(&mut avlvec, &mut table, &root.join("pictures.d"), name).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{:?}", name);
+    }
+    let e = ops::rename_directory(&mut avlvec, &mut table, &root.join("pictures.d"), "photos-old").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+    assert!(root.join("pictures.d").is_dir() && root.join("photos-old/d.jpg").exists());
+}
@@ -0,0 +1,211 @@
+mod common;
+
+use std::path::Path;
+
+use common::fixture;
+use file_management::avl::insert_into_avl_tree;
+use file_management::metadata::{
+    is_too_long, long_path_form, name_key, normal_path, path_key, same_path, short_path_form, CASE_INSENSITIVE_PATHS,
+};
+use file_management::ops;
+use file_management::scan::{
+    build_index, canonical_root, expand_path, expand_path_with, parse_roots, resolve_path, validate_scan_root,
+};
+use file_management::search::search_all;
+use file_management::{FileMetadata, FileType, HashTable};
+
+#[test]
+fn dot_components_are_ignored() {
+    assert!(same_path(Path::new("/a/./b"), Path::new("/a/b")));
+    assert!(same_path(Path::new("./a/b/"), Path::new("a/b")));
+    assert!(!same_path(Path::new("/a/b"), Path::new("/a/c")));
+}
+
+#[test]
+fn case_folding_follows_the_platform() {
+    assert_eq!(name_key("Report.TXT") == name_key("report.txt"), CASE_INSENSITIVE_PATHS);
+    assert_eq!(same_path(Path::new("/Foo/Bar"), Path::new("/foo/bar")), CASE_INSENSITIVE_PATHS);
+    assert_eq!(path_key(Path::new("/foo/bar")), Path::new("/foo/bar"));
+}
+
+#[test]
+fn lookups_match_the_platform_case_rules() {
+    let dir = fixture();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(16), false, &mut Vec::new(), None);
+
+    let upper = dir.path().join("SRC").join("MAIN.RS");
+    assert_eq!(search_all(&avlvec, &upper).len(), usize::from(CASE_INSENSITIVE_PATHS));
+    assert_eq!(table.find_by_path(&dir.path().join("DOCS")).is_some(), CASE_INSENSITIVE_PATHS);
+
+    let dotted = dir.path().join(".").join("src").join("main.rs");
+    assert_eq!(search_all(&avlvec, &dotted).len(), 1);
+}
+
+#[test]
+fn table_lookups_accept_every_spelling_of_a_path() {
+    let mut table = HashTable::new(8);
+    table.insert(FileMetadata::new("/data/Reports".into(), 7, FileType::Directory, None));
+
+    let dotted = Path::new("/data/./Reports");
+    assert_eq!(HashTable::key("Reports", dotted, 7), ["Reports", "/data/Reports", "7"].join("\0"));
+    assert!(table.get("Reports", dotted, 7).is_some());
+    let upper = Path::new("/DATA/REPORTS");
+    assert_eq!(table.get("REPORTS", upper, 7).is_some(), CASE_INSENSITIVE_PATHS);
+
+    // Replacing through another spelling keeps one entry.
+    table.insert(FileMetadata::new(dotted.into(), 7, FileType::Directory, None));
+    assert_eq!(table.len(), 1);
+    assert!(table.remove("Reports", dotted, 7).is_some());
+    assert!(table.is_empty());
+
+    table.insert(FileMetadata::new("/data/Reports/q1".into(), 0, FileType::Directory, None));
+    assert_eq!(table.remove_under(Path::new("/data/./Reports")).len(), 1);
+    table.insert(FileMetadata::new("/data/Reports".into(), 7, FileType::Directory, None));
+    assert!(table.remove_path(dotted).is_some());
+}
+
+#[test]
+fn relative_input_finds_the_absolute_entry() {
+    let dir = fixture();
+    let root = canonical_root(dir.path());
+    let roots = vec![root.clone()];
+    let (avlvec, table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+    let elsewhere = Path::new("/");
+
+    for typed in ["src/main.rs", "./src/main.rs", "docs/../src/main.rs", " src/main.rs\n"] {
+        let resolved = resolve_path(typed, &roots, elsewhere);
+        assert_eq!(resolved, root.join("src/main.rs"), "{:?}", typed);
+        assert_eq!(search_all(&avlvec, &resolved).len(), 1, "{:?}", typed);
+    }
+    assert!(table.find_by_path(&resolve_path("src/lib", &roots, elsewhere)).is_some());
+
+    // Absolute input and paths relative to the working directory resolve to the same entry.
+    let absolute = root.join("docs/a.txt");
+    assert_eq!(resolve_path(&absolute.to_string_lossy(), &roots, elsewhere), absolute);
+    assert_eq!(resolve_path("a.txt", &[], &root.join("docs")), absolute);
+
+    // Something that doesn't exist yet lands under the first root.
+    assert_eq!(resolve_path("new/file.txt", &roots, elsewhere), root.join("new/file.txt"));
+}
+
+#[test]
+fn tilde_and_variables_are_expanded() {
+    let home = Path::new("/home/me");
+    let var = |name: &str| match name {
+        "DATA" => Some("/srv/data".to_string()),
+        "USER" => Some("me".to_string()),
+        _ => None,
+    };
+
+    assert_eq!(expand_path_with("~", Some(home), &var), "/home/me");
+    assert_eq!(expand_path_with("~/Documents", Some(home), &var), "/home/me/Documents");
+    assert_eq!(expand_path_with("$DATA/logs", Some(home), &var), "/srv/data/logs");
+    assert_eq!(expand_path_with("${DATA}logs", Some(home), &var), "/srv/datalogs");
+    assert_eq!(expand_path_with("%DATA%\\logs", Some(home), &var), "/srv/data\\logs");
+    assert_eq!(expand_path_with("/home/$USER/$DATA", Some(home), &var), "/home/me//srv/data");
+
+    // Left as typed: other users' homes, unset variables, and a lone `$` or `%`.
+    assert_eq!(expand_path_with("~other/x", Some(home), &var), "~other/x");
+    assert_eq!(expand_path_with("~/x", None, &var), "~/x");
+    assert_eq!(expand_path_with("$NOPE/x", Some(home), &var), "$NOPE/x");
+    assert_eq!(expand_path_with("${NOPE}/x", Some(home), &var), "${NOPE}/x");
+    assert_eq!(expand_path_with("50%/a$", Some(home), &var), "50%/a$");
+}
+
+#[test]
+fn typed_paths_are_expanded_before_resolving() {
+    let Some(home) = std::env::home_dir() else { return };
+    assert_eq!(expand_path("~"), home.to_string_lossy());
+    assert_eq!(parse_roots("~, /tmp"), vec![home.clone(), "/tmp".into()]);
+
+    let Ok(home_var) = std::env::var("HOME") else { return };
+    let expected = resolve_path(&format!("{}/notes.txt", home_var), &[], Path::new("/"));
+    assert_eq!(resolve_path("$HOME/notes.txt", &[], Path::new("/")), expected);
+    assert_eq!(resolve_path("${HOME}/notes.txt", &[], Path::new("/")), expected);
+}
+
+#[test]
+fn long_paths_get_the_verbatim_prefix() {
+    assert_eq!(long_path_form(r"C:\data\deep").as_deref(), Some(r"\\?\C:\data\deep"));
+    assert_eq!(long_path_form("d:/data/deep").as_deref(), Some(r"\\?\d:\data\deep"));
+    assert_eq!(long_path_form(r"\\server\share\deep").as_deref(), Some(r"\\?\UNC\server\share\deep"));
+    // Already verbatim, relative, or with components a verbatim path wouldn't resolve.
+    assert_eq!(long_path_form(r"\\?\C:\data"), None);
+    assert_eq!(long_path_form(r"\\.\pipe\name"), None);
+    assert_eq!(long_path_form(r"data\deep"), None);
+    assert_eq!(long_path_form(r"C:data"), None);
+    assert_eq!(long_path_form(r"C:\data\..\deep"), None);
+    assert_eq!(long_path_form("/home/user"), None);
+}
+
+#[cfg(unix)]
+#[test]
+fn names_past_the_limit_are_reported_as_too_long() {
+    let dir = fixture();
+    // Longer than the 255 bytes any common Unix filesystem allows for one name.
+    let long = dir.path().join("x".repeat(300));
+
+    let file = FileMetadata::new(long.clone(), 0, FileType::File, None);
+    let errors = [
+        ops::read_file(&long).unwrap_err(),
+        validate_scan_root(&long).unwrap_err(),
+        ops::create_directory(&long).unwrap_err(),
+        ops::delete_directory(&long).unwrap_err(),
+        ops::append_file(&file, "x").unwrap_err(),
+    ];
+    for e in errors {
+        assert!(is_too_long(&e), "{:?}", e);
+        assert!(e.to_string().contains("too long"), "{}", e);
+    }
+}
+
+#[test]
+fn verbatim_prefixes_are_dropped_where_the_path_reads_the_same() {
+    assert_eq!(short_path_form(r"\\?\C:\data\deep").as_deref(), Some(r"C:\data\deep"));
+    assert_eq!(short_path_form(r"\\?\UNC\server\share\deep").as_deref(), Some(r"\\server\share\deep"));
+    for path in [r"C:\data\deep", r"\\server\share\deep"] {
+        assert_eq!(short_path_form(&long_path_form(path).unwrap()).as_deref(), Some(path));
+    }
+    // Not verbatim, or only meaningful as written.
+    assert_eq!(short_path_form(r"C:\data"), None);
+    assert_eq!(short_path_form(r"\\server\share"), None);
+    assert_eq!(short_path_form(r"\\?\Volume{1234}\data"), None);
+    assert_eq!(short_path_form(r"\\?\C:\data\..\deep"), None);
+    assert_eq!(short_path_form(r"\\?\C:\a/b"), None);
+    // Only Windows reads these prefixes, so elsewhere paths are left alone.
+    if !cfg!(windows) {
+        assert_eq!(normal_path(Path::new(r"\\?\C:\data")), Path::new(r"\\?\C:\data"));
+    }
+}
+
+#[test]
+fn network_share_paths_round_trip_through_the_index() {
+    let share = if cfg!(windows) { r"\\server\share" } else { "//server/share" };
+    let directory = Path::new(share).join("reports");
+    let path = directory.join("q1.txt");
+    let tree = Some(insert_into_avl_tree(None, FileMetadata::new(path.clone(), 5, FileType::File, None)));
+    let mut table = HashTable::new(4);
+    table.insert(FileMetadata::new(directory.clone(), 5, FileType::Directory, None));
+    let avlvec = vec![tree];
+
+    let found = search_all(&avlvec, &path);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].path, path);
+    let separator = std::path::MAIN_SEPARATOR;
+    assert_eq!(found[0].path.display().to_string(), format!("{}{}reports{}q1.txt", share, separator, separator));
+    assert_eq!(table.find_by_path(&directory).map(|entry| &entry.path), Some(&directory));
+    assert!(table.get("reports", &directory, 5).is_some());
+
+    #[cfg(windows)]
+    {
+        // The same file reached through the verbatim form or typed with forward slashes.
+        for typed in [r"\\?\UNC\server\share\reports\q1.txt", "//server/share/reports/q1.txt"] {
+            assert_eq!(search_all(&avlvec, Path::new(typed)).len(), 1, "{}", typed);
+            assert_eq!(HashTable::key("q1.txt", Path::new(typed), 5), HashTable::key("q1.txt", &path, 5));
+        }
+        assert!(table.find_by_path(Path::new(r"\\?\UNC\server\share\reports")).is_some());
+        let verbatim = Path::new(r"\\?\UNC\server\share\reports");
+        assert_eq!(table.get("reports", verbatim, 5).map(|entry| &entry.path), Some(&directory));
+        assert!(same_path(Path::new(r"\\?\C:\data"), Path::new(r"C:\data")));
+    }
+}
@@ -0,0 +1,405 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use common::fixture;
+use file_management::avl::{insert_into_avl_tree, merge_avl_trees};
+use file_management::metadata::extension_of;
+use file_management::scan::build_index;
+use file_management::watch::refresh_path;
+use file_management::search::{
+    directory_tree_lines, dirs_by_glob, duplicate_names, extension_histogram, find_empty, find_larger_than, fuzzy_score,
+    fuzzy_search, load_limit_rules, modified_since, page_ranges, parse_limit_rules, parse_size, run_query, scan_limits,
+    search_all, search_avl_by_extension, search_by_glob, search_by_regex, search_everything, sort_entries,
+    top_level_sizes, top_n_largest, verify_index, Query, SortKey,
+};
+use file_management::{FileMetadata, FileType, HashTable};
+
+#[test]
+fn search_all_looks_in_every_directory() {
+    let dir = fixture();
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(16), false, &mut Vec::new(), None);
+
+    let nested = dir.path().join("src").join("lib").join("a.txt");
+    let matches = search_all(&avlvec, &nested);
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].size, 6);
+
+    assert!(search_all(&avlvec, &dir.path().join("missing.txt")).is_empty());
+}
+
+#[test]
+fn extension_search_ignores_case() {
+    let dir = fixture();
+    common::write(dir.path(), "UPPER.RS", "x");
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(16), false, &mut Vec::new(), None);
+    let merged = merge_avl_trees(&avlvec);
+
+    let mut files = Vec::new();
+    search_avl_by_extension(&merged, "rs", &mut files);
+    let names: Vec<_> = files.iter().map(|file| file.name.as_str()).collect();
+    assert_eq!(names, ["UPPER.RS", "b.rs", "main.rs", "mod.rs"]);
+}
+
+#[test]
+fn regex_and_glob_search() {
+    let dir = fixture();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(16), false, &mut Vec::new(), None);
+    let merged = merge_avl_trees(&avlvec);
+
+    assert_eq!(search_by_regex(&merged, r"^m.*\.rs$").unwrap().len(), 2);
+    let mut classed: Vec<_> = search_by_regex(&merged, r"^[ab]\.").unwrap().into_iter().map(|file| file.name).collect();
+    classed.sort();
+    assert_eq!(classed, ["a.txt", "a.txt", "a.txt", "b.rs"]);
+    assert!(search_by_regex(&merged, r"^[^a-z]").unwrap().is_empty());
+    assert!(search_by_regex(&merged, "(").is_err());
+
+    assert_eq!(search_by_glob(&avlvec, "*.txt", false).unwrap().len(), 3);
+    let in_lib = search_by_glob(&avlvec, "lib/*.txt", true).unwrap();
+    assert_eq!(in_lib.len(), 1);
+    assert_eq!(in_lib[0].path, dir.path().join("src").join("lib").join("a.txt"));
+    // `**` crosses any number of folders, including none.
+    let nested: Vec<_> = search_by_glob(&avlvec, "src/**/mod.rs", true).unwrap().into_iter().map(|file| file.path).collect();
+    assert_eq!(nested, [dir.path().join("src/lib/mod.rs")]);
+    let anywhere = search_by_glob(&avlvec, "src/**/*.rs", true).unwrap();
+    assert_eq!(anywhere.len(), 2);
+    assert!(search_by_glob(&avlvec, "*.nope", false).unwrap().is_empty());
+    assert!(search_by_glob(&avlvec, "docs/**/*.rs", true).unwrap().is_empty());
+
+    // A pattern that doesn't parse is an error, not an empty result.
+    assert!(search_by_glob(&avlvec, "[a", false).is_err());
+    assert!(dirs_by_glob(&table, "src/[a", true).is_err());
+    assert!(run_query(&avlvec, &table, &Query { name: Some("[a".to_string()), ..Query::default() }).is_err());
+}
+
+#[test]
+fn size_filters() {
+    let dir = fixture();
+    common::write(dir.path(), "src/lib/blank.txt", "");
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(16), false, &mut Vec::new(), None);
+    let merged = merge_avl_trees(&avlvec);
+
+    let large: Vec<u64> = find_larger_than(&merged, 6).iter().map(|file| file.size).collect();
+    assert_eq!(large, [12, 12, 10, 6]);
+
+    assert_eq!(parse_size("2K"), Some(2048));
+    assert_eq!(parse_size("1.5 MB"), Some(1572864));
+    assert_eq!(parse_size("lots"), None);
+    assert_eq!(parse_size("4KB"), Some(4096));
+    assert_eq!(parse_size("500MB"), Some(500 * 1024 * 1024));
+    assert_eq!(parse_size("1GB"), Some(1024 * 1024 * 1024));
+    assert_eq!(parse_size("1gb"), Some(1024 * 1024 * 1024));
+    assert_eq!(parse_size(" 2 tb "), Some(2 * 1024 * 1024 * 1024 * 1024));
+    // Without a unit, or with "B", the number is bytes.
+    assert_eq!(parse_size("100"), Some(100));
+    assert_eq!(parse_size("100B"), Some(100));
+    assert_eq!(parse_size("10 XB"), None);
+    assert_eq!(parse_size("MB"), None);
+
+    // The zero-byte file and the empty folder, but none of the folders holding something.
+    let mut empty: Vec<_> = find_empty(&avlvec, &table).into_iter().map(|file| (file.file_type, file.path)).collect();
+    empty.sort_by(|a, b| a.1.cmp(&b.1));
+    assert_eq!(
+        empty,
+        [(FileType::Directory, dir.path().join("empty")), (FileType::File, dir.path().join("src/lib/blank.txt"))]
+    );
+}
+
+#[test]
+fn search_everything_returns_files_and_directories() {
+    let dir = fixture();
+    fs::create_dir(dir.path().join("src").join("docs")).unwrap();
+    common::write(dir.path(), "src/docs/keep", "");
+    common::write(dir.path(), "src/lib/docs", "a file named docs");
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(16), false, &mut Vec::new(), None);
+
+    let matches = search_everything(&avlvec, &table, "docs");
+    let mut found: Vec<_> = matches.iter().map(|file| (file.file_type.clone(), file.path.clone())).collect();
+    found.sort_by(|a, b| a.1.cmp(&b.1));
+    assert_eq!(
+        found,
+        [
+            (FileType::Directory, dir.path().join("docs")),
+            (FileType::Directory, dir.path().join("src").join("docs")),
+            (FileType::File, dir.path().join("src").join("lib").join("docs")),
+        ]
+    );
+    assert!(search_everything(&avlvec, &table, "nothing").is_empty());
+}
+
+#[test]
+fn entries_sort_by_each_key_both_ways() {
+    let mut entries: Vec<FileMetadata> = [("/r/b.rs", 12), ("/r/a.txt", 3), ("/r/src/c.md", 7), ("/r/docs/d", 1)]
+        .into_iter()
+        .map(|(path, size)| FileMetadata::new(PathBuf::from(path), size, FileType::File, None))
+        .collect();
+    let mut sorted = |key, descending| -> Vec<String> {
+        sort_entries(&mut entries, key, descending);
+        entries.iter().map(|file| file.name.clone()).collect()
+    };
+
+    assert_eq!(sorted(SortKey::Name, false), ["a.txt", "b.rs", "c.md", "d"]);
+    assert_eq!(sorted(SortKey::Name, true), ["d", "c.md", "b.rs", "a.txt"]);
+    assert_eq!(sorted(SortKey::Size, false), ["d", "a.txt", "c.md", "b.rs"]);
+    assert_eq!(sorted(SortKey::Size, true), ["b.rs", "c.md", "a.txt", "d"]);
+    // By path "docs/d" comes before "src/c.md", unlike by name.
+    assert_eq!(sorted(SortKey::Path, false), ["a.txt", "b.rs", "d", "c.md"]);
+    assert_eq!(sorted(SortKey::Path, true), ["c.md", "d", "b.rs", "a.txt"]);
+    // Every file counts 0, so both directions leave the previous order alone.
+    assert_eq!(sorted(SortKey::Files, false), ["c.md", "d", "b.rs", "a.txt"]);
+    assert_eq!(sorted(SortKey::Files, true), ["c.md", "d", "b.rs", "a.txt"]);
+}
+
+#[test]
+fn pages_split_at_the_page_size() {
+    assert_eq!(page_ranges(120, 50), [0..50, 50..100, 100..120]);
+    assert_eq!(page_ranges(100, 50), [0..50, 50..100]);
+    assert_eq!(page_ranges(3, 50).len(), 1);
+    assert_eq!(page_ranges(3, 50)[0], 0..3);
+    assert!(page_ranges(0, 50).is_empty());
+    assert_eq!(page_ranges(7, 0).len(), 1);
+    assert_eq!(page_ranges(7, 0)[0], 0..7);
+}
+
+#[test]
+fn duplicate_names_lists_every_location() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("one")).unwrap();
+    fs::create_dir(dir.path().join("two")).unwrap();
+    common::write(dir.path(), "one/config.txt", "a");
+    common::write(dir.path(), "two/config.txt", "b");
+    common::write(dir.path(), "two/unique.txt", "c");
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(16), false, &mut Vec::new(), None);
+
+    let duplicates = duplicate_names(&avlvec);
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].0, "config.txt");
+    assert_eq!(duplicates[0].1, [dir.path().join("one").join("config.txt"), dir.path().join("two").join("config.txt")]);
+}
+
+#[test]
+fn top_level_sizes_use_the_stored_directory_sizes() {
+    let dir = fixture();
+    let (_, table) = build_index(dir.path(), HashTable::new(16), false, &mut Vec::new(), None);
+
+    let sizes: Vec<_> = top_level_sizes(&table, dir.path()).into_iter().map(|file| (file.name, file.size)).collect();
+    assert_eq!(sizes, [("src".to_string(), 28), ("docs".to_string(), 4), ("empty".to_string(), 0)]);
+    assert!(top_level_sizes(&table, &dir.path().join("empty")).is_empty());
+}
+
+#[test]
+fn fuzzy_scores_rank_closer_names_higher() {
+    assert_eq!(fuzzy_score("main.rs", "MAIN.RS"), 1.0);
+    assert_eq!(fuzzy_score("", "main.rs"), 0.0);
+
+    let exact = fuzzy_score("report", "report");
+    let prefix = fuzzy_score("report", "report.pdf");
+    let longer = fuzzy_score("report", "quarterly_report_final.pdf");
+    let scattered = fuzzy_score("rprt", "report.pdf");
+    let typo = fuzzy_score("reprot", "report");
+    let unrelated = fuzzy_score("report", "zzz");
+    assert!(exact > prefix, "{} {}", exact, prefix);
+    assert!(prefix > longer, "{} {}", prefix, longer);
+    assert!(longer > 0.5 && scattered > 0.5);
+    assert!(typo > unrelated && typo < 0.5, "{} {}", typo, unrelated);
+    assert!(prefix > scattered);
+    assert_eq!(unrelated, 0.0);
+
+    assert!(fuzzy_score("mr", "mod.rs") > fuzzy_score("mr", "summary"));
+}
+
+#[test]
+fn fuzzy_search_returns_the_best_matches_first() {
+    let dir = fixture();
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(16), false, &mut Vec::new(), None);
+
+    let matches = fuzzy_search(&avlvec, "main", 2);
+    assert_eq!(matches.len(), 2);
+    assert_eq!(matches[0].1.name, "main.rs");
+    assert!(matches[0].0 >= matches[1].0);
+}
+
+#[test]
+fn directory_tree_is_drawn_in_name_order() {
+    let dir = fixture();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    let root = fs::canonicalize(dir.path()).unwrap();
+
+    let lines = directory_tree_lines(&avlvec, &table, &root, None);
+    assert_eq!(lines[0], root.display().to_string());
+    assert_eq!(
+        lines[1..],
+        [
+            "├── a.txt (3 bytes)",
+            "├── b.rs (12 bytes)",
+            "├── docs/ (4 bytes)",
+            "│   └── a.txt (4 bytes)",
+            "├── empty/ (0 bytes)",
+            "└── src/ (28 bytes)",
+            "    ├── lib/ (16 bytes)",
+            "    │   ├── a.txt (6 bytes)",
+            "    │   └── mod.rs (10 bytes)",
+            "    └── main.rs (12 bytes)",
+        ]
+    );
+
+    let shallow = directory_tree_lines(&avlvec, &table, &root, Some(1));
+    assert_eq!(shallow.len(), 6);
+    assert_eq!(shallow[5], "└── src/ (28 bytes)");
+}
+
+#[test]
+fn only_recently_modified_files_are_returned() {
+    let dir = fixture();
+    let week_ago = SystemTime::now() - Duration::from_secs(7 * 24 * 60 * 60);
+    for file in ["a.txt", "b.rs", "src/main.rs", "src/lib/mod.rs", "src/lib/a.txt", "docs/a.txt"] {
+        fs::File::options().write(true).open(dir.path().join(file)).unwrap().set_modified(week_ago).unwrap();
+    }
+    fs::write(dir.path().join("src/lib/a.txt"), "touched").unwrap();
+
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    let day_ago = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+    let recent = modified_since(&merge_avl_trees(&avlvec), day_ago);
+    assert_eq!(recent.len(), 1);
+    assert!(recent[0].path.ends_with("src/lib/a.txt"));
+
+    assert_eq!(modified_since(&merge_avl_trees(&avlvec), week_ago - Duration::from_secs(60)).len(), 6);
+}
+
+#[test]
+fn extension_histogram_counts_and_sums_per_extension() {
+    let dir = fixture();
+    fs::write(dir.path().join("Makefile"), "all:").unwrap();
+    fs::write(dir.path().join("docs/README.TXT"), "readme").unwrap();
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+
+    let histogram = extension_histogram(&merge_avl_trees(&avlvec));
+    assert_eq!(histogram.len(), 3);
+    assert_eq!(histogram["txt"], (4, 3 + 6 + 4 + 6));
+    assert_eq!(histogram["rs"], (3, 12 + 12 + 10));
+    assert_eq!(histogram["(none)"], (1, 4));
+}
+
+#[test]
+fn extensions_come_from_the_last_dot_only() {
+    assert_eq!(extension_of(Path::new("Makefile")), None);
+    assert_eq!(extension_of(Path::new("archive.tar.gz")).as_deref(), Some("gz"));
+    // A leading dot marks a hidden file, not an extension.
+    assert_eq!(extension_of(Path::new(".gitignore")), None);
+    assert_eq!(extension_of(Path::new("docs/README.TXT")).as_deref(), Some("TXT"));
+
+    let dir = fixture();
+    fs::write(dir.path().join("archive.tar.gz"), "gz").unwrap();
+    fs::write(dir.path().join(".gitignore"), "target").unwrap();
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    let histogram = extension_histogram(&merge_avl_trees(&avlvec));
+    assert_eq!(histogram["gz"], (1, 2));
+    assert_eq!(histogram["(none)"], (1, 6));
+    assert!(!histogram.contains_key("tar") && !histogram.contains_key("gitignore"));
+}
+
+#[test]
+fn verify_reports_entries_deleted_behind_the_index() {
+    let dir = fixture();
+    let root = fs::canonicalize(dir.path()).unwrap();
+    let (mut avlvec, mut table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+    assert!(verify_index(&avlvec, &table).is_empty());
+
+    fs::remove_file(root.join("src/main.rs")).unwrap();
+    fs::remove_dir(root.join("empty")).unwrap();
+    let missing = verify_index(&avlvec, &table);
+    let paths: Vec<_> = missing.iter().map(|file| file.path.clone()).collect();
+    assert_eq!(paths, [root.join("empty"), root.join("src/main.rs")]);
+
+    for file in &missing {
+        refresh_path(&mut avlvec, &mut table, &root, &file.path);
+    }
+    assert!(verify_index(&avlvec, &table).is_empty());
+    assert_eq!(table.find_by_path(&root.join("src")).unwrap().size, 16);
+}
+
+#[test]
+fn top_n_largest_matches_a_full_sort() {
+    let dir = fixture();
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    let merged = merge_avl_trees(&avlvec);
+    let sizes: Vec<u64> = top_n_largest(&merged, 3).iter().map(|file| file.size).collect();
+    assert_eq!(sizes, [12, 12, 10]);
+    // Asking for more than there are returns them all; asking for none returns nothing.
+    assert_eq!(top_n_largest(&merged, 100).len(), 6);
+    assert!(top_n_largest(&merged, 0).is_empty());
+    assert!(top_n_largest(&None, 5).is_empty());
+
+    // xorshift, so the sizes are scrambled (with plenty of ties) but the same on every run.
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut root = None;
+    let mut all = Vec::new();
+    for i in 0..500 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let file = FileMetadata::new(PathBuf::from(format!("/d/f{:03}", i)), state % 64, FileType::File, None);
+        all.push(file.clone());
+        root = Some(insert_into_avl_tree(root, file));
+    }
+    all.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    for n in [1, 7, 20, 499, 500, 800] {
+        let top: Vec<_> = top_n_largest(&root, n).into_iter().map(|file| file.path).collect();
+        let expected: Vec<_> = all.iter().take(n).map(|file| file.path.clone()).collect();
+        assert_eq!(top, expected, "n = {}", n);
+    }
+}
+
+#[test]
+fn queries_keep_only_entries_passing_every_filter() {
+    let dir = fixture();
+    let root = fs::canonicalize(dir.path()).unwrap();
+    let (avlvec, table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+    let paths = |query: Query| -> Vec<PathBuf> { run_query(&avlvec, &table, &query).unwrap().into_iter().map(|file| file.path).collect() };
+
+    // No filters at all is the whole index.
+    assert_eq!(paths(Query::default()).len(), 10);
+
+    let query = Query { name: Some("a.*".to_string()), min_size: Some(4), ..Query::default() };
+    assert_eq!(paths(query), [root.join("docs/a.txt"), root.join("src/lib/a.txt")]);
+
+    let query = Query { file_type: Some(FileType::Directory), min_size: Some(5), ..Query::default() };
+    assert_eq!(paths(query), [root.join("src"), root.join("src/lib")]);
+
+    let query = Query { extension: Some(".RS".to_string()), min_size: Some(11), file_type: Some(FileType::File), ..Query::default() };
+    assert_eq!(paths(query), [root.join("b.rs"), root.join("src/main.rs")]);
+
+    let query = Query {
+        name: Some("*.txt".to_string()),
+        max_size: Some(4),
+        extension: Some("txt".to_string()),
+        ..Query::default()
+    };
+    assert_eq!(paths(query), [root.join("a.txt"), root.join("docs/a.txt")]);
+
+    let query = Query { name: Some("src/**".to_string()), file_type: Some(FileType::Special), ..Query::default() };
+    assert!(paths(query).is_empty());
+}
+
+#[test]
+fn only_files_over_their_extension_limit_are_flagged() {
+    let dir = fixture();
+    let (avlvec, _) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    let merged = merge_avl_trees(&avlvec);
+
+    let config = dir.path().join("limits.conf");
+    fs::write(&config, "# quotas\n.TXT = 5\n\nrs 12B\nmp4 2GB\n").unwrap();
+    let rules = load_limit_rules(&config).unwrap();
+    assert_eq!(rules, [("TXT".to_string(), 5), ("rs".to_string(), 12), ("mp4".to_string(), 2 * 1024 * 1024 * 1024)]);
+
+    // a.txt (3) and docs/a.txt (4) are under the limit and the .rs files exactly at it.
+    let violations = scan_limits(&merged, &rules);
+    assert_eq!(violations.iter().map(|file| file.path.clone()).collect::<Vec<_>>(), [dir.path().join("src/lib/a.txt")]);
+    assert!(scan_limits(&merged, &[]).is_empty());
+
+    assert!(parse_limit_rules("mp4").unwrap_err().starts_with("line 1"));
+    assert!(parse_limit_rules("# ok\nmp4 lots").unwrap_err().starts_with("line 2"));
+    assert!(load_limit_rules(&dir.path().join("missing.conf")).is_err());
+}
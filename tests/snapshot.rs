@@ -0,0 +1,151 @@
+mod common;
+
+use std::fs;
+use std::time::SystemTime;
+
+use common::fixture;
+use file_management::avl::{files_in_size_range, merge_avl_trees_by, OrderKey};
+use file_management::metadata::format_time;
+use file_management::scan::build_index;
+use file_management::search::search_everything;
+use file_management::snapshot::{diff_indexes, diff_trees, Snapshot, BINARY_VERSION};
+use file_management::HashTable;
+
+#[test]
+fn snapshots_survive_a_round_trip() {
+    let dir = fixture();
+    let out = tempfile::tempdir().unwrap();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+
+    let snapshot = Snapshot::from_index(&avlvec, &table);
+    snapshot.save(&out.path().join("index.json")).unwrap();
+    let loaded = Snapshot::load(&out.path().join("index.json")).unwrap();
+    assert_eq!(loaded, snapshot);
+
+    let (trees, rebuilt) = loaded.into_index();
+    assert!(diff_trees(&avlvec, &trees).is_empty());
+    assert!(diff_indexes(&table, &rebuilt).is_empty());
+}
+
+#[test]
+fn diff_reports_added_and_resized_entries() {
+    let dir = fixture();
+    let out = tempfile::tempdir().unwrap();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    Snapshot::from_index(&avlvec, &table).save(&out.path().join("old.json")).unwrap();
+
+    fs::write(dir.path().join("docs/new.txt"), "12345").unwrap();
+    fs::write(dir.path().join("a.txt"), "grown").unwrap();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    Snapshot::from_index(&avlvec, &table).save(&out.path().join("new.json")).unwrap();
+
+    let (old_trees, old_table) = Snapshot::load(&out.path().join("old.json")).unwrap().into_index();
+    let (new_trees, new_table) = Snapshot::load(&out.path().join("new.json")).unwrap().into_index();
+
+    let files = diff_trees(&old_trees, &new_trees);
+    assert_eq!(files.added.len(), 1);
+    assert!(files.added[0].path.ends_with("docs/new.txt"));
+    assert!(files.removed.is_empty());
+    assert_eq!(files.resized.len(), 1);
+    let (before, after) = &files.resized[0];
+    assert!(after.path.ends_with("a.txt"));
+    assert_eq!((before.size, after.size), (3, 5));
+
+    let directories = diff_indexes(&old_table, &new_table);
+    assert!(directories.added.is_empty() && directories.removed.is_empty());
+    assert_eq!(directories.resized.len(), 1);
+    assert!(directories.resized[0].1.path.ends_with("docs"));
+    assert_eq!((directories.resized[0].0.size, directories.resized[0].1.size), (4, 9));
+}
+
+#[test]
+fn binary_snapshots_survive_a_round_trip() {
+    let dir = fixture();
+    let out = tempfile::tempdir().unwrap();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+
+    let snapshot = Snapshot::from_index(&avlvec, &table);
+    let json = out.path().join("index.json");
+    let binary = out.path().join("index.bin");
+    snapshot.save(&json).unwrap();
+    snapshot.save_binary(&binary).unwrap();
+    assert_eq!(Snapshot::load_binary(&binary).unwrap(), snapshot);
+    assert!(fs::metadata(&binary).unwrap().len() < fs::metadata(&json).unwrap().len());
+    assert_eq!(fs::read(&binary).unwrap()[0], BINARY_VERSION);
+}
+
+#[test]
+fn binary_snapshots_from_another_version_are_rejected() {
+    let dir = fixture();
+    let out = tempfile::tempdir().unwrap();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    let binary = out.path().join("index.bin");
+    Snapshot::from_index(&avlvec, &table).save_binary(&binary).unwrap();
+
+    let mut bytes = fs::read(&binary).unwrap();
+    bytes[0] = BINARY_VERSION + 1;
+    fs::write(&binary, &bytes).unwrap();
+    let e = Snapshot::load_binary(&binary).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(e.to_string(), format!("snapshot format version {} is not supported (expected {})", BINARY_VERSION + 1, BINARY_VERSION));
+
+    fs::write(&binary, []).unwrap();
+    assert_eq!(Snapshot::load_binary(&binary).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn snapshots_answer_queries_without_the_indexed_files() {
+    let dir = fixture();
+    let out = tempfile::tempdir().unwrap();
+    let (avlvec, table) = build_index(dir.path(), HashTable::new(8), false, &mut Vec::new(), None);
+    Snapshot::from_index(&avlvec, &table).save(&out.path().join("index.json")).unwrap();
+    let root = dir.path().to_path_buf();
+    drop(dir);
+    assert!(!root.exists());
+
+    let snapshot = Snapshot::load(&out.path().join("index.json")).unwrap();
+    assert_eq!(snapshot.roots(), vec![root]);
+    let (trees, table) = snapshot.into_index();
+
+    let named = search_everything(&trees, &table, "a.txt");
+    assert_eq!(named.len(), 3);
+    let named = search_everything(&trees, &table, "lib");
+    assert_eq!(named.len(), 1);
+    assert_eq!(named[0].size, 16);
+
+    let by_size = merge_avl_trees_by(&trees, OrderKey::Size);
+    let sizes: Vec<u64> = files_in_size_range(&by_size, 4, 12).iter().map(|file| file.size).collect();
+    assert_eq!(sizes, vec![4, 6, 10, 12, 12]);
+}
+
+#[test]
+fn snapshots_remember_where_and_when_they_were_taken() {
+    let dir = fixture();
+    let out = tempfile::tempdir().unwrap();
+    let root = fs::canonicalize(dir.path()).unwrap();
+    let (avlvec, table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+
+    let before = SystemTime::now();
+    let snapshot = Snapshot::from_index(&avlvec, &table).with_roots(std::slice::from_ref(&root));
+    let after = SystemTime::now();
+    let captured = snapshot.captured.unwrap();
+    assert!(before <= captured && captured <= after);
+
+    snapshot.save(&out.path().join("index.json")).unwrap();
+    snapshot.save_binary(&out.path().join("index.bin")).unwrap();
+    for loaded in [Snapshot::load(&out.path().join("index.json")).unwrap(), Snapshot::load_binary(&out.path().join("index.bin")).unwrap()] {
+        assert_eq!(loaded.roots, vec![root.clone()]);
+        assert_eq!(loaded.captured, Some(captured));
+        assert_eq!(loaded.describe(), format!("index of {} captured {}", root.display(), format_time(Some(captured))));
+    }
+
+    // Snapshots from before this was recorded still load, with the roots worked out from the entries.
+    let mut old: serde_json::Value = serde_json::from_str(&fs::read_to_string(out.path().join("index.json")).unwrap()).unwrap();
+    old.as_object_mut().unwrap().remove("roots");
+    old.as_object_mut().unwrap().remove("captured");
+    fs::write(out.path().join("old.json"), old.to_string()).unwrap();
+    let loaded = Snapshot::load(&out.path().join("old.json")).unwrap();
+    assert_eq!((loaded.roots.len(), loaded.captured), (0, None));
+    assert_eq!(loaded.roots(), vec![root.clone()]);
+    assert_eq!(loaded.describe(), format!("index of {} (capture time not recorded)", root.display()));
+}
@@ -0,0 +1,180 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{all_files, fixture};
+use file_management::export::collect_entries;
+use file_management::scan::build_index;
+use file_management::search::search_all;
+use file_management::watch::{refresh_path, rescan_subtree, IndexWatcher, SharedIndex};
+use file_management::{FileMetadata, HashTable};
+
+#[test]
+fn readers_and_a_writer_share_the_index() {
+    let dir = fixture();
+    let root = dir.path().to_path_buf();
+    let (avlvec, table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+    let index = SharedIndex::new(avlvec, table);
+
+    let writer = {
+        let index = index.clone();
+        let root = root.clone();
+        thread::spawn(move || {
+            for i in 0..50 {
+                let path = root.join(format!("new{}.txt", i));
+                fs::write(&path, "x").unwrap();
+                index.write(|avlvec, table| refresh_path(avlvec, table, &root, &path));
+            }
+        })
+    };
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let index = index.clone();
+            thread::spawn(move || {
+                let mut seen = 0;
+                for _ in 0..200 {
+                    let count = index.read(|avlvec, _| all_files(avlvec).len());
+                    assert!(count >= seen && count <= 56, "{} after {}", count, seen);
+                    seen = count;
+                }
+            })
+        })
+        .collect();
+
+    writer.join().unwrap();
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    index.read(|avlvec, _| {
+        assert_eq!(all_files(avlvec).len(), 56);
+        assert_eq!(search_all(avlvec, &root.join("new49.txt")).len(), 1);
+    });
+}
+
+#[test]
+fn refreshing_adjusts_ancestor_file_counts() {
+    let dir = fixture();
+    let root = dir.path().to_path_buf();
+    let (mut avlvec, mut table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+    let file_count = |table: &HashTable, dir: &str| table.find_by_path(&root.join(dir)).unwrap().file_count;
+
+    let added = root.join("src/lib/new.rs");
+    fs::write(&added, "").unwrap();
+    refresh_path(&mut avlvec, &mut table, &root, &added);
+    assert_eq!(file_count(&table, "src"), 4);
+    assert_eq!(file_count(&table, "src/lib"), 3);
+
+    fs::remove_dir_all(root.join("src/lib")).unwrap();
+    refresh_path(&mut avlvec, &mut table, &root, &root.join("src/lib"));
+    assert_eq!(file_count(&table, "src"), 1);
+}
+
+#[test]
+fn rescanning_a_folder_only_replaces_what_is_under_it() {
+    let dir = fixture();
+    let root = fs::canonicalize(dir.path()).unwrap();
+    let (mut avlvec, mut table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+    let before = collect_entries(&avlvec, &table, true);
+
+    let lib = root.join("src/lib");
+    fs::write(lib.join("mod.rs"), "x".repeat(20)).unwrap();
+    fs::remove_file(lib.join("a.txt")).unwrap();
+    fs::write(lib.join("new.txt"), "fresh").unwrap();
+    // Changed too, but outside the folder being rescanned.
+    fs::write(root.join("docs/a.txt"), "not seen yet").unwrap();
+
+    rescan_subtree(&mut avlvec, &mut table, &lib);
+    let after = collect_entries(&avlvec, &table, true);
+
+    let outside = |entries: &[FileMetadata]| -> Vec<(PathBuf, u64)> {
+        entries
+            .iter()
+            .filter(|entry| !entry.path.starts_with(&lib) && entry.path != root.join("src"))
+            .map(|entry| (entry.path.clone(), entry.size))
+            .collect()
+    };
+    assert_eq!(outside(&after), outside(&before));
+    assert_eq!(search_all(&avlvec, &root.join("docs/a.txt"))[0].size, 4);
+
+    let lib_files: Vec<_> =
+        all_files(&avlvec).into_iter().filter(|file| file.path.starts_with(&lib)).map(|file| (file.name, file.size)).collect();
+    assert_eq!(lib_files, [("mod.rs".to_string(), 20), ("new.txt".to_string(), 5)]);
+    assert_eq!(table.find_by_path(&lib).unwrap().size, 25);
+    let src = table.find_by_path(&root.join("src")).unwrap();
+    assert_eq!((src.size, src.file_count), (37, 3));
+
+    // A scan root has no table entry; rescanning it picks up the rest.
+    rescan_subtree(&mut avlvec, &mut table, &root);
+    assert_eq!(search_all(&avlvec, &root.join("docs/a.txt"))[0].size, 12);
+    assert!(table.find_by_path(&root).is_none());
+    assert_eq!(collect_entries(&avlvec, &table, true).len(), before.len());
+}
+
+// Polls `done` until it holds, failing the test if the watcher hasn't caught up within a few seconds.
+fn wait_until(what: &str, mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn applying_watched_changes_updates_the_index() {
+    let dir = fixture();
+    let root = fs::canonicalize(dir.path()).unwrap();
+    let (mut avlvec, mut table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+    let roots = vec![root.clone()];
+    let watcher = IndexWatcher::new(&roots).unwrap();
+    let added = root.join("src/lib/new.rs");
+    let mut applied = 0;
+
+    fs::write(&added, "fresh").unwrap();
+    wait_until("the new file", || {
+        applied += watcher.apply(&mut avlvec, &mut table, &roots);
+        search_all(&avlvec, &added).len() == 1
+    });
+    assert!(applied > 0);
+    let lib = table.find_by_path(&root.join("src/lib")).unwrap();
+    assert_eq!((lib.size, lib.file_count), (21, 3));
+
+    fs::remove_file(&added).unwrap();
+    wait_until("the removal", || {
+        watcher.apply(&mut avlvec, &mut table, &roots);
+        search_all(&avlvec, &added).is_empty()
+    });
+    let lib = table.find_by_path(&root.join("src/lib")).unwrap();
+    assert_eq!((lib.size, lib.file_count), (16, 2));
+    // Nothing has changed since, so there is nothing left to apply.
+    assert_eq!(watcher.apply(&mut avlvec, &mut table, &roots), 0);
+}
+
+#[test]
+fn a_spawned_watcher_updates_the_shared_index() {
+    let dir = fixture();
+    let root = fs::canonicalize(dir.path()).unwrap();
+    let (avlvec, table) = build_index(&root, HashTable::new(8), false, &mut Vec::new(), None);
+    let index = SharedIndex::new(avlvec, table);
+    // The thread runs for as long as the watcher does, so it is left detached.
+    let roots = vec![root.clone()];
+    IndexWatcher::new(&roots).unwrap().spawn(index.clone(), roots);
+    let added = root.join("docs/new.txt");
+    let mut updates = 0;
+
+    fs::write(&added, "fresh").unwrap();
+    wait_until("the new file", || {
+        updates += index.take_updates();
+        index.read(|avlvec, _| search_all(avlvec, &added).len() == 1)
+    });
+    assert!(updates > 0);
+    index.read(|_, table| assert_eq!(table.find_by_path(&root.join("docs")).unwrap().size, 9));
+
+    fs::remove_file(&added).unwrap();
+    wait_until("the removal", || index.read(|avlvec, _| search_all(avlvec, &added).is_empty()));
+    index.read(|_, table| assert_eq!(table.find_by_path(&root.join("docs")).unwrap().size, 4));
+}